@@ -28,6 +28,22 @@ CREATE TABLE IF NOT EXISTS trades (
     exchange VARCHAR(10) NOT NULL,            -- 'ctf' or 'neg_risk'
     order_hash VARCHAR(66),
 
+    -- P&L attribution (trader = maker, the filled order's owner; the taker's leg is its own fill)
+    trader VARCHAR(42),
+    position_before NUMERIC(38, 6),
+    position_after NUMERIC(38, 6),
+    avg_entry_price NUMERIC(20, 18),          -- Pre-trade average entry price
+    realized_pnl NUMERIC(38, 6),              -- P&L realized by this fill
+    position_action VARCHAR(10),              -- 'open', 'add', 'reduce', 'close', 'flip'
+
+    -- Wash detection ('self_trade', 'same_owner', 'round_trip', 'circular'; NULL when clean)
+    wash_flag VARCHAR(12),
+
+    -- Price impact against the token's previous traded price (NULL on its first fill)
+    price_before NUMERIC(20, 18),
+    price_change NUMERIC(20, 18),             -- price - price_before
+    slippage_bps NUMERIC(24, 4),              -- Change against the taker, bps of price_before
+
     -- Indexes for common queries
     created_at TIMESTAMP DEFAULT NOW()
 );
@@ -37,6 +53,8 @@ CREATE INDEX IF NOT EXISTS idx_trades_maker ON trades(maker);
 CREATE INDEX IF NOT EXISTS idx_trades_taker ON trades(taker);
 CREATE INDEX IF NOT EXISTS idx_trades_token ON trades(token_id);
 CREATE INDEX IF NOT EXISTS idx_trades_timestamp ON trades(block_timestamp);
+CREATE INDEX IF NOT EXISTS idx_trades_trader ON trades(trader, block_number DESC);
+CREATE INDEX IF NOT EXISTS idx_trades_wash ON trades(wash_flag) WHERE wash_flag IS NOT NULL;
 
 -------------------------------------------------
 -- USER_PNL TABLE: Aggregated P&L per user
@@ -47,10 +65,20 @@ CREATE TABLE IF NOT EXISTS user_pnl (
     -- P&L Metrics
     realized_pnl NUMERIC(38, 6) NOT NULL DEFAULT 0,      -- Total realized P&L
     unrealized_pnl NUMERIC(38, 6) NOT NULL DEFAULT 0,    -- Current unrealized P&L
-    total_pnl NUMERIC(38, 6) NOT NULL DEFAULT 0,         -- realized + unrealized
+    total_pnl NUMERIC(38, 6) NOT NULL DEFAULT 0,         -- realized + unrealized + liquidity
+    liquidity_pnl NUMERIC(38, 6) NOT NULL DEFAULT 0,     -- Realized FPMM LP P&L
+    total_arbitrage_profit NUMERIC(38, 6) NOT NULL DEFAULT 0,    -- Locked-in complete-set arbitrage
+    cash_balance NUMERIC(38, 6) NOT NULL DEFAULT 0,      -- USDC held (portfolio = cash + positions)
+
+    -- Capital Efficiency
+    capital_deployed NUMERIC(38, 6) NOT NULL DEFAULT 0,  -- Peak net investment (buys - sell proceeds)
+    usdc_spent NUMERIC(38, 6) NOT NULL DEFAULT 0,        -- Total USDC spent on buys
+    roi NUMERIC(38, 18) NOT NULL DEFAULT 0,              -- total_pnl / capital_deployed
+    return_per_volume NUMERIC(38, 18) NOT NULL DEFAULT 0,    -- total_pnl / total_volume
 
     -- Trading Stats
     total_volume NUMERIC(38, 6) NOT NULL DEFAULT 0,      -- Total traded volume
+    clean_volume NUMERIC(38, 6) NOT NULL DEFAULT 0,      -- Volume excluding wash-flagged fills
     total_trades INTEGER NOT NULL DEFAULT 0,              -- Number of trades
     total_fees_paid NUMERIC(38, 6) NOT NULL DEFAULT 0,   -- Total fees
 
@@ -84,7 +112,7 @@ CREATE TABLE IF NOT EXISTS user_positions (
 
     -- Position details
     quantity NUMERIC(38, 18) NOT NULL DEFAULT 0,         -- Current holding
-    avg_entry_price NUMERIC(20, 18) NOT NULL DEFAULT 0,  -- Weighted avg price
+    avg_entry_price NUMERIC(20, 18) NOT NULL DEFAULT 0,  -- Average price of the position's buys
     total_cost_basis NUMERIC(38, 6) NOT NULL DEFAULT 0,  -- Total spent
 
     -- P&L for this position
@@ -104,6 +132,208 @@ CREATE INDEX IF NOT EXISTS idx_positions_user ON user_positions(user_address);
 CREATE INDEX IF NOT EXISTS idx_positions_token ON user_positions(token_id);
 CREATE INDEX IF NOT EXISTS idx_positions_quantity ON user_positions(quantity) WHERE quantity > 0;
 
+-------------------------------------------------
+-- POSITION_LIFECYCLES TABLE: One row per holding episode
+-------------------------------------------------
+CREATE TABLE IF NOT EXISTS position_lifecycles (
+    id VARCHAR(192) PRIMARY KEY,              -- user_address:token_id:opened_block
+    user_address VARCHAR(42) NOT NULL,
+    token_id VARCHAR(78) NOT NULL,
+
+    -- Lifecycle
+    status VARCHAR(10) NOT NULL DEFAULT 'open',   -- 'open' or 'closed'
+    opened_block BIGINT NOT NULL,
+    opened_at TIMESTAMP,
+    closed_block BIGINT,
+    closed_at TIMESTAMP,
+    holding_seconds BIGINT,
+    exit_type VARCHAR(20),                    -- 'sold', 'redeemed', 'merged', 'expired_worthless', 'transferred'
+
+    -- Trading within the episode
+    max_size NUMERIC(38, 6) NOT NULL DEFAULT 0,
+    total_bought NUMERIC(38, 6) NOT NULL DEFAULT 0,
+    total_sold NUMERIC(38, 6) NOT NULL DEFAULT 0,
+    realized_pnl NUMERIC(38, 6) NOT NULL DEFAULT 0
+);
+
+CREATE INDEX IF NOT EXISTS idx_lifecycles_user ON position_lifecycles(user_address, opened_block DESC);
+CREATE INDEX IF NOT EXISTS idx_lifecycles_token ON position_lifecycles(token_id);
+CREATE INDEX IF NOT EXISTS idx_lifecycles_open ON position_lifecycles(user_address) WHERE status = 'open';
+
+-------------------------------------------------
+-- USER_PROFILES TABLE: Behavioral features and trading style per user
+-------------------------------------------------
+CREATE TABLE IF NOT EXISTS user_profiles (
+    user_address VARCHAR(42) PRIMARY KEY,
+    total_trades INTEGER NOT NULL DEFAULT 0,
+    maker_share NUMERIC(20, 18) NOT NULL DEFAULT 0,           -- Fills as maker / total fills
+    avg_holding_seconds BIGINT NOT NULL DEFAULT 0,            -- Closed positions only
+    trades_per_day NUMERIC(38, 18) NOT NULL DEFAULT 0,
+    avg_trade_size NUMERIC(38, 6) NOT NULL DEFAULT 0,
+    neg_risk_volume_share NUMERIC(20, 18) NOT NULL DEFAULT 0,
+    style VARCHAR(16) NOT NULL DEFAULT 'holder',    -- 'market_maker', 'scalper', 'swing', 'holder', 'arbitrageur'
+    last_updated_at TIMESTAMP DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_user_profiles_style ON user_profiles(style);
+
+-------------------------------------------------
+-- USER_RESOLUTION_TRADES TABLE: Trades per user in the 24h before a condition resolved
+-- (counted by trigger, once per user and condition; removed with the resolution on reorg undo)
+-------------------------------------------------
+CREATE TABLE IF NOT EXISTS user_resolution_trades (
+    user_address VARCHAR(42) NOT NULL,
+    condition_id VARCHAR(66) NOT NULL,
+    trades INTEGER NOT NULL DEFAULT 0,        -- Tracked fills as maker or taker, like user_profiles.total_trades
+    PRIMARY KEY (user_address, condition_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_resolution_trades_condition ON user_resolution_trades(condition_id);
+
+-------------------------------------------------
+-- USER_CASH_FLOWS TABLE: Classified USDC transfers of Polymarket users
+-------------------------------------------------
+CREATE TABLE IF NOT EXISTS user_cash_flows (
+    id VARCHAR(180) PRIMARY KEY,              -- tx_hash-log_index-user
+    tx_hash VARCHAR(66) NOT NULL,
+    log_index INTEGER NOT NULL,
+    block_number BIGINT NOT NULL,
+    block_timestamp TIMESTAMP NOT NULL,
+    user_address VARCHAR(42) NOT NULL,
+    counterparty VARCHAR(42) NOT NULL,
+    flow_type VARCHAR(16) NOT NULL,           -- 'deposit', 'withdrawal', 'trade_settlement', 'split',
+                                              -- 'merge', 'redemption', 'fee'
+    amount NUMERIC(38, 6) NOT NULL            -- Signed: positive = cash in
+);
+
+CREATE INDEX IF NOT EXISTS idx_cash_flows_user ON user_cash_flows(user_address, block_number DESC);
+CREATE INDEX IF NOT EXISTS idx_cash_flows_type ON user_cash_flows(flow_type);
+
+-------------------------------------------------
+-- USER_PERIOD_PNL TABLE: P&L per user per day / ISO week / month
+-------------------------------------------------
+CREATE TABLE IF NOT EXISTS user_period_pnl (
+    id VARCHAR(80) PRIMARY KEY,               -- user:period:period_start_unix
+    user_address VARCHAR(42) NOT NULL,
+    period VARCHAR(8) NOT NULL,               -- 'day', 'week' (ISO, Monday start) or 'month'
+    period_start TIMESTAMP NOT NULL,
+    realized_pnl NUMERIC(38, 6) NOT NULL DEFAULT 0,      -- Realized during the period
+    unrealized_start NUMERIC(38, 6) NOT NULL DEFAULT 0,  -- Last mark of the previous period (trigger)
+    unrealized_pnl NUMERIC(38, 6) NOT NULL DEFAULT 0,    -- Latest mark in the period
+    period_pnl NUMERIC(38, 6) GENERATED ALWAYS AS (realized_pnl + unrealized_pnl - unrealized_start) STORED,
+    volume NUMERIC(38, 6) NOT NULL DEFAULT 0,
+    trades INTEGER NOT NULL DEFAULT 0,
+    block_number BIGINT NOT NULL              -- Last block in the period
+);
+
+CREATE INDEX IF NOT EXISTS idx_period_pnl_rank ON user_period_pnl(period, period_start, period_pnl DESC);
+CREATE INDEX IF NOT EXISTS idx_period_pnl_user ON user_period_pnl(user_address, period, period_start DESC);
+
+-------------------------------------------------
+-- USER_NAV_HISTORY TABLE: Portfolio NAV per user per hour or day (db_out nav_bucket param)
+-------------------------------------------------
+CREATE TABLE IF NOT EXISTS user_nav_history (
+    id VARCHAR(64) PRIMARY KEY,               -- user:bucket_start_unix
+    user_address VARCHAR(42) NOT NULL,
+    bucket_start TIMESTAMP NOT NULL,
+    nav NUMERIC(38, 6) NOT NULL,              -- cash_balance + position_value
+    cash_balance NUMERIC(38, 6) NOT NULL,
+    position_value NUMERIC(38, 6) NOT NULL,   -- Long positions marked at latest prices
+    net_deposits NUMERIC(38, 6) NOT NULL,     -- Cumulative deposits - withdrawals
+    time_weighted_return NUMERIC(20, 8) NOT NULL DEFAULT 0,   -- Cumulative, net of deposits / withdrawals
+    block_number BIGINT NOT NULL              -- Last block in the bucket
+);
+
+CREATE INDEX IF NOT EXISTS idx_nav_history_user ON user_nav_history(user_address, bucket_start DESC);
+
+-------------------------------------------------
+-- ARBITRAGE_TRADES TABLE: Complete sets bought below / sold above 1 USDC in one transaction
+-------------------------------------------------
+CREATE TABLE IF NOT EXISTS arbitrage_trades (
+    id VARCHAR(320) PRIMARY KEY,              -- tx_hash-user-market_id-kind
+    tx_hash VARCHAR(66) NOT NULL,
+    block_number BIGINT NOT NULL,
+    block_timestamp TIMESTAMP NOT NULL,
+    user_address VARCHAR(42) NOT NULL,
+    market_id VARCHAR(66) NOT NULL,           -- Condition id, or NegRisk market id
+    is_neg_risk BOOLEAN NOT NULL DEFAULT FALSE,   -- Set spans every outcome of a NegRisk event
+    kind VARCHAR(8) NOT NULL,                 -- 'buy_set' or 'sell_set'
+    outcomes INTEGER NOT NULL,
+    sets NUMERIC(38, 6) NOT NULL,             -- Complete sets locked in
+    usdc NUMERIC(38, 6) NOT NULL,             -- Cost or proceeds of the sets
+    profit NUMERIC(38, 6) NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_arbitrage_user ON arbitrage_trades(user_address, block_number DESC);
+CREATE INDEX IF NOT EXISTS idx_arbitrage_market ON arbitrage_trades(market_id);
+
+-------------------------------------------------
+-- USER_MARKET_PNL TABLE: Positions rolled up per condition (YES + NO legs)
+-------------------------------------------------
+CREATE TABLE IF NOT EXISTS user_market_pnl (
+    id VARCHAR(110) PRIMARY KEY,              -- user_address:condition_id
+    user_address VARCHAR(42) NOT NULL,
+    condition_id VARCHAR(66) NOT NULL,
+    yes_token_id VARCHAR(78) NOT NULL,
+    no_token_id VARCHAR(78) NOT NULL,
+
+    -- Legs
+    yes_quantity NUMERIC(38, 6) NOT NULL DEFAULT 0,
+    no_quantity NUMERIC(38, 6) NOT NULL DEFAULT 0,
+    net_exposure NUMERIC(38, 6) NOT NULL DEFAULT 0,   -- yes_quantity - no_quantity
+
+    -- P&L
+    cost_basis NUMERIC(38, 6) NOT NULL DEFAULT 0,     -- Cost of the shares held (average entry x quantity)
+    realized_pnl NUMERIC(38, 6) NOT NULL DEFAULT 0,
+    unrealized_pnl NUMERIC(38, 6) NOT NULL DEFAULT 0, -- Market value of the shares held - cost_basis
+
+    -- Hedging
+    complete_sets NUMERIC(38, 6) NOT NULL DEFAULT 0,  -- min(yes_quantity, no_quantity)
+    is_hedged BOOLEAN NOT NULL DEFAULT FALSE,
+
+    last_updated_at TIMESTAMP DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_user_market_pnl_user ON user_market_pnl(user_address);
+CREATE INDEX IF NOT EXISTS idx_user_market_pnl_condition ON user_market_pnl(condition_id);
+CREATE INDEX IF NOT EXISTS idx_user_market_pnl_hedged ON user_market_pnl(condition_id) WHERE is_hedged;
+
+-------------------------------------------------
+-- MARKET_PNL TABLE: Trader P&L per condition (aggregates maintained by trigger)
+-------------------------------------------------
+CREATE TABLE IF NOT EXISTS market_pnl (
+    condition_id VARCHAR(66) PRIMARY KEY,
+    traders INTEGER NOT NULL DEFAULT 0,
+    winners INTEGER NOT NULL DEFAULT 0,
+    losers INTEGER NOT NULL DEFAULT 0,
+    total_realized_pnl NUMERIC(38, 6) NOT NULL DEFAULT 0,
+    total_unrealized_pnl NUMERIC(38, 6) NOT NULL DEFAULT 0,
+    is_settled BOOLEAN NOT NULL DEFAULT FALSE,        -- Ranked at resolution payouts, then frozen while resolved
+    top_n INTEGER NOT NULL DEFAULT 100,               -- Winners / losers kept (db_out param top_leaderboard)
+    block_number BIGINT NOT NULL,
+    last_updated_at TIMESTAMP DEFAULT NOW()
+);
+
+-------------------------------------------------
+-- MARKET_LEADERBOARD TABLE: Top winners and losers per condition (top_n rows each)
+-- Derived state, rebuilt by the market_pnl trigger. NOT reorg-safe: the sink's reorg undo does
+-- not track these rows, so after a reorg they can reflect orphaned blocks until the condition's
+-- next market_pnl write re-ranks it (a reorged-out resolution re-ranks it at once).
+-------------------------------------------------
+CREATE TABLE IF NOT EXISTS market_leaderboard (
+    condition_id VARCHAR(66) NOT NULL,
+    side VARCHAR(8) NOT NULL,                 -- 'winner' or 'loser'
+    rank INTEGER NOT NULL,
+    user_address VARCHAR(42) NOT NULL,
+    realized_pnl NUMERIC(38, 6) NOT NULL,
+    unrealized_pnl NUMERIC(38, 6) NOT NULL,   -- Marked at current prices, or at payouts once settled
+    total_pnl NUMERIC(38, 6) NOT NULL,
+    is_settled BOOLEAN NOT NULL DEFAULT FALSE,
+    PRIMARY KEY (condition_id, side, rank)
+);
+
+CREATE INDEX IF NOT EXISTS idx_market_leaderboard_user ON market_leaderboard(user_address);
+
 -------------------------------------------------
 -- MARKETS TABLE: Market/Token statistics
 -------------------------------------------------
@@ -112,10 +342,12 @@ CREATE TABLE IF NOT EXISTS markets (
 
     -- Market info (populated from TokenRegistered events)
     condition_id VARCHAR(66),
+    complement_token_id VARCHAR(78),          -- Other outcome of the same condition
     is_neg_risk BOOLEAN DEFAULT FALSE,
 
     -- Trading stats
     total_volume NUMERIC(38, 6) NOT NULL DEFAULT 0,
+    clean_volume NUMERIC(38, 6) NOT NULL DEFAULT 0,       -- Volume excluding wash-flagged fills
     total_trades INTEGER NOT NULL DEFAULT 0,
     unique_traders INTEGER NOT NULL DEFAULT 0,
 
@@ -126,6 +358,14 @@ CREATE TABLE IF NOT EXISTS markets (
     high_24h NUMERIC(20, 18) DEFAULT 0,
     low_24h NUMERIC(20, 18) DEFAULT 0,
 
+    -- Open interest
+    open_interest NUMERIC(38, 6) NOT NULL DEFAULT 0,      -- Outstanding shares (minted - burned)
+    open_interest_usdc NUMERIC(38, 6) NOT NULL DEFAULT 0, -- Collateral locked in the condition
+
+    -- Price impact: Kyle's lambda, price change per 1,000 USDC of net taker flow
+    kyle_lambda_1h NUMERIC(38, 18),
+    kyle_lambda_24h NUMERIC(38, 18),
+
     -- Timestamps
     first_trade_at TIMESTAMP,
     last_trade_at TIMESTAMP,
@@ -134,6 +374,390 @@ CREATE TABLE IF NOT EXISTS markets (
 
 CREATE INDEX IF NOT EXISTS idx_markets_volume ON markets(total_volume DESC);
 CREATE INDEX IF NOT EXISTS idx_markets_trades ON markets(total_trades DESC);
+CREATE INDEX IF NOT EXISTS idx_markets_condition ON markets(condition_id);
+CREATE INDEX IF NOT EXISTS idx_markets_open_interest ON markets(open_interest_usdc DESC);
+
+-------------------------------------------------
+-- MARKET_SMART_FLOW TABLE: Win-rate weighted net flow per token (as of its last fill)
+-------------------------------------------------
+CREATE TABLE IF NOT EXISTS market_smart_flow (
+    token_id VARCHAR(78) PRIMARY KEY,
+    flow_1h NUMERIC(38, 6) NOT NULL DEFAULT 0,        -- Positive = smart money buying
+    flow_24h NUMERIC(38, 6) NOT NULL DEFAULT 0,
+    block_number BIGINT NOT NULL,
+    last_updated_at TIMESTAMP DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_smart_flow_1h ON market_smart_flow(flow_1h DESC);
+CREATE INDEX IF NOT EXISTS idx_smart_flow_24h ON market_smart_flow(flow_24h DESC);
+
+-------------------------------------------------
+-- SMART_FLOW_ALERTS TABLE: Smart money flow crossing the map_smart_flow thresholds
+-------------------------------------------------
+CREATE TABLE IF NOT EXISTS smart_flow_alerts (
+    id VARCHAR(120) PRIMARY KEY,              -- token_id:window:block_number
+    token_id VARCHAR(78) NOT NULL,
+    flow_window VARCHAR(4) NOT NULL,          -- '1h' or '24h'
+    flow NUMERIC(38, 6) NOT NULL,
+    threshold NUMERIC(38, 6) NOT NULL,
+    direction VARCHAR(8) NOT NULL,            -- 'inflow' or 'outflow'
+    block_number BIGINT NOT NULL,
+    block_timestamp TIMESTAMP NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_smart_flow_alerts_time ON smart_flow_alerts(block_number DESC);
+
+-------------------------------------------------
+-- ALERTS TABLE: Rule-based alerts from map_alerts
+-------------------------------------------------
+-- value and threshold are USDC with 6 decimals, except price_move (basis points); threshold is NULL for new_market
+CREATE TABLE IF NOT EXISTS alerts (
+    id VARCHAR(200) PRIMARY KEY,              -- alert_type:subject:block_number
+    alert_type VARCHAR(20) NOT NULL,          -- 'large_trade', 'price_move', 'new_market', 'user_pnl',
+                                              -- 'large_redemption' or 'smart_money_flow'
+    block_number BIGINT NOT NULL,
+    block_timestamp TIMESTAMP NOT NULL,
+    tx_hash VARCHAR(66),
+    token_id VARCHAR(78),
+    condition_id VARCHAR(66),
+    user_address VARCHAR(42),
+    value NUMERIC(38, 0),
+    threshold NUMERIC(38, 0),
+    description TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_alerts_time ON alerts(block_number DESC);
+CREATE INDEX IF NOT EXISTS idx_alerts_type ON alerts(alert_type, block_number DESC);
+
+-------------------------------------------------
+-- SUSPICIOUS_POSITIONS TABLE: Large, concentrated bets by fresh wallets
+-------------------------------------------------
+-- suspicion_score averages four factors in [0, 1]: freshness (1 at first trade, 0 after 7 days),
+-- size (share of the condition's volume, capped at 10%), concentration of the wallet's spending
+-- and, once resolved, timing (1 when the last buy was at resolution, 0 at 72h or more before).
+CREATE TABLE IF NOT EXISTS suspicious_positions (
+    id VARCHAR(110) PRIMARY KEY,              -- user_address:condition_id
+    user_address VARCHAR(42) NOT NULL,
+    condition_id VARCHAR(66) NOT NULL,
+    token_id VARCHAR(78) NOT NULL,            -- Outcome of the latest buy
+    first_trade_at TIMESTAMP NOT NULL,
+    wallet_age_seconds BIGINT NOT NULL,       -- At the latest buy
+    position_usdc NUMERIC(38, 6) NOT NULL,
+    market_volume NUMERIC(38, 6) NOT NULL,
+    market_share NUMERIC(38, 18) NOT NULL DEFAULT 0,
+    concentration NUMERIC(38, 18) NOT NULL DEFAULT 0,
+    last_buy_block BIGINT NOT NULL,
+    last_buy_at TIMESTAMP NOT NULL,
+
+    -- Filled on resolution (trigger)
+    hours_to_resolution NUMERIC(20, 4),       -- From the latest buy
+    outcome_payout NUMERIC(20, 18),           -- Payout per share of token_id
+    outcome VARCHAR(8),                       -- 'won', 'lost' or 'split'
+
+    suspicion_score NUMERIC(10, 6) GENERATED ALWAYS AS (
+        (
+            GREATEST(0, 1 - wallet_age_seconds / 604800.0)
+            + LEAST(1, market_share * 10)
+            + LEAST(1, concentration)
+            + COALESCE(GREATEST(0, 1 - hours_to_resolution / 72.0), 0)
+        ) / CASE WHEN hours_to_resolution IS NULL THEN 3 ELSE 4 END
+    ) STORED
+);
+
+CREATE INDEX IF NOT EXISTS idx_suspicious_score ON suspicious_positions(suspicion_score DESC);
+CREATE INDEX IF NOT EXISTS idx_suspicious_condition ON suspicious_positions(condition_id);
+
+-------------------------------------------------
+-- CONDITION_RESOLUTIONS TABLE: Resolved conditions and payouts
+-------------------------------------------------
+CREATE TABLE IF NOT EXISTS condition_resolutions (
+    condition_id VARCHAR(66) PRIMARY KEY,
+    question_id VARCHAR(66),
+    oracle VARCHAR(42),
+    payout_numerators TEXT,                   -- Comma-separated, per outcome slot
+    yes_token_id VARCHAR(78),
+    no_token_id VARCHAR(78),
+    yes_payout NUMERIC(20, 18) NOT NULL DEFAULT 0,    -- Payout per YES share (0-1)
+    no_payout NUMERIC(20, 18) NOT NULL DEFAULT 0,
+    resolved_block BIGINT NOT NULL,
+    resolved_at TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_resolutions_time ON condition_resolutions(resolved_at DESC);
+
+-------------------------------------------------
+-- CALIBRATION_BUCKETS TABLE: Market-wide calibration (last price vs outcome)
+-------------------------------------------------
+CREATE TABLE IF NOT EXISTS calibration_buckets (
+    bucket INTEGER PRIMARY KEY,               -- Decile: 0 = [0.0, 0.1), ..., 9 = [0.9, 1.0]
+    price_low NUMERIC(20, 18) NOT NULL,
+    price_high NUMERIC(20, 18) NOT NULL,
+    token_count INTEGER NOT NULL DEFAULT 0,   -- Resolved outcome tokens in the bucket
+    resolved_yes INTEGER NOT NULL DEFAULT 0,  -- Tokens that paid out 1
+    resolved_no INTEGER NOT NULL DEFAULT 0,   -- Tokens that paid out 0
+    price_sum NUMERIC(38, 18) NOT NULL DEFAULT 0      -- For the mean forecast: price_sum / token_count
+);
+
+-------------------------------------------------
+-- USER_CONDITION_CALIBRATION TABLE: Forecast accuracy of entry prices per resolved condition
+-- (scored by trigger, once per user and condition; removed with the resolution on reorg undo)
+-------------------------------------------------
+CREATE TABLE IF NOT EXISTS user_condition_calibration (
+    user_address VARCHAR(42) NOT NULL,
+    condition_id VARCHAR(66) NOT NULL,
+    positions_scored INTEGER NOT NULL DEFAULT 0,
+    brier_sum NUMERIC(38, 18) NOT NULL DEFAULT 0,     -- Sum of (avg_entry_price - payout)^2
+    log_loss_sum NUMERIC(38, 18) NOT NULL DEFAULT 0,  -- Sum of -ln(probability given to the outcome)
+    resolved_at TIMESTAMP,
+    PRIMARY KEY (user_address, condition_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_condition_calibration_condition ON user_condition_calibration(condition_id);
+
+-------------------------------------------------
+-- OPEN_INTEREST_HISTORY TABLE: Hourly open interest per token
+-------------------------------------------------
+CREATE TABLE IF NOT EXISTS open_interest_history (
+    id VARCHAR(100) PRIMARY KEY,              -- token_id:hour_unix
+    token_id VARCHAR(78) NOT NULL,
+    condition_id VARCHAR(66),
+    hour TIMESTAMP NOT NULL,
+    open_interest NUMERIC(38, 6) NOT NULL DEFAULT 0,
+    open_interest_usdc NUMERIC(38, 6) NOT NULL DEFAULT 0,
+    block_number BIGINT NOT NULL              -- Last block of the hour with a change
+);
+
+CREATE INDEX IF NOT EXISTS idx_oi_history_token ON open_interest_history(token_id, hour DESC);
+CREATE INDEX IF NOT EXISTS idx_oi_history_condition ON open_interest_history(condition_id, hour DESC);
+
+-------------------------------------------------
+-- MARKET_HOLDERS TABLE: Holder count and concentration per token
+-------------------------------------------------
+CREATE TABLE IF NOT EXISTS market_holders (
+    token_id VARCHAR(78) PRIMARY KEY,
+    holder_count INTEGER NOT NULL DEFAULT 0,
+    total_shares NUMERIC(38, 6) NOT NULL DEFAULT 0,
+    hhi NUMERIC(20, 18) NOT NULL DEFAULT 0,       -- Herfindahl index: sum of squared holder shares (0-1)
+    top10_share NUMERIC(20, 18) NOT NULL DEFAULT 0,   -- Filled by trigger from user_positions
+    top_n INTEGER NOT NULL DEFAULT 100,           -- Holders listed in market_top_holders (db_out param top_holders)
+    last_updated_at TIMESTAMP DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_market_holders_count ON market_holders(holder_count DESC);
+CREATE INDEX IF NOT EXISTS idx_market_holders_hhi ON market_holders(hhi DESC);
+
+-------------------------------------------------
+-- MARKET_TOP_HOLDERS VIEW: Largest holders per token (top_n rows), ranked from user_positions
+-- (filter on token_id: it is pushed below the ranking)
+-------------------------------------------------
+CREATE INDEX IF NOT EXISTS idx_positions_token_quantity ON user_positions(token_id, quantity DESC) WHERE quantity > 0;
+
+CREATE OR REPLACE VIEW market_top_holders AS
+SELECT token_id, rank, user_address, quantity, share
+FROM (
+    SELECT
+        p.token_id,
+        ROW_NUMBER() OVER (PARTITION BY p.token_id ORDER BY p.quantity DESC, p.user_address) AS rank,
+        p.user_address,
+        p.quantity,
+        CASE WHEN h.total_shares > 0 THEN p.quantity / h.total_shares ELSE 0 END AS share,  -- quantity / total_shares
+        h.top_n
+    FROM user_positions p
+    JOIN market_holders h ON h.token_id = p.token_id
+    WHERE p.quantity > 0
+) ranked
+WHERE rank <= top_n;
+
+-------------------------------------------------
+-- ORDERS TABLE: Partial fills aggregated per order
+-------------------------------------------------
+CREATE TABLE IF NOT EXISTS orders (
+    order_hash VARCHAR(66) PRIMARY KEY,
+
+    maker VARCHAR(42),
+    token_id VARCHAR(78),
+    side VARCHAR(4),                          -- Maker's side: 'buy' or 'sell'
+    exchange VARCHAR(20),
+
+    filled_shares NUMERIC(38, 6) NOT NULL DEFAULT 0,
+    filled_usdc NUMERIC(38, 6) NOT NULL DEFAULT 0,
+    fill_count INTEGER NOT NULL DEFAULT 0,
+    vwap NUMERIC(20, 18),                     -- filled_usdc / filled_shares
+    size NUMERIC(38, 6),                      -- Maker asset size (USDC for buys, shares for sells), if decoded
+
+    -- 'filled' (whole size filled), 'partially_filled' (live or cancelled after fills),
+    -- 'cancelled' or 'open'
+    status VARCHAR(20) NOT NULL DEFAULT 'open',
+    first_fill_block BIGINT,
+    last_fill_block BIGINT,
+    cancelled_block BIGINT
+);
+
+CREATE INDEX IF NOT EXISTS idx_orders_maker ON orders(maker);
+CREATE INDEX IF NOT EXISTS idx_orders_token ON orders(token_id);
+CREATE INDEX IF NOT EXISTS idx_orders_status ON orders(status);
+
+-------------------------------------------------
+-- EXCHANGE_EVENTS TABLE: Admin and governance events from both exchanges
+-------------------------------------------------
+CREATE TABLE IF NOT EXISTS exchange_events (
+    id VARCHAR(128) PRIMARY KEY,              -- tx_hash-log_index
+    block_number BIGINT NOT NULL,
+    block_timestamp TIMESTAMP NOT NULL,
+    tx_hash VARCHAR(66) NOT NULL,
+    log_index INTEGER NOT NULL,
+
+    exchange VARCHAR(20) NOT NULL,            -- 'ctf' or 'neg_risk'
+    -- trading_paused, trading_unpaused, new_admin, removed_admin, new_operator,
+    -- removed_operator, fee_charged, proxy_factory_updated, safe_factory_updated
+    event_type VARCHAR(32) NOT NULL,
+    account VARCHAR(42),                      -- Pauser, admin/operator, fee receiver or new factory
+    counterparty VARCHAR(42),                 -- Acting admin or previous factory
+    token_id VARCHAR(78),                     -- fee_charged only
+    amount NUMERIC(38, 6)                     -- fee_charged only
+);
+
+CREATE INDEX IF NOT EXISTS idx_exchange_events_type ON exchange_events(event_type, block_number DESC);
+CREATE INDEX IF NOT EXISTS idx_exchange_events_block ON exchange_events(block_number DESC);
+
+-------------------------------------------------
+-- TRADING_PAUSES TABLE: Trading halt windows per exchange
+-------------------------------------------------
+CREATE TABLE IF NOT EXISTS trading_pauses (
+    id VARCHAR(64) PRIMARY KEY,               -- exchange:paused_block
+    exchange VARCHAR(20) NOT NULL,
+
+    paused_block BIGINT NOT NULL,
+    paused_at TIMESTAMP,
+    paused_by VARCHAR(42),
+    unpaused_block BIGINT,
+    unpaused_at TIMESTAMP,
+    unpaused_by VARCHAR(42),
+
+    is_active BOOLEAN NOT NULL DEFAULT TRUE   -- Trading currently halted
+);
+
+CREATE INDEX IF NOT EXISTS idx_trading_pauses_window ON trading_pauses(exchange, paused_at, unpaused_at);
+
+-------------------------------------------------
+-- NEG_RISK_MARKETS TABLE: NegRisk market groups (events)
+-------------------------------------------------
+CREATE TABLE IF NOT EXISTS neg_risk_markets (
+    market_id VARCHAR(66) PRIMARY KEY,
+
+    oracle VARCHAR(42),
+    fee_bips NUMERIC(10, 0) DEFAULT 0,
+    data TEXT,                                -- Raw market metadata (hex)
+    question_count INTEGER NOT NULL DEFAULT 0,
+
+    created_block BIGINT,
+    created_at TIMESTAMP
+);
+
+-------------------------------------------------
+-- NEG_RISK_QUESTIONS TABLE: Questions within a NegRisk market
+-------------------------------------------------
+CREATE TABLE IF NOT EXISTS neg_risk_questions (
+    question_id VARCHAR(66) PRIMARY KEY,
+    market_id VARCHAR(66) NOT NULL,
+    question_index INTEGER NOT NULL DEFAULT 0,
+    condition_id VARCHAR(66),                 -- Join to markets.condition_id for tokens
+    data TEXT,                                -- Raw question metadata (hex)
+
+    created_block BIGINT,
+    created_at TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_neg_risk_questions_market ON neg_risk_questions(market_id);
+CREATE INDEX IF NOT EXISTS idx_neg_risk_questions_condition ON neg_risk_questions(condition_id);
+
+-------------------------------------------------
+-- NEG_RISK_CONVERSIONS TABLE: NO positions converted to YES + USDC
+-------------------------------------------------
+CREATE TABLE IF NOT EXISTS neg_risk_conversions (
+    id VARCHAR(128) PRIMARY KEY,              -- tx_hash-log_index
+    block_number BIGINT NOT NULL,
+    block_timestamp TIMESTAMP NOT NULL,
+    tx_hash VARCHAR(66) NOT NULL,
+    log_index INTEGER NOT NULL,
+
+    stakeholder VARCHAR(42) NOT NULL,
+    market_id VARCHAR(66) NOT NULL,
+    index_set NUMERIC(78, 0) NOT NULL,        -- Bitmask of converted questions
+    amount NUMERIC(38, 6) NOT NULL,           -- NO shares converted per question
+    no_count INTEGER NOT NULL DEFAULT 0,
+    usdc_returned NUMERIC(38, 6) NOT NULL DEFAULT 0,
+
+    no_token_ids TEXT,                        -- Comma-separated NO tokens given up
+    yes_token_ids TEXT                        -- Comma-separated YES tokens received
+);
+
+CREATE INDEX IF NOT EXISTS idx_neg_risk_conversions_stakeholder ON neg_risk_conversions(stakeholder);
+CREATE INDEX IF NOT EXISTS idx_neg_risk_conversions_market ON neg_risk_conversions(market_id);
+
+-------------------------------------------------
+-- NEG_RISK_EVENTS TABLE: Event-level aggregates across all outcomes
+-------------------------------------------------
+CREATE TABLE IF NOT EXISTS neg_risk_events (
+    market_id VARCHAR(66) PRIMARY KEY,        -- NegRisk market id (see neg_risk_markets)
+
+    total_volume NUMERIC(38, 6) NOT NULL DEFAULT 0,
+    open_interest NUMERIC(38, 6) NOT NULL DEFAULT 0,  -- YES shares held across questions
+    realized_pnl NUMERIC(38, 6) NOT NULL DEFAULT 0,   -- Traders' realized P&L across outcomes
+
+    -- Overround: sum of YES last prices vs 1 (> 0 overpriced, < 0 arbitrage by buying all YES)
+    yes_price_sum NUMERIC(20, 18) NOT NULL DEFAULT 0,
+    overround NUMERIC(20, 18) NOT NULL DEFAULT 0,
+
+    last_updated_at TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_neg_risk_events_volume ON neg_risk_events(total_volume DESC);
+CREATE INDEX IF NOT EXISTS idx_neg_risk_events_overround ON neg_risk_events(overround DESC);
+
+-------------------------------------------------
+-- NEG_RISK_EVENT_USERS TABLE: User P&L per NegRisk event
+-------------------------------------------------
+CREATE TABLE IF NOT EXISTS neg_risk_event_users (
+    id VARCHAR(128) PRIMARY KEY,              -- market_id:user_address
+    market_id VARCHAR(66) NOT NULL,
+    user_address VARCHAR(42) NOT NULL,
+
+    realized_pnl NUMERIC(38, 6) NOT NULL DEFAULT 0,
+
+    last_updated_at TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_neg_risk_event_users_market ON neg_risk_event_users(market_id, realized_pnl DESC);
+CREATE INDEX IF NOT EXISTS idx_neg_risk_event_users_user ON neg_risk_event_users(user_address);
+
+-------------------------------------------------
+-- LP_POSITIONS TABLE: FPMM liquidity per user/pool
+-------------------------------------------------
+CREATE TABLE IF NOT EXISTS lp_positions (
+    id VARCHAR(86) PRIMARY KEY,               -- user_address:pool_address
+    user_address VARCHAR(42) NOT NULL,
+    pool_address VARCHAR(42) NOT NULL,
+
+    -- Position details
+    shares NUMERIC(38, 0) NOT NULL DEFAULT 0,            -- Current LP share balance
+    usdc_deposited NUMERIC(38, 6) NOT NULL DEFAULT 0,    -- Total USDC deposited
+
+    -- Removals
+    fees_earned NUMERIC(38, 6) NOT NULL DEFAULT 0,       -- Fees withdrawn from the fee pool
+    removed_value NUMERIC(38, 6) NOT NULL DEFAULT 0,     -- Value of outcome tokens received
+    cost_removed NUMERIC(38, 6) NOT NULL DEFAULT 0,      -- Deposit cost of burnt shares
+
+    -- P&L
+    realized_pnl NUMERIC(38, 6) NOT NULL DEFAULT 0,      -- fees + removed value - cost removed
+
+    -- Timestamps
+    first_funded_at TIMESTAMP,
+    last_updated_at TIMESTAMP DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_lp_positions_user ON lp_positions(user_address);
+CREATE INDEX IF NOT EXISTS idx_lp_positions_pool ON lp_positions(pool_address);
 
 -------------------------------------------------
 -- DAILY_STATS TABLE: Daily aggregated statistics
@@ -176,6 +800,25 @@ WHERE total_trades >= 5  -- Minimum trades to qualify
 ORDER BY total_pnl DESC
 LIMIT 1000;
 
+-------------------------------------------------
+-- LEADERBOARD VIEW: Top traders by ROI (at least $1K capital deployed)
+-------------------------------------------------
+CREATE OR REPLACE VIEW leaderboard_roi AS
+SELECT
+    user_address,
+    roi,
+    total_pnl,
+    capital_deployed,
+    usdc_spent,
+    return_per_volume,
+    total_volume,
+    total_trades,
+    RANK() OVER (ORDER BY roi DESC) as rank
+FROM user_pnl
+WHERE capital_deployed >= 1000000000  -- Minimum capital to qualify ($1K, raw 6-decimal USDC)
+ORDER BY roi DESC
+LIMIT 1000;
+
 -------------------------------------------------
 -- LEADERBOARD VIEW: Top traders by volume
 -------------------------------------------------
@@ -206,6 +849,34 @@ WHERE t.amount >= 10000
 ORDER BY t.block_timestamp DESC
 LIMIT 1000;
 
+-------------------------------------------------
+-- USER_NEAR_RESOLUTION VIEW: Share of each user's trades made in the 24h before resolution
+-------------------------------------------------
+CREATE OR REPLACE VIEW user_near_resolution AS
+SELECT
+    p.user_address,
+    COALESCE(SUM(r.trades), 0) AS trades_near_resolution,
+    CASE WHEN p.total_trades > 0 THEN COALESCE(SUM(r.trades), 0)::NUMERIC / p.total_trades ELSE 0 END
+        AS near_resolution_share
+FROM user_profiles p
+LEFT JOIN user_resolution_trades r ON r.user_address = p.user_address
+GROUP BY p.user_address, p.total_trades;
+
+-------------------------------------------------
+-- USER_CALIBRATION VIEW: Forecast accuracy of entry prices at resolution, per user
+-------------------------------------------------
+CREATE OR REPLACE VIEW user_calibration AS
+SELECT
+    user_address,
+    SUM(positions_scored) AS positions_scored,
+    SUM(brier_sum) AS brier_sum,
+    SUM(log_loss_sum) AS log_loss_sum,
+    SUM(brier_sum) / SUM(positions_scored) AS brier_score,
+    SUM(log_loss_sum) / SUM(positions_scored) AS log_loss,
+    MAX(resolved_at) AS last_resolved_at
+FROM user_condition_calibration
+GROUP BY user_address;
+
 -------------------------------------------------
 -- FUNCTIONS
 -------------------------------------------------
@@ -236,3 +907,257 @@ CREATE TRIGGER trigger_update_win_rate
     BEFORE INSERT OR UPDATE ON user_pnl
     FOR EACH ROW
     EXECUTE FUNCTION update_win_rate();
+
+-- Trigger to set a market's top-10 share whenever its holder stats change
+-- (db_out writes market_holders after user_positions within each block). It only writes the
+-- row being written: no side table for the sink's reorg undo to miss.
+CREATE OR REPLACE FUNCTION set_market_top10_share()
+RETURNS TRIGGER AS $$
+BEGIN
+    NEW.top10_share := CASE WHEN NEW.total_shares > 0 THEN COALESCE(
+        (SELECT SUM(quantity) FROM (
+            SELECT quantity FROM user_positions
+            WHERE token_id = NEW.token_id AND quantity > 0
+            ORDER BY quantity DESC, user_address
+            LIMIT 10
+        ) top10) / NEW.total_shares,
+        0
+    ) ELSE 0 END;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Trigger to score every open position of a resolved condition into user_condition_calibration.
+-- The forecast is the position's average entry price, the outcome its token's payout;
+-- probabilities are clamped to [0.0001, 0.9999] for log loss. Scores replace any earlier score
+-- of the same (user, condition), so a replayed resolution is not counted twice.
+CREATE OR REPLACE FUNCTION score_user_calibration()
+RETURNS TRIGGER AS $$
+BEGIN
+    INSERT INTO user_condition_calibration
+        (user_address, condition_id, positions_scored, brier_sum, log_loss_sum, resolved_at)
+    SELECT
+        user_address,
+        NEW.condition_id,
+        COUNT(*),
+        SUM(brier),
+        SUM(log_loss),
+        NEW.resolved_at
+    FROM (
+        SELECT
+            p.user_address,
+            POWER(p.avg_entry_price - o.payout, 2) AS brier,
+            -(o.payout * LN(LEAST(GREATEST(p.avg_entry_price, 0.0001), 0.9999))
+              + (1 - o.payout) * LN(1 - LEAST(GREATEST(p.avg_entry_price, 0.0001), 0.9999))) AS log_loss
+        FROM user_positions p
+        JOIN (VALUES (NEW.yes_token_id, NEW.yes_payout), (NEW.no_token_id, NEW.no_payout)) AS o(token_id, payout)
+            ON p.token_id = o.token_id
+        WHERE p.quantity > 0 AND p.avg_entry_price > 0
+    ) scored
+    GROUP BY user_address
+    ON CONFLICT (user_address, condition_id) DO UPDATE SET
+        positions_scored = EXCLUDED.positions_scored,
+        brier_sum = EXCLUDED.brier_sum,
+        log_loss_sum = EXCLUDED.log_loss_sum,
+        resolved_at = EXCLUDED.resolved_at;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Trigger to drop a condition's scores when its resolution row is deleted (the sink's reorg undo)
+CREATE OR REPLACE FUNCTION unscore_user_calibration()
+RETURNS TRIGGER AS $$
+BEGIN
+    DELETE FROM user_condition_calibration WHERE condition_id = OLD.condition_id;
+    RETURN OLD;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Trigger to carry the previous period's last unrealized mark into a new period row,
+-- so period_pnl counts only the change in unrealized P&L within the period
+CREATE OR REPLACE FUNCTION carry_period_unrealized()
+RETURNS TRIGGER AS $$
+BEGIN
+    NEW.unrealized_start := COALESCE(
+        (SELECT unrealized_pnl FROM user_period_pnl
+         WHERE user_address = NEW.user_address AND period = NEW.period AND period_start < NEW.period_start
+         ORDER BY period_start DESC LIMIT 1),
+        0
+    );
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS trigger_carry_period_unrealized ON user_period_pnl;
+CREATE TRIGGER trigger_carry_period_unrealized
+    BEFORE INSERT ON user_period_pnl
+    FOR EACH ROW
+    EXECUTE FUNCTION carry_period_unrealized();
+
+-- Realized and unrealized P&L of every trader in a condition. Long legs are marked at the
+-- current price, or at the payouts once the condition has resolved, against the cost of the
+-- shares still held (cost_basis), so sold shares only count in realized_pnl.
+CREATE OR REPLACE FUNCTION market_trader_pnl(condition VARCHAR)
+RETURNS TABLE (user_address VARCHAR, realized_pnl NUMERIC, unrealized_pnl NUMERIC) AS $$
+    SELECT
+        p.user_address,
+        p.realized_pnl,
+        GREATEST(p.yes_quantity, 0) * COALESCE(r.yes_payout, y.current_price, 0)
+            + GREATEST(p.no_quantity, 0) * COALESCE(r.no_payout, n.current_price, 0)
+            - p.cost_basis
+    FROM user_market_pnl p
+    LEFT JOIN condition_resolutions r ON r.condition_id = p.condition_id
+    LEFT JOIN markets y ON y.token_id = p.yes_token_id
+    LEFT JOIN markets n ON n.token_id = p.no_token_id
+    WHERE p.condition_id = condition;
+$$ LANGUAGE sql STABLE;
+
+-- Trigger to re-rank a condition's winners and losers whenever its positions or prices change
+-- (db_out writes market_pnl after user_market_pnl, markets and condition_resolutions).
+-- The ranking settled at resolution is kept while the resolution row exists: later redemptions
+-- burn the winning shares. market_leaderboard is a side write the sink cannot undo (see the table).
+CREATE OR REPLACE FUNCTION refresh_market_leaderboard()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF EXISTS (SELECT 1 FROM market_pnl WHERE condition_id = NEW.condition_id AND is_settled)
+        AND EXISTS (SELECT 1 FROM condition_resolutions WHERE condition_id = NEW.condition_id) THEN
+        IF TG_OP = 'UPDATE' THEN
+            RETURN OLD;
+        END IF;
+        RETURN NEW;
+    END IF;
+
+    NEW.is_settled := EXISTS (SELECT 1 FROM condition_resolutions WHERE condition_id = NEW.condition_id);
+
+    SELECT
+        COUNT(*),
+        COUNT(*) FILTER (WHERE realized_pnl + unrealized_pnl > 0),
+        COUNT(*) FILTER (WHERE realized_pnl + unrealized_pnl < 0),
+        COALESCE(SUM(realized_pnl), 0),
+        COALESCE(SUM(unrealized_pnl), 0)
+    INTO NEW.traders, NEW.winners, NEW.losers, NEW.total_realized_pnl, NEW.total_unrealized_pnl
+    FROM market_trader_pnl(NEW.condition_id);
+
+    DELETE FROM market_leaderboard WHERE condition_id = NEW.condition_id;
+
+    INSERT INTO market_leaderboard
+        (condition_id, side, rank, user_address, realized_pnl, unrealized_pnl, total_pnl, is_settled)
+    SELECT NEW.condition_id, side, rank, user_address, realized_pnl, unrealized_pnl, total_pnl, NEW.is_settled
+    FROM (
+        SELECT
+            *,
+            realized_pnl + unrealized_pnl AS total_pnl,
+            CASE WHEN realized_pnl + unrealized_pnl > 0 THEN 'winner' ELSE 'loser' END AS side,
+            ROW_NUMBER() OVER (
+                PARTITION BY realized_pnl + unrealized_pnl > 0
+                ORDER BY ABS(realized_pnl + unrealized_pnl) DESC, user_address
+            ) AS rank
+        FROM market_trader_pnl(NEW.condition_id)
+        WHERE realized_pnl + unrealized_pnl <> 0
+    ) ranked
+    WHERE rank <= NEW.top_n;
+
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS trigger_refresh_market_leaderboard ON market_pnl;
+CREATE TRIGGER trigger_refresh_market_leaderboard
+    BEFORE INSERT OR UPDATE ON market_pnl
+    FOR EACH ROW
+    EXECUTE FUNCTION refresh_market_leaderboard();
+
+-- Trigger to un-settle and re-rank a condition when its resolution row is deleted (the sink's reorg undo)
+CREATE OR REPLACE FUNCTION unsettle_market_leaderboard()
+RETURNS TRIGGER AS $$
+BEGIN
+    UPDATE market_pnl SET is_settled = FALSE WHERE condition_id = OLD.condition_id;
+    RETURN OLD;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS trigger_unsettle_market_leaderboard ON condition_resolutions;
+CREATE TRIGGER trigger_unsettle_market_leaderboard
+    AFTER DELETE ON condition_resolutions
+    FOR EACH ROW
+    EXECUTE FUNCTION unsettle_market_leaderboard();
+
+-- Trigger to count each user's trades on a resolved condition in the 24 hours before resolution.
+-- Each fill counts once for its maker and once for its taker, as in total_trades (trades below
+-- db_out's min_trade_size are not stored and not counted); a replayed resolution replaces the count.
+CREATE OR REPLACE FUNCTION count_trades_near_resolution()
+RETURNS TRIGGER AS $$
+BEGIN
+    INSERT INTO user_resolution_trades (user_address, condition_id, trades)
+    SELECT u.user_address, NEW.condition_id, COUNT(*)
+    FROM trades t
+    CROSS JOIN LATERAL (VALUES (t.maker), (t.taker)) AS u(user_address)
+    WHERE t.token_id IN (NEW.yes_token_id, NEW.no_token_id)
+      AND t.block_timestamp >= NEW.resolved_at - INTERVAL '24 hours'
+      AND t.block_timestamp <= NEW.resolved_at
+    GROUP BY u.user_address
+    ON CONFLICT (user_address, condition_id) DO UPDATE SET trades = EXCLUDED.trades;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Trigger to drop a condition's counts when its resolution row is deleted (the sink's reorg undo)
+CREATE OR REPLACE FUNCTION uncount_trades_near_resolution()
+RETURNS TRIGGER AS $$
+BEGIN
+    DELETE FROM user_resolution_trades WHERE condition_id = OLD.condition_id;
+    RETURN OLD;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS trigger_count_trades_near_resolution ON condition_resolutions;
+CREATE TRIGGER trigger_count_trades_near_resolution
+    AFTER INSERT ON condition_resolutions
+    FOR EACH ROW
+    EXECUTE FUNCTION count_trades_near_resolution();
+
+DROP TRIGGER IF EXISTS trigger_uncount_trades_near_resolution ON condition_resolutions;
+CREATE TRIGGER trigger_uncount_trades_near_resolution
+    AFTER DELETE ON condition_resolutions
+    FOR EACH ROW
+    EXECUTE FUNCTION uncount_trades_near_resolution();
+
+-- Trigger to complete suspicious_positions of a resolved condition with the time between the
+-- latest buy and resolution, and the payout of the bought outcome
+CREATE OR REPLACE FUNCTION resolve_suspicious_positions()
+RETURNS TRIGGER AS $$
+BEGIN
+    UPDATE suspicious_positions s
+    SET
+        hours_to_resolution = GREATEST(EXTRACT(EPOCH FROM NEW.resolved_at - s.last_buy_at) / 3600, 0),
+        outcome_payout = o.payout,
+        outcome = CASE WHEN o.payout = 1 THEN 'won' WHEN o.payout = 0 THEN 'lost' ELSE 'split' END
+    FROM (VALUES (NEW.yes_token_id, NEW.yes_payout), (NEW.no_token_id, NEW.no_payout)) AS o(token_id, payout)
+    WHERE s.condition_id = NEW.condition_id AND s.token_id = o.token_id;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS trigger_resolve_suspicious_positions ON condition_resolutions;
+CREATE TRIGGER trigger_resolve_suspicious_positions
+    AFTER INSERT ON condition_resolutions
+    FOR EACH ROW
+    EXECUTE FUNCTION resolve_suspicious_positions();
+
+DROP TRIGGER IF EXISTS trigger_score_user_calibration ON condition_resolutions;
+CREATE TRIGGER trigger_score_user_calibration
+    AFTER INSERT ON condition_resolutions
+    FOR EACH ROW
+    EXECUTE FUNCTION score_user_calibration();
+
+DROP TRIGGER IF EXISTS trigger_unscore_user_calibration ON condition_resolutions;
+CREATE TRIGGER trigger_unscore_user_calibration
+    AFTER DELETE ON condition_resolutions
+    FOR EACH ROW
+    EXECUTE FUNCTION unscore_user_calibration();
+
+DROP TRIGGER IF EXISTS trigger_set_market_top10_share ON market_holders;
+CREATE TRIGGER trigger_set_market_top10_share
+    BEFORE INSERT OR UPDATE ON market_holders
+    FOR EACH ROW
+    EXECUTE FUNCTION set_market_top10_share();
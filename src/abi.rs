@@ -115,6 +115,48 @@ pub fn decode_erc1155_transfer_single(log: &Log) -> Option<TransferSingleEvent>
     })
 }
 
+/// Decoded ERC1155 TransferBatch event
+pub struct TransferBatchEvent {
+    pub from: Vec<u8>,
+    pub to: Vec<u8>,
+    pub token_ids: Vec<String>,
+    pub amounts: Vec<String>,
+}
+
+/// ERC1155 TransferBatch event signature
+const TRANSFER_BATCH_SIG: [u8; 32] = [
+    0x4a, 0x39, 0xdc, 0x06, 0xd4, 0xc0, 0xdb, 0xc6, 0x4b, 0x70, 0xaf, 0x90,
+    0xfd, 0x69, 0x8a, 0x23, 0x3a, 0x51, 0x8a, 0xa5, 0xd0, 0x7e, 0x59, 0x5d,
+    0x98, 0x3b, 0x8c, 0x05, 0x26, 0xc8, 0xf7, 0xfb,
+];
+
+/// Decode ERC1155 TransferBatch event
+/// Event: TransferBatch(address indexed operator, address indexed from, address indexed to, uint256[] ids, uint256[] values)
+pub fn decode_erc1155_transfer_batch(log: &Log) -> Option<TransferBatchEvent> {
+    if log.topics.is_empty() || log.topics[0] != TRANSFER_BATCH_SIG {
+        return None;
+    }
+    if log.topics.len() < 4 || log.data.len() < 128 {
+        return None;
+    }
+
+    let from = log.topics[2][12..32].to_vec();
+    let to = log.topics[3][12..32].to_vec();
+    let token_ids = parse_uint256_array(&log.data, 0)?;
+    let amounts = parse_uint256_array(&log.data, 32)?;
+
+    if token_ids.len() != amounts.len() {
+        return None;
+    }
+
+    Some(TransferBatchEvent {
+        from,
+        to,
+        token_ids,
+        amounts,
+    })
+}
+
 /// ERC20 Transfer event signature
 const TRANSFER_SIG: [u8; 32] = [
     0xdd, 0xf2, 0x52, 0xad, 0x1b, 0xe2, 0xc8, 0x9b, 0x69, 0xc2, 0xb0, 0x68,
@@ -140,6 +182,761 @@ pub fn decode_erc20_transfer(log: &Log) -> Option<TransferEvent> {
     Some(TransferEvent { from, to, amount })
 }
 
+/// Decoded FPMMFundingAdded / FPMMFundingRemoved event
+pub struct FpmmFundingEvent {
+    pub funder: Vec<u8>,
+    pub amounts: Vec<String>,
+    pub shares: String,
+    /// Fees withdrawn from the pool's fee pool (removals only, "0" for additions)
+    pub collateral_removed_from_fee_pool: String,
+}
+
+/// FPMMFundingAdded event signature: FPMMFundingAdded(address,uint256[],uint256)
+const FPMM_FUNDING_ADDED_SIG: [u8; 32] = [
+    0xec, 0x2d, 0xc3, 0xe5, 0xa3, 0xbb, 0x9a, 0xa0, 0xa1, 0xde, 0xb9, 0x05,
+    0xd2, 0xbd, 0x23, 0x64, 0x0d, 0x07, 0xf1, 0x07, 0xe6, 0xce, 0xb4, 0x84,
+    0x02, 0x45, 0x01, 0xaa, 0xd9, 0x64, 0xa9, 0x51,
+];
+
+/// FPMMFundingRemoved event signature: FPMMFundingRemoved(address,uint256[],uint256,uint256)
+const FPMM_FUNDING_REMOVED_SIG: [u8; 32] = [
+    0x8b, 0x4b, 0x2c, 0x8e, 0xbd, 0x04, 0xc4, 0x7f, 0xc8, 0xbc, 0xe1, 0x36,
+    0xa8, 0x5d, 0xf9, 0xb9, 0x3f, 0xcb, 0x1f, 0x47, 0xc8, 0xaa, 0x29, 0x64,
+    0x57, 0xd4, 0x39, 0x15, 0x19, 0xd1, 0x90, 0xe7,
+];
+
+/// Decode FPMMFundingAdded event
+/// Event: FPMMFundingAdded(address indexed funder, uint256[] amountsAdded, uint256 sharesMinted)
+pub fn decode_fpmm_funding_added(log: &Log) -> Option<FpmmFundingEvent> {
+    if log.topics.len() < 2 || log.topics[0] != FPMM_FUNDING_ADDED_SIG {
+        return None;
+    }
+    if log.data.len() < 96 {
+        return None;
+    }
+
+    let funder = log.topics[1][12..32].to_vec();
+    let amounts = parse_uint256_array(&log.data, 0)?;
+    let shares = parse_uint256(&log.data[32..64]);
+
+    Some(FpmmFundingEvent {
+        funder,
+        amounts,
+        shares,
+        collateral_removed_from_fee_pool: "0".to_string(),
+    })
+}
+
+/// Decode FPMMFundingRemoved event
+/// Event: FPMMFundingRemoved(address indexed funder, uint256[] amountsRemoved, uint256 collateralRemovedFromFeePool, uint256 sharesBurnt)
+pub fn decode_fpmm_funding_removed(log: &Log) -> Option<FpmmFundingEvent> {
+    if log.topics.len() < 2 || log.topics[0] != FPMM_FUNDING_REMOVED_SIG {
+        return None;
+    }
+    if log.data.len() < 128 {
+        return None;
+    }
+
+    let funder = log.topics[1][12..32].to_vec();
+    let amounts = parse_uint256_array(&log.data, 0)?;
+    let collateral_removed_from_fee_pool = parse_uint256(&log.data[32..64]);
+    let shares = parse_uint256(&log.data[64..96]);
+
+    Some(FpmmFundingEvent {
+        funder,
+        amounts,
+        shares,
+        collateral_removed_from_fee_pool,
+    })
+}
+
+/// Decoded TokenRegistered event (CTF Exchange / NegRisk Exchange)
+pub struct TokenRegisteredEvent {
+    pub token0: String,
+    pub token1: String,
+    pub condition_id: String,
+}
+
+/// TokenRegistered event signature: TokenRegistered(uint256,uint256,bytes32)
+const TOKEN_REGISTERED_SIG: [u8; 32] = [
+    0xbc, 0x9a, 0x24, 0x32, 0xe8, 0xae, 0xb4, 0x83, 0x27, 0x24, 0x6c, 0xdd,
+    0xd6, 0xe8, 0x72, 0xef, 0x45, 0x28, 0x12, 0xb4, 0x24, 0x3c, 0x04, 0xe6,
+    0xbf, 0xb7, 0x86, 0xa2, 0xcd, 0x8f, 0xaf, 0x0d,
+];
+
+/// Decode TokenRegistered event
+/// Event: TokenRegistered(uint256 indexed token0, uint256 indexed token1, bytes32 indexed conditionId)
+pub fn decode_token_registered(log: &Log) -> Option<TokenRegisteredEvent> {
+    if log.topics.len() < 4 || log.topics[0] != TOKEN_REGISTERED_SIG {
+        return None;
+    }
+
+    Some(TokenRegisteredEvent {
+        token0: parse_uint256(&log.topics[1]),
+        token1: parse_uint256(&log.topics[2]),
+        condition_id: format!("0x{}", Hex(&log.topics[3]).to_string()),
+    })
+}
+
+/// Decoded OrderCancelled event (CTF Exchange / NegRisk Exchange)
+pub struct OrderCancelledEvent {
+    pub order_hash: String,
+}
+
+/// OrderCancelled event signature: OrderCancelled(bytes32)
+const ORDER_CANCELLED_SIG: [u8; 32] = [
+    0x51, 0x52, 0xab, 0xf9, 0x59, 0xf6, 0x56, 0x46, 0x62, 0x35, 0x8c, 0x2e,
+    0x52, 0xb7, 0x02, 0x25, 0x9b, 0x78, 0xba, 0xc5, 0xee, 0x78, 0x42, 0xa0,
+    0xf0, 0x19, 0x37, 0xe6, 0x70, 0xef, 0xcc, 0x7d,
+];
+
+/// Decode OrderCancelled event
+/// Event: OrderCancelled(bytes32 indexed orderHash)
+/// The hash is formatted like OrderFilledEvent::order_hash (no 0x prefix) so the two join.
+pub fn decode_order_cancelled(log: &Log) -> Option<OrderCancelledEvent> {
+    if log.topics.len() < 2 || log.topics[0] != ORDER_CANCELLED_SIG {
+        return None;
+    }
+
+    Some(OrderCancelledEvent {
+        order_hash: Hex(&log.topics[1]).to_string(),
+    })
+}
+
+/// Decoded exchange admin / governance event (CTF Exchange / NegRisk Exchange)
+pub struct ExchangeAdminEvent {
+    pub kind: &'static str,
+    /// Pauser, admin/operator added or removed, fee receiver, or new factory
+    pub account: Vec<u8>,
+    /// Acting admin (admin/operator changes) or previous factory (factory updates)
+    pub counterparty: Vec<u8>,
+    pub token_id: String,
+    pub amount: String,
+}
+
+/// TradingPaused event signature: TradingPaused(address)
+const TRADING_PAUSED_SIG: [u8; 32] = [
+    0x20, 0x3c, 0x4b, 0xd3, 0xe5, 0x26, 0x63, 0x4f, 0x66, 0x15, 0x75, 0x35,
+    0x9f, 0xf3, 0x0d, 0xe3, 0xb0, 0xed, 0xab, 0xa6, 0xc2, 0xcb, 0x1e, 0xac,
+    0x60, 0xf7, 0x30, 0xb6, 0xd2, 0xd9, 0xd5, 0x36,
+];
+
+/// TradingUnpaused event signature: TradingUnpaused(address)
+const TRADING_UNPAUSED_SIG: [u8; 32] = [
+    0xa1, 0xe8, 0xa5, 0x48, 0x50, 0xdb, 0xd7, 0xf5, 0x20, 0xbc, 0xc0, 0x9f,
+    0x47, 0xbf, 0xf1, 0x52, 0x29, 0x4b, 0x77, 0xb2, 0x08, 0x1d, 0xa5, 0x45,
+    0xa7, 0xad, 0xf5, 0x31, 0xb7, 0xea, 0x28, 0x3b,
+];
+
+/// NewAdmin event signature: NewAdmin(address,address)
+const NEW_ADMIN_SIG: [u8; 32] = [
+    0xf9, 0xff, 0xab, 0xca, 0x9c, 0x82, 0x76, 0xe9, 0x93, 0x21, 0x72, 0x5b,
+    0xcb, 0x43, 0xfb, 0x07, 0x6a, 0x6c, 0x66, 0xa5, 0x4b, 0x7f, 0x21, 0xc4,
+    0xe8, 0x14, 0x6d, 0x85, 0x19, 0xb4, 0x17, 0xdc,
+];
+
+/// RemovedAdmin event signature: RemovedAdmin(address,address)
+const REMOVED_ADMIN_SIG: [u8; 32] = [
+    0x78, 0x7a, 0x2e, 0x12, 0xf4, 0xa5, 0x5b, 0x65, 0x8b, 0x8f, 0x57, 0x3c,
+    0x32, 0x43, 0x2e, 0xe1, 0x1a, 0x5e, 0x8b, 0x51, 0x67, 0x7d, 0x1e, 0x1e,
+    0x93, 0x7a, 0xaf, 0x6a, 0x0b, 0xb5, 0x77, 0x6e,
+];
+
+/// NewOperator event signature: NewOperator(address,address)
+const NEW_OPERATOR_SIG: [u8; 32] = [
+    0xf1, 0xe0, 0x4d, 0x73, 0xc4, 0x30, 0x4b, 0x5f, 0xf1, 0x64, 0xf9, 0xd1,
+    0x0c, 0x74, 0x73, 0xe2, 0xa1, 0x59, 0x3b, 0x74, 0x06, 0x74, 0xa6, 0x10,
+    0x79, 0x75, 0xe2, 0xa7, 0x00, 0x1c, 0x1e, 0x5c,
+];
+
+/// RemovedOperator event signature: RemovedOperator(address,address)
+const REMOVED_OPERATOR_SIG: [u8; 32] = [
+    0xf7, 0x26, 0x2e, 0xd0, 0x44, 0x3c, 0xc2, 0x11, 0x12, 0x1c, 0xeb, 0x1a,
+    0x80, 0xd6, 0x90, 0x04, 0xf3, 0x19, 0x24, 0x56, 0x15, 0xa7, 0x48, 0x8f,
+    0x95, 0x1f, 0x14, 0x37, 0xfd, 0x91, 0x64, 0x2c,
+];
+
+/// FeeCharged event signature: FeeCharged(address,uint256,uint256)
+const FEE_CHARGED_SIG: [u8; 32] = [
+    0xac, 0xff, 0xcc, 0x86, 0x83, 0x4d, 0x0f, 0x1a, 0x64, 0xb0, 0xd5, 0xa6,
+    0x75, 0x79, 0x8d, 0xee, 0xd6, 0xff, 0x0b, 0xcf, 0xc2, 0x23, 0x1e, 0xdd,
+    0x34, 0x80, 0xe7, 0x28, 0x8d, 0xba, 0x7f, 0xf4,
+];
+
+/// ProxyFactoryUpdated event signature: ProxyFactoryUpdated(address,address)
+const PROXY_FACTORY_UPDATED_SIG: [u8; 32] = [
+    0x30, 0x53, 0xc6, 0x25, 0x2a, 0x93, 0x25, 0x54, 0x23, 0x5c, 0x17, 0x3c,
+    0xaf, 0xfc, 0x19, 0x13, 0x60, 0x4d, 0xba, 0x3a, 0x41, 0xce, 0xe8, 0x95,
+    0x16, 0xf6, 0x31, 0xc4, 0xa1, 0xa5, 0x0a, 0x37,
+];
+
+/// SafeFactoryUpdated event signature: SafeFactoryUpdated(address,address)
+const SAFE_FACTORY_UPDATED_SIG: [u8; 32] = [
+    0x97, 0x26, 0xd7, 0xfa, 0xf7, 0x42, 0x9d, 0x6b, 0x05, 0x95, 0x60, 0xdc,
+    0x85, 0x8e, 0xd7, 0x69, 0x37, 0x7c, 0xcd, 0xf8, 0xb7, 0x54, 0x1e, 0xab,
+    0xe1, 0x2b, 0x22, 0x54, 0x87, 0x19, 0x83, 0x1f,
+];
+
+/// Decode an exchange admin / governance event
+/// Events: TradingPaused(address indexed pauser), TradingUnpaused(address indexed pauser),
+/// NewAdmin / RemovedAdmin / NewOperator / RemovedOperator(address indexed account, address indexed admin),
+/// FeeCharged(address indexed receiver, uint256 tokenId, uint256 amount),
+/// ProxyFactoryUpdated / SafeFactoryUpdated(address indexed oldFactory, address indexed newFactory)
+pub fn decode_exchange_admin_event(log: &Log) -> Option<ExchangeAdminEvent> {
+    if log.topics.len() < 2 {
+        return None;
+    }
+
+    let topic_address = |i: usize| log.topics.get(i).map(|t| t[12..32].to_vec());
+    let sig = log.topics[0].as_slice();
+
+    let (kind, account, counterparty) = if sig == TRADING_PAUSED_SIG {
+        ("trading_paused", topic_address(1)?, vec![])
+    } else if sig == TRADING_UNPAUSED_SIG {
+        ("trading_unpaused", topic_address(1)?, vec![])
+    } else if sig == NEW_ADMIN_SIG {
+        ("new_admin", topic_address(1)?, topic_address(2)?)
+    } else if sig == REMOVED_ADMIN_SIG {
+        ("removed_admin", topic_address(1)?, topic_address(2)?)
+    } else if sig == NEW_OPERATOR_SIG {
+        ("new_operator", topic_address(1)?, topic_address(2)?)
+    } else if sig == REMOVED_OPERATOR_SIG {
+        ("removed_operator", topic_address(1)?, topic_address(2)?)
+    } else if sig == PROXY_FACTORY_UPDATED_SIG {
+        ("proxy_factory_updated", topic_address(2)?, topic_address(1)?)
+    } else if sig == SAFE_FACTORY_UPDATED_SIG {
+        ("safe_factory_updated", topic_address(2)?, topic_address(1)?)
+    } else if sig == FEE_CHARGED_SIG {
+        if log.data.len() < 64 {
+            return None;
+        }
+        return Some(ExchangeAdminEvent {
+            kind: "fee_charged",
+            account: topic_address(1)?,
+            counterparty: vec![],
+            token_id: parse_uint256(&log.data[0..32]),
+            amount: parse_uint256(&log.data[32..64]),
+        });
+    } else {
+        return None;
+    };
+
+    Some(ExchangeAdminEvent {
+        kind,
+        account,
+        counterparty,
+        token_id: String::new(),
+        amount: String::new(),
+    })
+}
+
+/// Decoded ConditionPreparation event (Conditional Tokens)
+pub struct ConditionPreparationEvent {
+    pub condition_id: String,
+    pub oracle: Vec<u8>,
+    pub question_id: Vec<u8>,
+    pub outcome_slot_count: String,
+}
+
+/// ConditionPreparation event signature: ConditionPreparation(bytes32,address,bytes32,uint256)
+const CONDITION_PREPARATION_SIG: [u8; 32] = [
+    0xab, 0x37, 0x60, 0xc3, 0xbd, 0x2b, 0xb3, 0x8b, 0x5b, 0xcf, 0x54, 0xdc,
+    0x79, 0x80, 0x2e, 0xd6, 0x73, 0x38, 0xb4, 0xcf, 0x29, 0xf3, 0x05, 0x4d,
+    0xed, 0x67, 0xed, 0x24, 0x66, 0x1e, 0x41, 0x77,
+];
+
+/// Decode ConditionPreparation event
+/// Event: ConditionPreparation(bytes32 indexed conditionId, address indexed oracle, bytes32 indexed questionId, uint256 outcomeSlotCount)
+pub fn decode_condition_preparation(log: &Log) -> Option<ConditionPreparationEvent> {
+    if log.topics.len() < 4 || log.topics[0] != CONDITION_PREPARATION_SIG {
+        return None;
+    }
+    if log.data.len() < 32 {
+        return None;
+    }
+
+    Some(ConditionPreparationEvent {
+        condition_id: format!("0x{}", Hex(&log.topics[1]).to_string()),
+        oracle: log.topics[2][12..32].to_vec(),
+        question_id: log.topics[3].clone(),
+        outcome_slot_count: parse_uint256(&log.data[0..32]),
+    })
+}
+
+/// Decoded NegRiskAdapter MarketPrepared event
+pub struct MarketPreparedEvent {
+    pub market_id: String,
+    pub oracle: Vec<u8>,
+    pub fee_bips: String,
+    pub data: String,
+}
+
+/// MarketPrepared event signature: MarketPrepared(bytes32,address,uint256,bytes)
+const MARKET_PREPARED_SIG: [u8; 32] = [
+    0xf0, 0x59, 0xab, 0x16, 0xd1, 0xca, 0x60, 0xe1, 0x23, 0xea, 0xb6, 0x0e,
+    0x3c, 0x02, 0xb6, 0x8f, 0xaf, 0x06, 0x03, 0x47, 0xc7, 0x01, 0xa5, 0xd1,
+    0x48, 0x85, 0xa8, 0xe1, 0xde, 0xf7, 0xb3, 0xa8,
+];
+
+/// Decode NegRiskAdapter MarketPrepared event
+/// Event: MarketPrepared(bytes32 indexed marketId, address indexed oracle, uint256 feeBips, bytes data)
+pub fn decode_market_prepared(log: &Log) -> Option<MarketPreparedEvent> {
+    if log.topics.len() < 3 || log.topics[0] != MARKET_PREPARED_SIG {
+        return None;
+    }
+    if log.data.len() < 64 {
+        return None;
+    }
+
+    Some(MarketPreparedEvent {
+        market_id: format!("0x{}", Hex(&log.topics[1]).to_string()),
+        oracle: log.topics[2][12..32].to_vec(),
+        fee_bips: parse_uint256(&log.data[0..32]),
+        data: parse_bytes(&log.data, 32).unwrap_or_default(),
+    })
+}
+
+/// Decoded NegRiskAdapter QuestionPrepared event
+pub struct QuestionPreparedEvent {
+    pub market_id: String,
+    pub question_id: String,
+    pub index: String,
+    pub data: String,
+}
+
+/// QuestionPrepared event signature: QuestionPrepared(bytes32,bytes32,uint256,bytes)
+const QUESTION_PREPARED_SIG: [u8; 32] = [
+    0xaa, 0xc4, 0x10, 0xf8, 0x7d, 0x42, 0x3a, 0x92, 0x2a, 0x7b, 0x22, 0x6a,
+    0xc6, 0x8f, 0x0c, 0x2e, 0xaf, 0x5b, 0xf6, 0xd1, 0x5e, 0x64, 0x4a, 0xc0,
+    0x75, 0x8c, 0x7f, 0x96, 0xe2, 0xc2, 0x53, 0xf7,
+];
+
+/// Decode NegRiskAdapter QuestionPrepared event
+/// Event: QuestionPrepared(bytes32 indexed marketId, bytes32 indexed questionId, uint256 index, bytes data)
+pub fn decode_question_prepared(log: &Log) -> Option<QuestionPreparedEvent> {
+    if log.topics.len() < 3 || log.topics[0] != QUESTION_PREPARED_SIG {
+        return None;
+    }
+    if log.data.len() < 64 {
+        return None;
+    }
+
+    Some(QuestionPreparedEvent {
+        market_id: format!("0x{}", Hex(&log.topics[1]).to_string()),
+        question_id: format!("0x{}", Hex(&log.topics[2]).to_string()),
+        index: parse_uint256(&log.data[0..32]),
+        data: parse_bytes(&log.data, 32).unwrap_or_default(),
+    })
+}
+
+/// Decoded NegRiskAdapter PositionSplit / PositionsMerge event
+pub struct NegRiskPositionEvent {
+    pub stakeholder: Vec<u8>,
+    pub condition_id: String,
+    pub amount: String,
+}
+
+/// NegRiskAdapter PositionSplit event signature: PositionSplit(address,bytes32,uint256)
+const NEG_RISK_POSITION_SPLIT_SIG: [u8; 32] = [
+    0xbb, 0xed, 0x93, 0x0d, 0xbf, 0xb7, 0x90, 0x7a, 0xe2, 0xd6, 0x0d, 0xdf,
+    0x78, 0x34, 0x56, 0x10, 0x21, 0x4f, 0x26, 0x41, 0x9a, 0x01, 0x28, 0xdf,
+    0x39, 0xb6, 0xcc, 0x3d, 0x9e, 0x5d, 0xf9, 0xb0,
+];
+
+/// NegRiskAdapter PositionsMerge event signature: PositionsMerge(address,bytes32,uint256)
+const NEG_RISK_POSITIONS_MERGE_SIG: [u8; 32] = [
+    0xba, 0x33, 0xac, 0x50, 0xd8, 0x89, 0x46, 0x76, 0x59, 0x7e, 0x6e, 0x35,
+    0xdc, 0x09, 0xcf, 0xf5, 0x98, 0x54, 0x70, 0x8b, 0x64, 0x2c, 0xd0, 0x69,
+    0xd2, 0x1e, 0xb9, 0xc7, 0xca, 0x07, 0x2a, 0x04,
+];
+
+/// Decode NegRiskAdapter PositionSplit event
+/// Event: PositionSplit(address indexed stakeholder, bytes32 indexed conditionId, uint256 amount)
+pub fn decode_neg_risk_position_split(log: &Log) -> Option<NegRiskPositionEvent> {
+    decode_neg_risk_position_event(log, &NEG_RISK_POSITION_SPLIT_SIG)
+}
+
+/// Decode NegRiskAdapter PositionsMerge event
+/// Event: PositionsMerge(address indexed stakeholder, bytes32 indexed conditionId, uint256 amount)
+pub fn decode_neg_risk_positions_merge(log: &Log) -> Option<NegRiskPositionEvent> {
+    decode_neg_risk_position_event(log, &NEG_RISK_POSITIONS_MERGE_SIG)
+}
+
+fn decode_neg_risk_position_event(log: &Log, sig: &[u8; 32]) -> Option<NegRiskPositionEvent> {
+    if log.topics.len() < 3 || log.topics[0] != sig {
+        return None;
+    }
+    if log.data.len() < 32 {
+        return None;
+    }
+
+    Some(NegRiskPositionEvent {
+        stakeholder: log.topics[1][12..32].to_vec(),
+        condition_id: format!("0x{}", Hex(&log.topics[2]).to_string()),
+        amount: parse_uint256(&log.data[0..32]),
+    })
+}
+
+/// Decoded NegRiskAdapter PositionsConverted event
+pub struct PositionsConvertedEvent {
+    pub stakeholder: Vec<u8>,
+    pub market_id: String,
+    pub index_set: String,
+    pub amount: String,
+}
+
+/// PositionsConverted event signature: PositionsConverted(address,bytes32,uint256,uint256)
+const POSITIONS_CONVERTED_SIG: [u8; 32] = [
+    0xb0, 0x3d, 0x19, 0xdd, 0xdb, 0xc7, 0x2a, 0x87, 0xe7, 0x35, 0xff, 0x0e,
+    0xa3, 0xb5, 0x7b, 0xef, 0x13, 0x3e, 0xbe, 0x44, 0xe1, 0x89, 0x42, 0x84,
+    0x91, 0x6a, 0x84, 0x04, 0x4d, 0xeb, 0x36, 0x7e,
+];
+
+/// Decode NegRiskAdapter PositionsConverted event
+/// Event: PositionsConverted(address indexed stakeholder, bytes32 indexed marketId, uint256 indexed indexSet, uint256 amount)
+pub fn decode_positions_converted(log: &Log) -> Option<PositionsConvertedEvent> {
+    if log.topics.len() < 4 || log.topics[0] != POSITIONS_CONVERTED_SIG {
+        return None;
+    }
+    if log.data.len() < 32 {
+        return None;
+    }
+
+    Some(PositionsConvertedEvent {
+        stakeholder: log.topics[1][12..32].to_vec(),
+        market_id: format!("0x{}", Hex(&log.topics[2]).to_string()),
+        index_set: parse_uint256(&log.topics[3]),
+        amount: parse_uint256(&log.data[0..32]),
+    })
+}
+
+/// Decoded NegRiskAdapter PayoutRedemption event
+pub struct NegRiskPayoutRedemptionEvent {
+    pub redeemer: Vec<u8>,
+    pub condition_id: String,
+    pub payout: String,
+}
+
+/// NegRiskAdapter PayoutRedemption event signature: PayoutRedemption(address,bytes32,uint256[],uint256)
+const NEG_RISK_PAYOUT_REDEMPTION_SIG: [u8; 32] = [
+    0x91, 0x40, 0xa6, 0xa2, 0x70, 0xef, 0x94, 0x52, 0x60, 0xc0, 0x38, 0x94,
+    0xb3, 0xc6, 0xb3, 0xb2, 0x69, 0x5e, 0x9d, 0x51, 0x01, 0xfe, 0xef, 0x0f,
+    0xf2, 0x4f, 0xec, 0x96, 0x0c, 0xfd, 0x32, 0x24,
+];
+
+/// Decode NegRiskAdapter PayoutRedemption event
+/// Event: PayoutRedemption(address indexed redeemer, bytes32 indexed conditionId, uint256[] amounts, uint256 payout)
+pub fn decode_neg_risk_payout_redemption(log: &Log) -> Option<NegRiskPayoutRedemptionEvent> {
+    if log.topics.len() < 3 || log.topics[0] != NEG_RISK_PAYOUT_REDEMPTION_SIG {
+        return None;
+    }
+    if log.data.len() < 96 {
+        return None;
+    }
+
+    Some(NegRiskPayoutRedemptionEvent {
+        redeemer: log.topics[1][12..32].to_vec(),
+        condition_id: format!("0x{}", Hex(&log.topics[2]).to_string()),
+        payout: parse_uint256(&log.data[32..64]),
+    })
+}
+
+/// Decoded Conditional Tokens PositionSplit / PositionsMerge event
+pub struct CtfPositionEvent {
+    pub stakeholder: Vec<u8>,
+    pub collateral_token: Vec<u8>,
+    pub condition_id: String,
+    pub partition: Vec<String>,
+    pub amount: String,
+}
+
+/// Conditional Tokens PositionSplit event signature: PositionSplit(address,address,bytes32,bytes32,uint256[],uint256)
+const CTF_POSITION_SPLIT_SIG: [u8; 32] = [
+    0x2e, 0x6b, 0xb9, 0x1f, 0x8c, 0xbc, 0xda, 0x0c, 0x93, 0x62, 0x3c, 0x54,
+    0xd0, 0x40, 0x3a, 0x43, 0x51, 0x4f, 0xab, 0xc4, 0x00, 0x84, 0xec, 0x96,
+    0xb6, 0xd5, 0x37, 0x9a, 0x74, 0x78, 0x62, 0x98,
+];
+
+/// Conditional Tokens PositionsMerge event signature: PositionsMerge(address,address,bytes32,bytes32,uint256[],uint256)
+const CTF_POSITIONS_MERGE_SIG: [u8; 32] = [
+    0x6f, 0x13, 0xca, 0x62, 0x55, 0x3f, 0xcc, 0x2b, 0xcd, 0x23, 0x72, 0x18,
+    0x0a, 0x43, 0x94, 0x9c, 0x1e, 0x4c, 0xeb, 0xba, 0x60, 0x39, 0x01, 0xed,
+    0xe2, 0xf4, 0xe1, 0x4f, 0x36, 0xb2, 0x82, 0xca,
+];
+
+/// Decode Conditional Tokens PositionSplit event
+/// Event: PositionSplit(address indexed stakeholder, address collateralToken, bytes32 indexed parentCollectionId,
+///                      bytes32 indexed conditionId, uint256[] partition, uint256 amount)
+pub fn decode_ctf_position_split(log: &Log) -> Option<CtfPositionEvent> {
+    decode_ctf_position_event(log, &CTF_POSITION_SPLIT_SIG)
+}
+
+/// Decode Conditional Tokens PositionsMerge event (same layout as PositionSplit)
+pub fn decode_ctf_positions_merge(log: &Log) -> Option<CtfPositionEvent> {
+    decode_ctf_position_event(log, &CTF_POSITIONS_MERGE_SIG)
+}
+
+fn decode_ctf_position_event(log: &Log, sig: &[u8; 32]) -> Option<CtfPositionEvent> {
+    if log.topics.len() < 4 || log.topics[0] != sig {
+        return None;
+    }
+    if log.data.len() < 96 {
+        return None;
+    }
+
+    Some(CtfPositionEvent {
+        stakeholder: log.topics[1][12..32].to_vec(),
+        collateral_token: log.data[12..32].to_vec(),
+        condition_id: format!("0x{}", Hex(&log.topics[3]).to_string()),
+        partition: parse_uint256_array(&log.data, 32)?,
+        amount: parse_uint256(&log.data[64..96]),
+    })
+}
+
+/// Decoded Conditional Tokens PayoutRedemption event
+pub struct CtfPayoutRedemptionEvent {
+    pub redeemer: Vec<u8>,
+    pub collateral_token: Vec<u8>,
+    pub condition_id: String,
+    pub index_sets: Vec<String>,
+    pub payout: String,
+}
+
+/// Conditional Tokens PayoutRedemption event signature: PayoutRedemption(address,address,bytes32,bytes32,uint256[],uint256)
+const CTF_PAYOUT_REDEMPTION_SIG: [u8; 32] = [
+    0x26, 0x82, 0x01, 0x2a, 0x4a, 0x4f, 0x19, 0x73, 0x11, 0x9f, 0x1c, 0x9b,
+    0x90, 0x74, 0x5d, 0x1b, 0xd9, 0x1f, 0xa2, 0xba, 0xb3, 0x87, 0x34, 0x4f,
+    0x04, 0x4c, 0xb3, 0x58, 0x68, 0x64, 0xd1, 0x8d,
+];
+
+/// Decode Conditional Tokens PayoutRedemption event
+/// Event: PayoutRedemption(address indexed redeemer, address indexed collateralToken,
+///                         bytes32 indexed parentCollectionId, bytes32 conditionId, uint256[] indexSets, uint256 payout)
+pub fn decode_ctf_payout_redemption(log: &Log) -> Option<CtfPayoutRedemptionEvent> {
+    if log.topics.len() < 4 || log.topics[0] != CTF_PAYOUT_REDEMPTION_SIG {
+        return None;
+    }
+    if log.data.len() < 96 {
+        return None;
+    }
+
+    Some(CtfPayoutRedemptionEvent {
+        redeemer: log.topics[1][12..32].to_vec(),
+        collateral_token: log.topics[2][12..32].to_vec(),
+        condition_id: format!("0x{}", Hex(&log.data[0..32]).to_string()),
+        index_sets: parse_uint256_array(&log.data, 32)?,
+        payout: parse_uint256(&log.data[64..96]),
+    })
+}
+
+/// Decoded Polymarket SafeProxyFactory ProxyCreation event
+pub struct ProxyCreationEvent {
+    pub proxy: Vec<u8>,
+    pub owner: Vec<u8>,
+}
+
+/// ProxyCreation event signature: ProxyCreation(address,address)
+const PROXY_CREATION_SIG: [u8; 32] = [
+    0x4f, 0x51, 0xfa, 0xf6, 0xc4, 0x56, 0x1f, 0xf9, 0x5f, 0x06, 0x76, 0x57,
+    0xe4, 0x34, 0x39, 0xf0, 0xf8, 0x56, 0xd9, 0x7c, 0x04, 0xd9, 0xec, 0x90,
+    0x70, 0xa6, 0x19, 0x9a, 0xd4, 0x18, 0xe2, 0x35,
+];
+
+/// Decode SafeProxyFactory ProxyCreation event
+/// Event: ProxyCreation(address proxy, address owner)
+pub fn decode_proxy_creation(log: &Log) -> Option<ProxyCreationEvent> {
+    if log.topics.is_empty() || log.topics[0] != PROXY_CREATION_SIG {
+        return None;
+    }
+    if log.data.len() < 64 {
+        return None;
+    }
+
+    Some(ProxyCreationEvent {
+        proxy: log.data[12..32].to_vec(),
+        owner: log.data[44..64].to_vec(),
+    })
+}
+
+/// Decoded Conditional Tokens ConditionResolution event
+pub struct ConditionResolutionEvent {
+    pub condition_id: String,
+    pub oracle: Vec<u8>,
+    pub question_id: Vec<u8>,
+    pub outcome_slot_count: String,
+    pub payout_numerators: Vec<String>,
+}
+
+/// ConditionResolution event signature: ConditionResolution(bytes32,address,bytes32,uint256,uint256[])
+const CONDITION_RESOLUTION_SIG: [u8; 32] = [
+    0xb4, 0x4d, 0x84, 0xd3, 0x28, 0x96, 0x91, 0xf7, 0x14, 0x97, 0x56, 0x4b,
+    0x85, 0xd4, 0x23, 0x36, 0x48, 0xd9, 0xdb, 0xae, 0x8c, 0xbd, 0xbb, 0x43,
+    0x29, 0xf3, 0x01, 0xc3, 0xa0, 0x18, 0x58, 0x94,
+];
+
+/// Decode Conditional Tokens ConditionResolution event
+/// Event: ConditionResolution(bytes32 indexed conditionId, address indexed oracle, bytes32 indexed questionId,
+///                            uint256 outcomeSlotCount, uint256[] payoutNumerators)
+pub fn decode_condition_resolution(log: &Log) -> Option<ConditionResolutionEvent> {
+    if log.topics.len() < 4 || log.topics[0] != CONDITION_RESOLUTION_SIG {
+        return None;
+    }
+    if log.data.len() < 64 {
+        return None;
+    }
+
+    Some(ConditionResolutionEvent {
+        condition_id: format!("0x{}", Hex(&log.topics[1]).to_string()),
+        oracle: log.topics[2][12..32].to_vec(),
+        question_id: log.topics[3].clone(),
+        outcome_slot_count: parse_uint256(&log.data[0..32]),
+        payout_numerators: parse_uint256_array(&log.data, 32)?,
+    })
+}
+
+/// Order struct passed to the exchanges' fillOrder / fillOrders / matchOrders
+pub struct ExchangeOrder {
+    pub order_hash: String,   // EIP-712 hash, formatted like OrderFilledEvent::order_hash
+    pub maker_amount: String, // Order size in the maker asset (USDC for buys, tokens for sells)
+}
+
+/// fillOrder(Order,uint256) selector
+const FILL_ORDER_SELECTOR: [u8; 4] = [0xfe, 0x72, 0x9a, 0xaf];
+/// fillOrders(Order[],uint256[]) selector
+const FILL_ORDERS_SELECTOR: [u8; 4] = [0xd7, 0x98, 0xef, 0xf6];
+/// matchOrders(Order,Order[],uint256,uint256[]) selector
+const MATCH_ORDERS_SELECTOR: [u8; 4] = [0xe6, 0x0f, 0x0c, 0x05];
+
+/// Head words of an encoded Order: salt, maker, signer, taker, tokenId, makerAmount, takerAmount,
+/// expiration, nonce, feeRateBps, side, signatureType and the signature offset
+const ORDER_HEAD_WORDS: usize = 13;
+
+/// EIP-712 Order type; its fields are the first 12 head words of an encoded Order
+const ORDER_TYPE: &str = "Order(uint256 salt,address maker,address signer,address taker,uint256 tokenId,\
+uint256 makerAmount,uint256 takerAmount,uint256 expiration,uint256 nonce,uint256 feeRateBps,uint8 side,uint8 signatureType)";
+const EIP712_DOMAIN_TYPE: &str = "EIP712Domain(string name,string version,uint256 chainId,address verifyingContract)";
+/// EIP-712 domain name and version of both exchanges (NegRiskCtfExchange inherits CTFExchange's)
+const EXCHANGE_DOMAIN_NAME: &str = "Polymarket CTF Exchange";
+const EXCHANGE_DOMAIN_VERSION: &str = "1";
+const POLYGON_CHAIN_ID: u64 = 137;
+
+/// Decode the orders of a fillOrder / fillOrders / matchOrders call to `exchange` from its input
+pub fn decode_exchange_orders(input: &[u8], exchange: &[u8]) -> Vec<ExchangeOrder> {
+    if input.len() < 4 {
+        return Vec::new();
+    }
+    let (selector, args) = input.split_at(4);
+
+    let orders = if selector == FILL_ORDER_SELECTOR {
+        parse_order(args, 0, exchange).map(|order| vec![order])
+    } else if selector == FILL_ORDERS_SELECTOR {
+        parse_order_array(args, 0, exchange)
+    } else if selector == MATCH_ORDERS_SELECTOR {
+        parse_order(args, 0, exchange).and_then(|taker_order| {
+            let mut orders = vec![taker_order];
+            orders.extend(parse_order_array(args, 32, exchange)?);
+            Some(orders)
+        })
+    } else {
+        None
+    };
+
+    orders.unwrap_or_default()
+}
+
+/// Parse an Order tuple whose ABI offset is stored in the data word starting at `head`
+fn parse_order(data: &[u8], head: usize, exchange: &[u8]) -> Option<ExchangeOrder> {
+    let offset = parse_usize(data.get(head..head + 32)?)?;
+    parse_order_at(data, offset, exchange)
+}
+
+/// Parse an Order tuple encoded at `start`
+fn parse_order_at(data: &[u8], start: usize, exchange: &[u8]) -> Option<ExchangeOrder> {
+    let words = data.get(start..start.checked_add(ORDER_HEAD_WORDS * 32)?)?;
+    Some(ExchangeOrder {
+        order_hash: order_hash(&words[..(ORDER_HEAD_WORDS - 1) * 32], exchange),
+        maker_amount: parse_uint256(&words[160..192]),
+    })
+}
+
+/// EIP-712 hash of an Order (the orderHash of its OrderFilled events) from its encoded fields,
+/// as signed for `exchange`: keccak256(0x1901 || domainSeparator || hashStruct(order))
+fn order_hash(fields: &[u8], exchange: &[u8]) -> String {
+    let mut chain_id = [0u8; 32];
+    chain_id[24..].copy_from_slice(&POLYGON_CHAIN_ID.to_be_bytes());
+    let mut domain = crate::keccak256(EIP712_DOMAIN_TYPE.as_bytes()).to_vec();
+    domain.extend(crate::keccak256(EXCHANGE_DOMAIN_NAME.as_bytes()));
+    domain.extend(crate::keccak256(EXCHANGE_DOMAIN_VERSION.as_bytes()));
+    domain.extend(chain_id);
+    domain.extend([0u8; 12]);
+    domain.extend(exchange);
+
+    let mut order = crate::keccak256(ORDER_TYPE.as_bytes()).to_vec();
+    order.extend(fields);
+
+    let mut digest = vec![0x19, 0x01];
+    digest.extend(crate::keccak256(&domain));
+    digest.extend(crate::keccak256(&order));
+    Hex(crate::keccak256(&digest)).to_string()
+}
+
+/// Parse a dynamic Order[] whose ABI offset is stored in the data word starting at `head`
+fn parse_order_array(data: &[u8], head: usize, exchange: &[u8]) -> Option<Vec<ExchangeOrder>> {
+    let offset = parse_usize(data.get(head..head + 32)?)?;
+    let len = parse_usize(data.get(offset..offset + 32)?)?;
+    let base = offset + 32;
+
+    // Reject lengths that would run past the end of the call data
+    if len > (data.len().saturating_sub(base)) / 32 {
+        return None;
+    }
+
+    // Element offsets are relative to the start of the array's contents
+    (0..len)
+        .map(|i| {
+            let element = parse_usize(&data[base + i * 32..base + (i + 1) * 32])?;
+            parse_order_at(data, base.checked_add(element)?, exchange)
+        })
+        .collect()
+}
+
+/// Parse a dynamic `bytes` value whose ABI offset is stored at `head`, returned as 0x-prefixed hex
+fn parse_bytes(data: &[u8], head: usize) -> Option<String> {
+    let offset = parse_usize(data.get(head..head + 32)?)?;
+    let len = parse_usize(data.get(offset..offset + 32)?)?;
+    let start = offset + 32;
+    let bytes = data.get(start..start.checked_add(len)?)?;
+    Some(format!("0x{}", Hex(bytes).to_string()))
+}
+
+/// Parse a dynamic uint256[] whose ABI offset is stored in the data word starting at `head`
+fn parse_uint256_array(data: &[u8], head: usize) -> Option<Vec<String>> {
+    let offset = parse_usize(data.get(head..head + 32)?)?;
+    let len = parse_usize(data.get(offset..offset + 32)?)?;
+    let start = offset + 32;
+
+    // Reject lengths that would run past the end of the log data
+    if len > (data.len().saturating_sub(start)) / 32 {
+        return None;
+    }
+
+    Some(
+        (0..len)
+            .map(|i| parse_uint256(&data[start + i * 32..start + (i + 1) * 32]))
+            .collect(),
+    )
+}
+
+/// Parse a uint256 word that is used as an offset or length
+fn parse_usize(data: &[u8]) -> Option<usize> {
+    if data.len() != 32 || data[..24].iter().any(|b| *b != 0) {
+        return None;
+    }
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(&data[24..32]);
+    usize::try_from(u64::from_be_bytes(buf)).ok()
+}
+
 /// Parse uint256 from bytes (big-endian)
 fn parse_uint256(data: &[u8]) -> String {
     if data.len() != 32 {
@@ -168,4 +965,152 @@ mod tests {
         data[31] = 100;
         assert_eq!(parse_uint256(&data), "100");
     }
+
+    #[test]
+    fn test_parse_uint256_array() {
+        // offset (0x20), length (2), 7, 9
+        let mut data = [0u8; 128];
+        data[31] = 0x20;
+        data[63] = 2;
+        data[95] = 7;
+        data[127] = 9;
+        assert_eq!(
+            parse_uint256_array(&data, 0),
+            Some(vec!["7".to_string(), "9".to_string()])
+        );
+
+        // Length pointing past the end of data is rejected
+        data[63] = 3;
+        assert_eq!(parse_uint256_array(&data, 0), None);
+    }
+
+    #[test]
+    fn test_decode_exchange_admin_event_factory_update() {
+        let mut old_factory = vec![0u8; 32];
+        old_factory[31] = 0x01;
+        let mut new_factory = vec![0u8; 32];
+        new_factory[31] = 0x02;
+
+        let log = Log {
+            topics: vec![PROXY_FACTORY_UPDATED_SIG.to_vec(), old_factory, new_factory],
+            ..Default::default()
+        };
+        let decoded = decode_exchange_admin_event(&log).unwrap();
+        assert_eq!(decoded.kind, "proxy_factory_updated");
+        assert_eq!(decoded.account[19], 0x02);
+        assert_eq!(decoded.counterparty[19], 0x01);
+    }
+
+    #[test]
+    fn test_decode_condition_resolution() {
+        // outcomeSlotCount (2), offset (0x40), length (2), payouts [0, 1]
+        let mut data = vec![0u8; 160];
+        data[31] = 2;
+        data[63] = 0x40;
+        data[95] = 2;
+        data[159] = 1;
+
+        let log = Log {
+            topics: vec![CONDITION_RESOLUTION_SIG.to_vec(), vec![0xaa; 32], vec![0u8; 32], vec![0xbb; 32]],
+            data,
+            ..Default::default()
+        };
+        let decoded = decode_condition_resolution(&log).unwrap();
+        assert_eq!(decoded.condition_id, format!("0x{}", "aa".repeat(32)));
+        assert_eq!(decoded.outcome_slot_count, "2");
+        assert_eq!(decoded.payout_numerators, vec!["0".to_string(), "1".to_string()]);
+    }
+
+    #[test]
+    fn test_decode_exchange_admin_event_fee_charged() {
+        let mut data = vec![0u8; 64];
+        data[31] = 5;
+        data[63] = 100;
+
+        let log = Log {
+            topics: vec![FEE_CHARGED_SIG.to_vec(), vec![0u8; 32]],
+            data,
+            ..Default::default()
+        };
+        let decoded = decode_exchange_admin_event(&log).unwrap();
+        assert_eq!(decoded.kind, "fee_charged");
+        assert_eq!(decoded.token_id, "5");
+        assert_eq!(decoded.amount, "100");
+
+        // Truncated data is rejected
+        let log = Log {
+            topics: vec![FEE_CHARGED_SIG.to_vec(), vec![0u8; 32]],
+            data: vec![0u8; 32],
+            ..Default::default()
+        };
+        assert!(decode_exchange_admin_event(&log).is_none());
+    }
+
+    /// ABI word holding a small integer
+    fn word(value: u64) -> Vec<u8> {
+        let mut word = vec![0u8; 32];
+        word[24..].copy_from_slice(&value.to_be_bytes());
+        word
+    }
+
+    /// Encoded Order tuple with an empty signature
+    fn encoded_order(maker: u8, token_id: u64, maker_amount: u64, side: u64) -> Vec<u8> {
+        let mut maker_word = vec![0u8; 32];
+        maker_word[31] = maker;
+        let mut order = Vec::new();
+        for field in [
+            word(1),
+            maker_word.clone(),
+            maker_word,
+            word(0),
+            word(token_id),
+            word(maker_amount),
+            word(0),
+            word(0),
+            word(0),
+            word(0),
+            word(side),
+            word(0),
+            word(13 * 32), // signature offset
+            word(0),       // signature length
+        ] {
+            order.extend(field);
+        }
+        order
+    }
+
+    #[test]
+    fn test_decode_exchange_orders_match_orders() {
+        // matchOrders(takerOrder, [makerOrder], takerFillAmount, [])
+        let mut input = MATCH_ORDERS_SELECTOR.to_vec();
+        for head in [word(128), word(576), word(5), word(1088)] {
+            input.extend(head);
+        }
+        input.extend(encoded_order(0xaa, 7, 500, 0));
+        input.extend(word(1));
+        input.extend(word(32));
+        input.extend(encoded_order(0xbb, 7, 300, 1));
+        input.extend(word(0));
+
+        let exchange = hex_literal::hex!("4bfb41d5b3570defd03c39a9a4d8de6bd8b8982e");
+        let orders = decode_exchange_orders(&input, &exchange);
+        assert_eq!(orders.len(), 2);
+        assert_eq!(orders[0].order_hash, "83d46e55fdb37c3ddbc07f5871fd905c00353e21ee254c802090d46202838265");
+        assert_eq!(orders[0].maker_amount, "500");
+        assert_eq!(orders[1].order_hash, "da3d6f2c644e3dfd9dd444fce239d18de484387517eb0e3f95bbe85dbfeb24f1");
+        assert_eq!(orders[1].maker_amount, "300");
+
+        // Truncated input and other calls decode to nothing
+        assert!(decode_exchange_orders(&input[..600], &exchange).is_empty());
+        assert!(decode_exchange_orders(&[0u8; 4], &exchange).is_empty());
+    }
+
+    #[test]
+    fn test_order_typehash() {
+        // ORDER_TYPEHASH of the deployed exchanges
+        assert_eq!(
+            Hex(crate::keccak256(ORDER_TYPE.as_bytes())).to_string(),
+            "a852566c4e14d00869b6db0220888a9090a13eccdaea03713ff0a3d27bf9767c"
+        );
+    }
 }
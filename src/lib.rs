@@ -3,18 +3,38 @@
 //! Real-time Profit & Loss tracking with SQL sink support.
 //!
 //! Modules:
-//! - Layer 1: Event extraction (map_order_fills, map_token_transfers, map_usdc_transfers)
-//! - Layer 2: State stores (positions, cost_basis, realized_pnl, prices)
-//! - Layer 3: Analytics (map_user_pnl, map_market_stats)
+//! - Layer 1: Event extraction (map_order_fills, map_token_transfers, map_usdc_transfers, map_fpmm_funding,
+//!   map_order_cancellations, map_exchange_events, map_ctf_events, map_proxy_wallets, map_market_registry,
+//!   map_neg_risk_events)
+//! - Layer 2: State stores (positions, open_interest, position_episodes, cost_basis, buy_cost, bought_shares,
+//!   realized_pnl, prices, market_tokens, condition_tokens, market_holder_*, proxy_owners, known_users, usdc_balances,
+//!   cash_balance, net_deposits, position_values, log_return, usdc_spent, net_investment, capital_deployed,
+//!   maker_fills, neg_risk_volume, first_trade_at, closed_positions, holding_seconds, exits, winning_exits,
+//!   smart_flow, market_spent, last_sales, price_checkpoints, impact_price_flow, impact_flow_squared, clean_volume,
+//!   arbitrage_profit, arbitrage_count, orders, lp_shares, token_payouts, neg_risk_*)
+//! - Layer 3: Analytics (map_user_pnl, map_market_stats, map_enriched_fills, map_position_lifecycles,
+//!   map_user_cash_flows, map_user_nav, map_user_profiles, map_smart_flow, map_suspicious_positions, map_alerts,
+//!   map_price_impact, map_arbitrage_trades, map_wash_trades, map_condition_resolutions, map_open_interest,
+//!   map_market_holders, map_user_market_pnl, map_lp_pnl, map_orders, map_neg_risk_event_stats)
 //! - Layer 4: SQL sink (db_out)
 
+// Handlers taking `params` get a generated `pub extern "C"` wrapper that rebuilds the string from a
+// raw WASM pointer; attributes on the handler don't reach that wrapper, so the lint is allowed here.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+// Handlers take one argument per input declared in substreams.yaml, and the handler macros drop the
+// handler's own attributes, so this lint is allowed crate-wide as well.
+#![allow(clippy::too_many_arguments)]
+
 mod abi;
 mod pb;
 
 use hex_literal::hex;
 use pb::pnl::v1 as pnl;
 use substreams::prelude::*;
-use substreams::store::{StoreAddBigInt, StoreAddInt64, StoreGet, StoreGetBigInt, StoreGetProto, StoreSetProto};
+use substreams::store::{
+    StoreAddBigInt, StoreAddFloat64, StoreAddInt64, StoreGet, StoreGetBigInt, StoreGetFloat64, StoreGetInt64, StoreGetProto, StoreGetString, StoreSetBigInt,
+    StoreSetIfNotExistsProto, StoreSetInt64, StoreSetProto, StoreSetString,
+};
 use substreams::Hex;
 use substreams_database_change::pb::sf::substreams::sink::database::v1::DatabaseChanges;
 use substreams_database_change::tables::Tables;
@@ -63,29 +83,54 @@ substreams_ethereum::init!();
 const CTF_EXCHANGE: [u8; 20] = hex!("4bfb41d5b3570defd03c39a9a4d8de6bd8b8982e");
 const NEG_RISK_EXCHANGE: [u8; 20] = hex!("C5d563A36AE78145C45a50134d48A1215220f80a");
 const USDC_CONTRACT: [u8; 20] = hex!("2791bca1f2de4661ed88a30c99a7a9449aa84174");
+const CONDITIONAL_TOKENS: [u8; 20] = hex!("4d97dcd97ec945f40cf65f87097ace5ea0476045");
+const NEG_RISK_ADAPTER: [u8; 20] = hex!("d91e80cf2e7be2e162c6513ced06f1dd0da35296");
+const SAFE_PROXY_FACTORY: [u8; 20] = hex!("aacfeea03eb1561c4e67d661e40682bd20e3541b");
+// Collateral of NegRisk positions (the adapter's wrapped USDC)
+const NEG_RISK_WRAPPED_COLLATERAL: [u8; 20] = hex!("3a3bd7bb9528e159577f7c2e685cc81a765002e2");
+
+// alt_bn128 field modulus used by Conditional Tokens collection ids
+const ALT_BN128_P: &str = "21888242871839275222246405745257275088696311157297823662689037894645226208583";
 
 // Event signatures
 const TRANSFER_SINGLE_SIG: [u8; 32] =
     hex!("c3d58168c5ae7397731d063d5bbf3d657854427343f4c083240f7aacaa2d0f62");
+const TRANSFER_BATCH_SIG: [u8; 32] =
+    hex!("4a39dc06d4c0dbc64b70af90fd698a233a518aa5d07e595d983b8c0526c8f7fb");
 const TRANSFER_SIG: [u8; 32] =
     hex!("ddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef");
 
 // Excluded addresses (protocol contracts, not real users)
-const EXCLUDED_ADDRESSES: [&str; 6] = [
+const EXCLUDED_ADDRESSES: [&str; 7] = [
     "0x4d97dcd97ec945f40cf65f87097ace5ea0476045",
     "0x4bfb41d5b3570defd03c39a9a4d8de6bd8b8982e",
     "0xc5d563a36ae78145c45a50134d48a1215220f80a",
     "0x78769d50be1763ed1ca0d5e878d93f05aabff29e",
     "0xa5ef39c3d3e10d0b270233af41cac69796b12966",
+    "0xd91e80cf2e7be2e162c6513ced06f1dd0da35296",
     "0x0000000000000000000000000000000000000000",
 ];
 
+const ZERO_ADDRESS: &str = "0x0000000000000000000000000000000000000000";
+
 fn is_excluded_address(addr: &str) -> bool {
     EXCLUDED_ADDRESSES
         .iter()
         .any(|&excluded| excluded.eq_ignore_ascii_case(addr))
 }
 
+/// CTF or NegRisk exchange contract, the taker of a taker order's own fill
+fn is_exchange_address(addr: &str) -> bool {
+    [CTF_EXCHANGE, NEG_RISK_EXCHANGE]
+        .iter()
+        .any(|exchange| format_address(exchange).eq_ignore_ascii_case(addr))
+}
+
+/// Transfer between protocol contracts; mints and burns (zero address) are never internal
+fn is_internal_transfer(from: &str, to: &str) -> bool {
+    from != ZERO_ADDRESS && to != ZERO_ADDRESS && is_excluded_address(from) && is_excluded_address(to)
+}
+
 fn format_address(bytes: &[u8]) -> String {
     format!("0x{}", Hex(bytes).to_string())
 }
@@ -126,6 +171,24 @@ fn format_price_decimal(maker_amount: &BigInt, taker_amount: &BigInt) -> String
 // LAYER 1: Event Extraction
 //==============================================
 
+/// Orders passed to the exchanges' fill / match calls in a transaction (from its call traces,
+/// or its input when the exchange is called directly and no traces are available)
+fn exchange_call_orders(transaction: &eth::TransactionTrace) -> Vec<abi::ExchangeOrder> {
+    let is_exchange = |address: &[u8]| address == CTF_EXCHANGE || address == NEG_RISK_EXCHANGE;
+    if transaction.calls.is_empty() {
+        if is_exchange(&transaction.to) {
+            return abi::decode_exchange_orders(&transaction.input, &transaction.to);
+        }
+        return Vec::new();
+    }
+    transaction
+        .calls
+        .iter()
+        .filter(|call| !call.state_reverted && is_exchange(&call.address))
+        .flat_map(|call| abi::decode_exchange_orders(&call.input, &call.address))
+        .collect()
+}
+
 /// Extracts OrderFilled events from CTF Exchange and NegRisk Exchange
 #[substreams::handlers::map]
 fn map_order_fills(blk: eth::Block) -> Result<pnl::OrderFills, substreams::errors::Error> {
@@ -136,6 +199,7 @@ fn map_order_fills(blk: eth::Block) -> Result<pnl::OrderFills, substreams::error
     };
 
     for receipt in blk.receipts() {
+        let mut call_orders: Option<Vec<abi::ExchangeOrder>> = None;
         for log in &receipt.receipt.logs {
             let is_ctf = log.address == CTF_EXCHANGE;
             let is_neg_risk = log.address == NEG_RISK_EXCHANGE;
@@ -177,6 +241,14 @@ fn map_order_fills(blk: eth::Block) -> Result<pnl::OrderFills, substreams::error
                     )
                 };
 
+                // The filled order's size, from the call order with the same EIP-712 hash
+                let order_size = call_orders
+                    .get_or_insert_with(|| exchange_call_orders(receipt.transaction))
+                    .iter()
+                    .find(|o| o.order_hash == decoded.order_hash)
+                    .map(|o| o.maker_amount.clone())
+                    .unwrap_or_default();
+
                 let fill = pnl::OrderFill {
                     id: format!("{}-{}", Hex(&receipt.transaction.hash).to_string(), log.index),
                     tx_hash: Hex(&receipt.transaction.hash).to_string(),
@@ -196,6 +268,7 @@ fn map_order_fills(blk: eth::Block) -> Result<pnl::OrderFills, substreams::error
                     taker_amount_filled: decoded.taker_amount_filled,
                     exchange: if is_ctf { "ctf" } else { "neg_risk" }.to_string(),
                     order_hash: decoded.order_hash,
+                    order_size,
                 };
 
                 fills.fills.push(fill);
@@ -206,11 +279,88 @@ fn map_order_fills(blk: eth::Block) -> Result<pnl::OrderFills, substreams::error
     Ok(fills)
 }
 
-/// Extracts ERC1155 TransferSingle events
+/// Extracts OrderCancelled events from CTF Exchange and NegRisk Exchange
+#[substreams::handlers::map]
+fn map_order_cancellations(blk: eth::Block) -> Result<pnl::OrderCancellations, substreams::errors::Error> {
+    let mut cancellations = pnl::OrderCancellations {
+        block_number: blk.number,
+        block_timestamp: Some(*blk.timestamp()),
+        ..Default::default()
+    };
+
+    for receipt in blk.receipts() {
+        for log in &receipt.receipt.logs {
+            let is_ctf = log.address == CTF_EXCHANGE;
+            if !is_ctf && log.address != NEG_RISK_EXCHANGE {
+                continue;
+            }
+
+            if let Some(decoded) = abi::decode_order_cancelled(log) {
+                cancellations.cancellations.push(pnl::OrderCancellation {
+                    id: format!("{}-{}", Hex(&receipt.transaction.hash).to_string(), log.index),
+                    tx_hash: Hex(&receipt.transaction.hash).to_string(),
+                    log_index: log.index,
+                    block_number: blk.number,
+                    timestamp: Some(*blk.timestamp()),
+                    order_hash: decoded.order_hash,
+                    exchange: if is_ctf { "ctf" } else { "neg_risk" }.to_string(),
+                });
+            }
+        }
+    }
+
+    Ok(cancellations)
+}
+
+/// Extracts admin / governance events (pauses, admins, operators, fees, factories) from both exchanges
+#[substreams::handlers::map]
+fn map_exchange_events(blk: eth::Block) -> Result<pnl::ExchangeEvents, substreams::errors::Error> {
+    let mut events = pnl::ExchangeEvents {
+        block_number: blk.number,
+        block_timestamp: Some(*blk.timestamp()),
+        ..Default::default()
+    };
+
+    for receipt in blk.receipts() {
+        for log in &receipt.receipt.logs {
+            let is_ctf = log.address == CTF_EXCHANGE;
+            if !is_ctf && log.address != NEG_RISK_EXCHANGE {
+                continue;
+            }
+
+            if let Some(decoded) = abi::decode_exchange_admin_event(log) {
+                let counterparty = if decoded.counterparty.is_empty() {
+                    String::new()
+                } else {
+                    format_address(&decoded.counterparty)
+                };
+
+                events.events.push(pnl::ExchangeEvent {
+                    id: format!("{}-{}", Hex(&receipt.transaction.hash).to_string(), log.index),
+                    tx_hash: Hex(&receipt.transaction.hash).to_string(),
+                    log_index: log.index,
+                    block_number: blk.number,
+                    timestamp: Some(*blk.timestamp()),
+                    exchange: if is_ctf { "ctf" } else { "neg_risk" }.to_string(),
+                    event_type: decoded.kind.to_string(),
+                    account: format_address(&decoded.account),
+                    counterparty,
+                    token_id: decoded.token_id,
+                    amount: decoded.amount,
+                });
+            }
+        }
+    }
+
+    Ok(events)
+}
+
+/// Extracts ERC1155 TransferSingle and TransferBatch events
 #[substreams::handlers::map]
 fn map_token_transfers(blk: eth::Block) -> Result<pnl::TokenTransfers, substreams::errors::Error> {
     let mut transfers = pnl::TokenTransfers {
         block_number: blk.number,
+        block_timestamp: Some(*blk.timestamp()),
         ..Default::default()
     };
 
@@ -221,8 +371,8 @@ fn map_token_transfers(blk: eth::Block) -> Result<pnl::TokenTransfers, substream
                     let from = format_address(&decoded.from);
                     let to = format_address(&decoded.to);
 
-                    // Skip internal transfers
-                    if is_excluded_address(&from) && is_excluded_address(&to) {
+                    // Skip internal transfers (mints and burns are kept for open interest)
+                    if is_internal_transfer(&from, &to) {
                         continue;
                     }
 
@@ -238,6 +388,30 @@ fn map_token_transfers(blk: eth::Block) -> Result<pnl::TokenTransfers, substream
                         contract_address: format_address(&log.address),
                     });
                 }
+            } else if log.topics.len() >= 4 && log.topics[0] == TRANSFER_BATCH_SIG {
+                // Batch transfers (NegRisk adapter conversions, splits, merges) - one entry per id
+                if let Some(decoded) = abi::decode_erc1155_transfer_batch(log) {
+                    let from = format_address(&decoded.from);
+                    let to = format_address(&decoded.to);
+
+                    if is_internal_transfer(&from, &to) {
+                        continue;
+                    }
+
+                    for (token_id, amount) in decoded.token_ids.into_iter().zip(decoded.amounts) {
+                        transfers.transfers.push(pnl::TokenTransfer {
+                            tx_hash: Hex(&receipt.transaction.hash).to_string(),
+                            log_index: log.index,
+                            block_number: blk.number,
+                            timestamp: Some(*blk.timestamp()),
+                            from_address: from.clone(),
+                            to_address: to.clone(),
+                            token_id,
+                            amount,
+                            contract_address: format_address(&log.address),
+                        });
+                    }
+                }
             }
         }
     }
@@ -245,11 +419,14 @@ fn map_token_transfers(blk: eth::Block) -> Result<pnl::TokenTransfers, substream
     Ok(transfers)
 }
 
-/// Extracts USDC Transfer events
+/// Extracts USDC Transfer events to or from known users (store_known_users)
 #[substreams::handlers::map]
-fn map_usdc_transfers(blk: eth::Block) -> Result<pnl::UsdcTransfers, substreams::errors::Error> {
+fn map_usdc_transfers(blk: eth::Block, known_users_store: StoreGetInt64) -> Result<pnl::UsdcTransfers, substreams::errors::Error> {
+    let is_known_user = |addr: &str| !is_excluded_address(addr) && known_users_store.get_last(addr).is_some();
+
     let mut transfers = pnl::UsdcTransfers {
         block_number: blk.number,
+        block_timestamp: Some(*blk.timestamp()),
         ..Default::default()
     };
 
@@ -260,13 +437,18 @@ fn map_usdc_transfers(blk: eth::Block) -> Result<pnl::UsdcTransfers, substreams:
                 && log.topics[0] == TRANSFER_SIG
             {
                 if let Some(decoded) = abi::decode_erc20_transfer(log) {
+                    let from_address = format_address(&decoded.from);
+                    let to_address = format_address(&decoded.to);
+                    if !is_known_user(&from_address) && !is_known_user(&to_address) {
+                        continue;
+                    }
                     transfers.transfers.push(pnl::UsdcTransfer {
                         tx_hash: Hex(&receipt.transaction.hash).to_string(),
                         log_index: log.index,
                         block_number: blk.number,
                         timestamp: Some(blk.timestamp().clone()),
-                        from_address: format_address(&decoded.from),
-                        to_address: format_address(&decoded.to),
+                        from_address,
+                        to_address,
                         amount: decoded.amount,
                     });
                 }
@@ -277,284 +459,3422 @@ fn map_usdc_transfers(blk: eth::Block) -> Result<pnl::UsdcTransfers, substreams:
     Ok(transfers)
 }
 
-//==============================================
-// LAYER 2: Stores
-//==============================================
-
-/// Store user positions: key = {user}:{token_id}, value = quantity delta
-#[substreams::handlers::store]
-fn store_user_positions(transfers: pnl::TokenTransfers, store: StoreAddBigInt) {
-    for transfer in transfers.transfers {
-        let amount = BigInt::from_str(&transfer.amount).unwrap_or_default();
+/// Value of outcome tokens removed from an FPMM pool, in USDC.
+///
+/// Removals are proportional to the pool balances, so the removed amounts carry the
+/// pool's marginal prices: price_i = (1 / a_i) / sum(1 / a_k). The value
+/// sum(a_i * price_i) is therefore the harmonic mean of the amounts, computed here
+/// as n * prod(a) / sum_k(prod_{j != k} a_j) to stay in integer math.
+fn fpmm_outcome_value(amounts: &[BigInt]) -> BigInt {
+    if amounts.is_empty() || amounts.iter().any(|a| a.is_zero()) {
+        return BigInt::from(0);
+    }
 
-        // Decrease from sender
-        if !is_excluded_address(&transfer.from_address) {
-            let key = format!("{}:{}", transfer.from_address.to_lowercase(), transfer.token_id);
-            let neg_amount = BigInt::from(0i64) - amount.clone();
-            store.add(0, &key, &neg_amount);
-        }
+    let mut product = BigInt::from(1);
+    for amount in amounts {
+        product = product * amount;
+    }
 
-        // Increase for receiver
-        if !is_excluded_address(&transfer.to_address) {
-            let key = format!("{}:{}", transfer.to_address.to_lowercase(), transfer.token_id);
-            store.add(0, &key, &amount);
-        }
+    let mut denominator = BigInt::from(0);
+    for amount in amounts {
+        denominator += &product / amount;
     }
+
+    (product * BigInt::from(amounts.len() as u64)) / denominator
 }
 
-/// Store user cost basis: key = {user}:{token_id}, value = total cost
-#[substreams::handlers::store]
-fn store_user_cost_basis(fills: pnl::OrderFills, store: StoreAddBigInt) {
-    for fill in fills.fills {
-        let amount = BigInt::from_str(&fill.amount).unwrap_or_default();
+/// Marginal prices of an FPMM pool whose balances are proportional to `amounts`
+/// (18 decimals): price_i = (1 / a_i) / sum(1 / a_k). Empty when any amount is zero.
+fn fpmm_prices(amounts: &[BigInt]) -> Vec<BigInt> {
+    if amounts.is_empty() || amounts.iter().any(|a| a.is_zero()) {
+        return Vec::new();
+    }
 
-        if fill.side == "buy" && !is_excluded_address(&fill.taker) {
-            let key = format!("{}:{}", fill.taker.to_lowercase(), fill.token_id);
-            store.add(0, &key, &amount);
-        } else if fill.side == "sell" && !is_excluded_address(&fill.taker) {
-            let key = format!("{}:{}", fill.taker.to_lowercase(), fill.token_id);
-            let neg_amount = -amount;
-            store.add(0, &key, &neg_amount);
+    let scale = BigInt::from_str("1000000000000000000").unwrap(); // 10^18
+    let mut product = BigInt::from(1);
+    for amount in amounts {
+        product = product * amount;
+    }
+
+    let weights: Vec<BigInt> = amounts.iter().map(|a| &product / a).collect();
+    let total = weights.iter().fold(BigInt::from(0), |acc, w| acc + w);
+    weights.into_iter().map(|w| (w * &scale) / &total).collect()
+}
+
+/// ERC1155 legs sent from `from` to `to` in `logs`, in emission order (zero amounts included,
+/// so a batch transfer's legs line up with its outcome indexes)
+fn collect_sent_legs(logs: &[eth::Log], from: &[u8], to: &[u8]) -> Vec<pnl::TokenLeg> {
+    let mut legs = Vec::new();
+    for log in logs {
+        if log.address != CONDITIONAL_TOKENS {
+            continue;
+        }
+        if let Some(single) = abi::decode_erc1155_transfer_single(log) {
+            if single.from == from && single.to == to {
+                legs.push(pnl::TokenLeg { token_id: single.token_id, amount: single.amount });
+            }
+        } else if let Some(batch) = abi::decode_erc1155_transfer_batch(log) {
+            if batch.from == from && batch.to == to {
+                legs.extend(
+                    batch
+                        .token_ids
+                        .into_iter()
+                        .zip(batch.amounts)
+                        .map(|(token_id, amount)| pnl::TokenLeg { token_id, amount }),
+                );
+            }
         }
     }
+    legs
 }
 
-/// Store user realized P&L: key = {user}, value = realized P&L delta
-#[substreams::handlers::store]
-fn store_user_realized_pnl(
-    fills: pnl::OrderFills,
-    positions_store: StoreGetBigInt,
-    cost_basis_store: StoreGetBigInt,
-    store: StoreAddBigInt,
-) {
-    for fill in fills.fills {
-        if fill.side == "sell" && !is_excluded_address(&fill.taker) {
-            let key_user = fill.taker.to_lowercase();
-            let key_position = format!("{}:{}", key_user, fill.token_id);
+/// Cost of outcome tokens an FPMM pool sent to a funder, one entry per leg: each leg at the
+/// pool's marginal price for its outcome index (zero when the legs don't line up with `amounts`)
+fn fpmm_leg_costs(amounts: &[BigInt], legs: &[pnl::TokenLeg]) -> Vec<BigInt> {
+    let scale = BigInt::from_str("1000000000000000000").unwrap(); // 10^18
+    let prices = fpmm_prices(amounts);
+    if prices.len() != legs.len() {
+        return vec![BigInt::from(0); legs.len()];
+    }
+    legs.iter()
+        .zip(prices)
+        .map(|(leg, price)| (BigInt::from_str(&leg.amount).unwrap_or_default() * price) / &scale)
+        .collect()
+}
 
-            // Get position quantity and cost basis
-            let quantity = positions_store.get_last(&key_position).unwrap_or_else(|| BigInt::from(0));
-            let cost_basis = cost_basis_store.get_last(&key_position).unwrap_or_else(|| BigInt::from(0));
+/// Extracts FPMMFundingAdded / FPMMFundingRemoved events from legacy FPMM pools
+#[substreams::handlers::map]
+fn map_fpmm_funding(blk: eth::Block) -> Result<pnl::FpmmFundingEvents, substreams::errors::Error> {
+    let mut events = pnl::FpmmFundingEvents {
+        block_number: blk.number,
+        block_timestamp: Some(*blk.timestamp()),
+        ..Default::default()
+    };
 
-            // Calculate average entry price
-            let avg_entry_price = if !quantity.is_zero() {
-                &cost_basis / &quantity
+    for receipt in blk.receipts() {
+        let logs = &receipt.receipt.logs;
+        // Token transfers of a funding call precede its event; scan only the logs since the previous one
+        let mut window_start = 0;
+        for (position, log) in logs.iter().enumerate() {
+            let (kind, decoded) = if let Some(decoded) = abi::decode_fpmm_funding_added(log) {
+                ("added", decoded)
+            } else if let Some(decoded) = abi::decode_fpmm_funding_removed(log) {
+                ("removed", decoded)
             } else {
-                BigInt::from(0)
+                continue;
             };
 
-            // Parse sell price from fill.price (it's formatted as "0.XXXXXX")
-            let sell_price = parse_price_decimal(&fill.price);
-            let sell_amount = BigInt::from_str(&fill.amount).unwrap_or_default();
-
-            // Calculate realized P&L: (sell_price - avg_entry_price) * amount
-            let pnl = (&sell_price - &avg_entry_price) * &sell_amount;
+            let amounts: Vec<BigInt> = decoded
+                .amounts
+                .iter()
+                .map(|a| BigInt::from_str(a).unwrap_or_default())
+                .collect();
+
+            let tokens_received = collect_sent_legs(&logs[window_start..position], &log.address, &decoded.funder);
+            window_start = position + 1;
+
+            // addFunding pulls `addedFunds` of collateral and reports the per-outcome amounts
+            // kept by the pool; the outcome the pool is shortest on keeps all of it, the rest
+            // is sent back to the funder as outcome tokens and does not count as deposited.
+            let (usdc_deposited, fees_withdrawn, removed_value) = if kind == "added" {
+                let added = amounts.iter().cloned().max().unwrap_or_default();
+                let sent_back = fpmm_leg_costs(&amounts, &tokens_received)
+                    .into_iter()
+                    .fold(BigInt::from(0), |acc, cost| acc + cost);
+                ((added - sent_back).to_string(), "0".to_string(), "0".to_string())
+            } else {
+                (
+                    "0".to_string(),
+                    decoded.collateral_removed_from_fee_pool,
+                    fpmm_outcome_value(&amounts).to_string(),
+                )
+            };
 
-            store.add(0, &key_user, &pnl);
+            events.events.push(pnl::FpmmFundingEvent {
+                id: format!("{}-{}", Hex(&receipt.transaction.hash).to_string(), log.index),
+                tx_hash: Hex(&receipt.transaction.hash).to_string(),
+                log_index: log.index,
+                block_number: blk.number,
+                timestamp: Some(*blk.timestamp()),
+                pool_address: format_address(&log.address),
+                funder: format_address(&decoded.funder),
+                kind: kind.to_string(),
+                amounts: decoded.amounts,
+                shares: decoded.shares,
+                usdc_deposited,
+                fees_withdrawn,
+                removed_value,
+                tokens_received,
+            });
         }
     }
+
+    Ok(events)
 }
 
-/// Helper to parse price from "0.XXXXXXXXXXXXXXXXXX" format (18 decimals) back to BigInt (scaled by 10^18)
-fn parse_price_decimal(price_str: &str) -> BigInt {
-    let cleaned = price_str.trim_start_matches('0').trim_start_matches('.');
-    let padded = format!("{:0>18}", cleaned);
-    BigInt::from_str(&padded).unwrap_or_default()
+fn keccak256(data: &[u8]) -> [u8; 32] {
+    use tiny_keccak::{Hasher, Keccak};
+    let mut hasher = Keccak::v256();
+    let mut output = [0u8; 32];
+    hasher.update(data);
+    hasher.finalize(&mut output);
+    output
 }
 
-/// Helper to format BigInt (18 decimals) to USDC string (6 decimals)
-fn format_usdc_from_bigint(value: &BigInt) -> String {
-    // Convert from 18 decimals to 6 decimals (USDC)
-    let scale = BigInt::from_str("1000000000000").unwrap(); // 10^12
-    let scaled = value / &scale;
-    scaled.to_string()
+/// Conditional Tokens collection id of `index_set` in a top-level condition (CTHelpers.getCollectionId
+/// with no parent): the hash is lifted to an alt_bn128 point and compressed into 32 bytes
+fn ctf_collection_id(condition_id: &[u8], index_set: u64) -> [u8; 32] {
+    use num_bigint::BigUint;
+    use num_traits::One;
+
+    let p = BigUint::from_str(ALT_BN128_P).unwrap();
+    let mut packed = condition_id.to_vec();
+    packed.extend_from_slice(&[0u8; 24]);
+    packed.extend_from_slice(&index_set.to_be_bytes());
+    let hash = keccak256(&packed);
+    let odd = hash[0] & 0x80 != 0;
+
+    let mut x = BigUint::from_bytes_be(&hash);
+    let sqrt_exponent = (&p + BigUint::one()) >> 2;
+    let mut y = loop {
+        x = (x + BigUint::one()) % &p;
+        let yy = (&x * &x * &x + BigUint::from(3u8)) % &p;
+        let y = yy.modpow(&sqrt_exponent, &p);
+        if (&y * &y) % &p == yy {
+            break y;
+        }
+    };
+    if odd == !y.bit(0) {
+        y = &p - y;
+    }
+    if y.bit(0) {
+        x ^= BigUint::one() << 254;
+    }
+
+    let bytes = x.to_bytes_be();
+    let mut collection_id = [0u8; 32];
+    collection_id[32 - bytes.len()..].copy_from_slice(&bytes);
+    collection_id
 }
 
-/// Store user volume: key = {user}, value = volume delta
-#[substreams::handlers::store]
-fn store_user_volume(fills: pnl::OrderFills, store: StoreAddBigInt) {
-    for fill in fills.fills {
-        let amount = BigInt::from_str(&fill.amount).unwrap_or_default();
+/// Conditional Tokens position id (ERC1155 token id) of a collection backed by `collateral`
+fn ctf_position_id(collateral: &[u8], collection_id: &[u8; 32]) -> String {
+    let mut packed = collateral.to_vec();
+    packed.extend_from_slice(collection_id);
+    num_bigint::BigUint::from_bytes_be(&keccak256(&packed)).to_string()
+}
 
-        // Track volume for both maker and taker
-        if !is_excluded_address(&fill.maker) {
-            store.add(0, &fill.maker.to_lowercase(), &amount);
-        }
-        if !is_excluded_address(&fill.taker) {
-            store.add(0, &fill.taker.to_lowercase(), &amount);
-        }
+/// (YES, NO) tokens of a registration. YES is the position of index set 1 (outcome slot 0), backed
+/// by USDC on the CTF exchange or by wrapped collateral on NegRisk; registration order is the
+/// fallback when neither token matches.
+fn registration_outcomes(registration: &pnl::TokenRegistration) -> (String, String) {
+    let in_order = (registration.token_id.clone(), registration.complement_token_id.clone());
+    let Ok(condition_id) = Hex::decode(registration.condition_id.as_str()) else {
+        return in_order;
+    };
+    let collateral: &[u8] = if registration.exchange == "neg_risk" { &NEG_RISK_WRAPPED_COLLATERAL } else { &USDC_CONTRACT };
+    let yes_token_id = ctf_position_id(collateral, &ctf_collection_id(&condition_id, 1));
+
+    if yes_token_id == registration.complement_token_id {
+        (registration.complement_token_id.clone(), registration.token_id.clone())
+    } else {
+        in_order
     }
 }
 
-/// Store user trade count: key = {user}, value = count delta
-#[substreams::handlers::store]
-fn store_user_trade_count(fills: pnl::OrderFills, store: StoreAddInt64) {
-    for fill in fills.fills {
-        if !is_excluded_address(&fill.maker) {
-            store.add(0, &fill.maker.to_lowercase(), 1);
-        }
-        if !is_excluded_address(&fill.taker) {
-            store.add(0, &fill.taker.to_lowercase(), 1);
+/// Extracts TokenRegistered events (both exchanges) and ConditionPreparation events (Conditional Tokens)
+#[substreams::handlers::map]
+fn map_market_registry(blk: eth::Block) -> Result<pnl::MarketRegistry, substreams::errors::Error> {
+    let mut registry = pnl::MarketRegistry {
+        block_number: blk.number,
+        block_timestamp: Some(*blk.timestamp()),
+        ..Default::default()
+    };
+
+    for receipt in blk.receipts() {
+        for log in &receipt.receipt.logs {
+            if log.address == CTF_EXCHANGE || log.address == NEG_RISK_EXCHANGE {
+                if let Some(decoded) = abi::decode_token_registered(log) {
+                    registry.tokens.push(pnl::TokenRegistration {
+                        token_id: decoded.token0,
+                        complement_token_id: decoded.token1,
+                        condition_id: decoded.condition_id,
+                        exchange: if log.address == CTF_EXCHANGE { "ctf" } else { "neg_risk" }.to_string(),
+                        block_number: blk.number,
+                    });
+                }
+            } else if log.address == CONDITIONAL_TOKENS {
+                if let Some(decoded) = abi::decode_condition_preparation(log) {
+                    registry.conditions.push(pnl::ConditionPreparation {
+                        condition_id: decoded.condition_id,
+                        oracle: format_address(&decoded.oracle),
+                        question_id: format!("0x{}", Hex(&decoded.question_id).to_string()),
+                        outcome_slot_count: decoded.outcome_slot_count.parse().unwrap_or(0),
+                        block_number: blk.number,
+                    });
+                }
+            }
         }
     }
+
+    Ok(registry)
 }
 
-/// Store market volume: key = {token_id}, value = volume
-#[substreams::handlers::store]
-fn store_market_volume(fills: pnl::OrderFills, store: StoreAddBigInt) {
-    for fill in fills.fills {
-        let amount = BigInt::from_str(&fill.amount).unwrap_or_default();
-        store.add(0, &fill.token_id, &amount);
+/// NegRisk market id for a question: the question id with its index byte cleared
+fn neg_risk_market_id(question_id: &[u8]) -> String {
+    let mut market_id = question_id.to_vec();
+    if let Some(last) = market_id.last_mut() {
+        *last = 0;
     }
+    format!("0x{}", Hex(&market_id).to_string())
 }
 
-/// Store latest prices: key = {token_id}, value = TokenPrice proto
-#[substreams::handlers::store]
-fn store_latest_prices(fills: pnl::OrderFills, store: StoreSetProto<pnl::TokenPrice>) {
-    for fill in fills.fills {
-        let price = pnl::TokenPrice {
-            token_id: fill.token_id.clone(),
-            price: fill.price.clone(),
-            block_number: fill.block_number,
-            timestamp: fill.timestamp,
-            volume_24h: String::new(),
-        };
-        store.set(0, &fill.token_id, &price);
+/// Collect ERC1155 legs moved between `holder` and the NegRisk adapter in `logs`, the logs an
+/// adapter event emitted since the previous one in its transaction.
+/// Returns (tokens sent to the adapter, tokens received from the adapter).
+fn collect_adapter_legs(logs: &[eth::Log], holder: &[u8]) -> (Vec<pnl::TokenLeg>, Vec<pnl::TokenLeg>) {
+    let mut tokens_out = Vec::new();
+    let mut tokens_in = Vec::new();
+
+    for log in logs {
+        if log.address != CONDITIONAL_TOKENS {
+            continue;
+        }
+
+        let moved: Vec<(Vec<u8>, Vec<u8>, String, String)> =
+            if let Some(single) = abi::decode_erc1155_transfer_single(log) {
+                vec![(single.from, single.to, single.token_id, single.amount)]
+            } else if let Some(batch) = abi::decode_erc1155_transfer_batch(log) {
+                batch
+                    .token_ids
+                    .into_iter()
+                    .zip(batch.amounts)
+                    .map(|(token_id, amount)| (batch.from.clone(), batch.to.clone(), token_id, amount))
+                    .collect()
+            } else {
+                continue;
+            };
+
+        for (from, to, token_id, amount) in moved {
+            if from == holder && to == NEG_RISK_ADAPTER {
+                tokens_out.push(pnl::TokenLeg { token_id, amount });
+            } else if from == NEG_RISK_ADAPTER && to == holder {
+                tokens_in.push(pnl::TokenLeg { token_id, amount });
+            }
+        }
     }
-}
 
-//==============================================
-// LAYER 3: Analytics
-//==============================================
+    (tokens_out, tokens_in)
+}
 
-/// Calculate total fees paid by a user from order fills
-fn calculate_user_fees(fills: &pnl::OrderFills, user: &str) -> BigInt {
-    let mut total_fees = BigInt::from(0);
-    for fill in &fills.fills {
-        if fill.taker.eq_ignore_ascii_case(user) || fill.maker.eq_ignore_ascii_case(user) {
-            let fee = BigInt::from_str(&fill.fee).unwrap_or_default();
-            total_fees = total_fees + fee;
+/// Total USDC received by `holder` in `logs` (the adapter event's own logs, see collect_adapter_legs)
+fn usdc_received(logs: &[eth::Log], holder: &[u8]) -> BigInt {
+    let mut total = BigInt::from(0);
+    for log in logs {
+        if log.address != USDC_CONTRACT {
+            continue;
+        }
+        if let Some(decoded) = abi::decode_erc20_transfer(log) {
+            if decoded.to == holder && decoded.from != holder {
+                total += BigInt::from_str(&decoded.amount).unwrap_or_default();
+            }
         }
     }
-    total_fees
+    total
 }
 
-/// Compute user P&L updates
+/// Extracts NegRiskAdapter events: market/question preparation, conversions, splits, merges, redemptions
 #[substreams::handlers::map]
-fn map_user_pnl(
-    fills: pnl::OrderFills,
-    positions_deltas: Deltas<DeltaBigInt>,
-    positions_store: StoreGetBigInt,
-    cost_basis_store: StoreGetBigInt,
-    realized_pnl_store: StoreGetBigInt,
-    prices_store: StoreGetProto<pnl::TokenPrice>,
-    volume_store: StoreGetBigInt,
-    trade_count_store: StoreGetInt64,
-) -> Result<pnl::UserPnLUpdates, substreams::errors::Error> {
-    let mut updates = pnl::UserPnLUpdates {
-        block_number: fills.block_number,
+fn map_neg_risk_events(blk: eth::Block) -> Result<pnl::NegRiskEvents, substreams::errors::Error> {
+    let mut events = pnl::NegRiskEvents {
+        block_number: blk.number,
+        block_timestamp: Some(*blk.timestamp()),
         ..Default::default()
     };
 
-    // Track users with position changes
-    let mut affected_users: std::collections::HashSet<String> = std::collections::HashSet::new();
+    for receipt in blk.receipts() {
+        let logs = &receipt.receipt.logs;
+        let tx_hash = Hex(&receipt.transaction.hash).to_string();
+        // Start of the logs emitted for the next adapter event: transfers precede their event,
+        // so each conversion, split, merge or redemption owns the logs since the previous one
+        let mut window_start = 0;
+
+        for (position, log) in logs.iter().enumerate() {
+            if log.address != NEG_RISK_ADAPTER {
+                continue;
+            }
+            let window = &logs[window_start..position];
 
-    for delta in &positions_deltas.deltas {
-        let parts: Vec<&str> = delta.key.split(':').collect();
-        if parts.len() == 2 {
-            affected_users.insert(parts[0].to_string());
-        }
-    }
+            let id = format!("{}-{}", tx_hash, log.index);
 
-    // Also track users from fills
+            if let Some(decoded) = abi::decode_market_prepared(log) {
+                events.markets.push(pnl::NegRiskMarket {
+                    market_id: decoded.market_id,
+                    oracle: format_address(&decoded.oracle),
+                    fee_bips: decoded.fee_bips,
+                    data: decoded.data,
+                    tx_hash: tx_hash.clone(),
+                    block_number: blk.number,
+                    timestamp: Some(*blk.timestamp()),
+                });
+            } else if let Some(decoded) = abi::decode_question_prepared(log) {
+                events.questions.push(pnl::NegRiskQuestion {
+                    question_id: decoded.question_id,
+                    market_id: decoded.market_id,
+                    question_index: decoded.index.parse().unwrap_or(0),
+                    data: decoded.data,
+                    tx_hash: tx_hash.clone(),
+                    block_number: blk.number,
+                    timestamp: Some(*blk.timestamp()),
+                });
+            } else if let Some(decoded) = abi::decode_positions_converted(log) {
+                let (tokens_out, tokens_in) = collect_adapter_legs(window, &decoded.stakeholder);
+                window_start = position + 1;
+                let no_count = num_bigint::BigUint::from_str(&decoded.index_set)
+                    .map(|set| set.count_ones() as u32)
+                    .unwrap_or(0);
+
+                events.conversions.push(pnl::NegRiskConversion {
+                    id,
+                    tx_hash: tx_hash.clone(),
+                    log_index: log.index,
+                    block_number: blk.number,
+                    timestamp: Some(*blk.timestamp()),
+                    stakeholder: format_address(&decoded.stakeholder),
+                    market_id: decoded.market_id,
+                    index_set: decoded.index_set,
+                    amount: decoded.amount,
+                    no_count,
+                    usdc_returned: usdc_received(window, &decoded.stakeholder).to_string(),
+                    tokens_out,
+                    tokens_in,
+                });
+            } else {
+                let (kind, stakeholder, condition_id, amount) =
+                    if let Some(decoded) = abi::decode_neg_risk_position_split(log) {
+                        ("split", decoded.stakeholder, decoded.condition_id, decoded.amount)
+                    } else if let Some(decoded) = abi::decode_neg_risk_positions_merge(log) {
+                        ("merge", decoded.stakeholder, decoded.condition_id, decoded.amount)
+                    } else if let Some(decoded) = abi::decode_neg_risk_payout_redemption(log) {
+                        ("redemption", decoded.redeemer, decoded.condition_id, decoded.payout)
+                    } else {
+                        continue;
+                    };
+
+                let (tokens_out, tokens_in) = collect_adapter_legs(window, &stakeholder);
+                window_start = position + 1;
+
+                events.position_changes.push(pnl::NegRiskPositionChange {
+                    id,
+                    tx_hash: tx_hash.clone(),
+                    log_index: log.index,
+                    block_number: blk.number,
+                    timestamp: Some(*blk.timestamp()),
+                    stakeholder: format_address(&stakeholder),
+                    kind: kind.to_string(),
+                    condition_id,
+                    amount,
+                    tokens_out,
+                    tokens_in,
+                });
+            }
+        }
+    }
+
+    Ok(events)
+}
+
+/// Extracts PositionSplit, PositionsMerge, PayoutRedemption and ConditionResolution events from Conditional Tokens
+#[substreams::handlers::map]
+fn map_ctf_events(blk: eth::Block) -> Result<pnl::CtfEvents, substreams::errors::Error> {
+    let mut events = pnl::CtfEvents {
+        block_number: blk.number,
+        block_timestamp: Some(*blk.timestamp()),
+        ..Default::default()
+    };
+
+    for receipt in blk.receipts() {
+        for log in &receipt.receipt.logs {
+            if log.address != CONDITIONAL_TOKENS {
+                continue;
+            }
+
+            if let Some(decoded) = abi::decode_condition_resolution(log) {
+                events.resolutions.push(pnl::ConditionResolution {
+                    id: format!("{}-{}", Hex(&receipt.transaction.hash).to_string(), log.index),
+                    tx_hash: Hex(&receipt.transaction.hash).to_string(),
+                    log_index: log.index,
+                    block_number: blk.number,
+                    timestamp: Some(*blk.timestamp()),
+                    condition_id: decoded.condition_id,
+                    oracle: format_address(&decoded.oracle),
+                    question_id: format!("0x{}", Hex(&decoded.question_id).to_string()),
+                    outcome_slot_count: decoded.outcome_slot_count,
+                    payout_numerators: decoded.payout_numerators,
+                });
+                continue;
+            }
+
+            let change = if let Some(decoded) = abi::decode_ctf_position_split(log) {
+                Some(("split", decoded.stakeholder, decoded.collateral_token, decoded.condition_id, decoded.partition, decoded.amount))
+            } else if let Some(decoded) = abi::decode_ctf_positions_merge(log) {
+                Some(("merge", decoded.stakeholder, decoded.collateral_token, decoded.condition_id, decoded.partition, decoded.amount))
+            } else {
+                abi::decode_ctf_payout_redemption(log).map(|decoded| {
+                    ("redemption", decoded.redeemer, decoded.collateral_token, decoded.condition_id, decoded.index_sets, decoded.payout)
+                })
+            };
+
+            if let Some((kind, stakeholder, collateral_token, condition_id, index_sets, amount)) = change {
+                events.position_changes.push(pnl::CtfPositionChange {
+                    id: format!("{}-{}", Hex(&receipt.transaction.hash).to_string(), log.index),
+                    tx_hash: Hex(&receipt.transaction.hash).to_string(),
+                    log_index: log.index,
+                    block_number: blk.number,
+                    timestamp: Some(*blk.timestamp()),
+                    stakeholder: format_address(&stakeholder),
+                    kind: kind.to_string(),
+                    condition_id,
+                    collateral_token: format_address(&collateral_token),
+                    index_sets,
+                    amount,
+                });
+            }
+        }
+    }
+
+    Ok(events)
+}
+
+/// Extracts Safe proxy wallet creations (proxy -> owner) from Polymarket's SafeProxyFactory
+#[substreams::handlers::map]
+fn map_proxy_wallets(blk: eth::Block) -> Result<pnl::ProxyWallets, substreams::errors::Error> {
+    let mut wallets = pnl::ProxyWallets {
+        block_number: blk.number,
+        ..Default::default()
+    };
+
+    for receipt in blk.receipts() {
+        for log in &receipt.receipt.logs {
+            if log.address != SAFE_PROXY_FACTORY {
+                continue;
+            }
+
+            if let Some(decoded) = abi::decode_proxy_creation(log) {
+                wallets.wallets.push(pnl::ProxyWallet {
+                    proxy: format_address(&decoded.proxy),
+                    owner: format_address(&decoded.owner),
+                    block_number: blk.number,
+                });
+            }
+        }
+    }
+
+    Ok(wallets)
+}
+
+//==============================================
+// LAYER 2: Stores
+//==============================================
+
+/// Store user positions: key = {user}:{token_id}, value = quantity delta
+#[substreams::handlers::store]
+fn store_user_positions(transfers: pnl::TokenTransfers, store: StoreAddBigInt) {
+    for transfer in transfers.transfers {
+        let amount = BigInt::from_str(&transfer.amount).unwrap_or_default();
+
+        // Decrease from sender
+        if !is_excluded_address(&transfer.from_address) {
+            let key = format!("{}:{}", transfer.from_address.to_lowercase(), transfer.token_id);
+            let neg_amount = BigInt::from(0i64) - amount.clone();
+            store.add(0, &key, &neg_amount);
+        }
+
+        // Increase for receiver
+        if !is_excluded_address(&transfer.to_address) {
+            let key = format!("{}:{}", transfer.to_address.to_lowercase(), transfer.token_id);
+            store.add(0, &key, &amount);
+        }
+    }
+}
+
+/// Store token open interest: key = {token_id}, value = outstanding shares (minted - burned)
+#[substreams::handlers::store]
+fn store_token_open_interest(transfers: pnl::TokenTransfers, store: StoreAddBigInt) {
+    for transfer in transfers.transfers {
+        let amount = BigInt::from_str(&transfer.amount).unwrap_or_default();
+
+        if transfer.from_address == ZERO_ADDRESS {
+            store.add(0, &transfer.token_id, &amount);
+        } else if transfer.to_address == ZERO_ADDRESS {
+            store.add(0, &transfer.token_id, &(BigInt::from(0) - amount));
+        }
+    }
+}
+
+/// Store condition open interest: key = {condition_id}, value = collateral locked
+/// Splits lock collateral; merges and redemption payouts release it.
+#[substreams::handlers::store]
+fn store_condition_open_interest(ctf_events: pnl::CtfEvents, store: StoreAddBigInt) {
+    for change in ctf_events.position_changes {
+        let amount = BigInt::from_str(&change.amount).unwrap_or_default();
+        let amount = if change.kind == "split" { amount } else { BigInt::from(0) - amount };
+        store.add(0, &change.condition_id, &amount);
+    }
+}
+
+/// Store position episodes: key = {user}:{token_id}, value = PositionEpisode proto
+/// A new episode opens when the quantity crosses above zero and ends when it returns to zero.
+#[substreams::handlers::store]
+fn store_position_episodes(
+    transfers: pnl::TokenTransfers,
+    positions_deltas: Deltas<DeltaBigInt>,
+    store: StoreSetProto<pnl::PositionEpisode>,
+) {
+    let zero = BigInt::from(0);
+    for delta in positions_deltas.deltas {
+        let was_open = delta.old_value > zero;
+        let is_open = delta.new_value > zero;
+
+        if !was_open && is_open {
+            let episode = pnl::PositionEpisode {
+                opened_block: transfers.block_number,
+                opened_at: transfers.block_timestamp,
+            };
+            store.set(0, &delta.key, &episode);
+        } else if was_open && !is_open {
+            store.set(0, &delta.key, &pnl::PositionEpisode::default());
+        }
+    }
+}
+
+/// Split `total` across legs proportionally to `weights`; the last leg absorbs rounding.
+/// Falls back to an even split when every weight is zero.
+fn allocate_by_weight(total: &BigInt, weights: &[BigInt]) -> Vec<BigInt> {
+    if weights.is_empty() {
+        return vec![];
+    }
+
+    let weight_sum = weights.iter().fold(BigInt::from(0), |acc, w| acc + w);
+    let even = vec![BigInt::from(1); weights.len()];
+    let (weights, weight_sum) = if weight_sum.is_zero() {
+        (even.as_slice(), BigInt::from(weights.len() as u64))
+    } else {
+        (weights, weight_sum)
+    };
+
+    let mut allocated = BigInt::from(0);
+    let mut shares = Vec::with_capacity(weights.len());
+    for (i, weight) in weights.iter().enumerate() {
+        let share = if i == weights.len() - 1 {
+            total - &allocated
+        } else {
+            (total * weight) / &weight_sum
+        };
+        allocated += share.clone();
+        shares.push(share);
+    }
+    shares
+}
+
+/// Market value of token legs at latest prices (USDC, 6 decimals), one entry per leg
+fn leg_values(legs: &[pnl::TokenLeg], prices_store: &StoreGetProto<pnl::TokenPrice>) -> Vec<BigInt> {
+    let scale = BigInt::from_str("1000000000000000000").unwrap(); // 10^18
+    legs.iter()
+        .map(|leg| {
+            let quantity = BigInt::from_str(&leg.amount).unwrap_or_default();
+            let price = prices_store
+                .get_last(&leg.token_id)
+                .map(|p| parse_price_decimal(&p.price))
+                .unwrap_or_else(|| BigInt::from(0));
+            (quantity * price) / &scale
+        })
+        .collect()
+}
+
+/// Build synthetic fills for token legs exchanged with the NegRisk adapter.
+/// `base` carries the tx fields and stakeholder (as taker); each leg's USDC amount comes from `usdc`.
+fn adapter_fills(base: &pnl::OrderFill, side: &str, legs: &[pnl::TokenLeg], usdc: &[BigInt]) -> Vec<pnl::OrderFill> {
+    legs.iter()
+        .zip(usdc)
+        .map(|(leg, usdc)| {
+            let quantity = BigInt::from_str(&leg.amount).unwrap_or_default();
+            let (maker_asset_id, taker_asset_id, maker_amount_filled, taker_amount_filled) = if side == "buy" {
+                (leg.token_id.clone(), "0".to_string(), leg.amount.clone(), usdc.to_string())
+            } else {
+                ("0".to_string(), leg.token_id.clone(), usdc.to_string(), leg.amount.clone())
+            };
+
+            pnl::OrderFill {
+                id: format!("{}-{}-{}", base.id, side, leg.token_id),
+                token_id: leg.token_id.clone(),
+                side: side.to_string(),
+                price: format_price_decimal(usdc, &quantity),
+                amount: usdc.to_string(),
+                maker_asset_id,
+                taker_asset_id,
+                maker_amount_filled,
+                taker_amount_filled,
+                ..base.clone()
+            }
+        })
+        .collect()
+}
+
+/// Converts NegRisk adapter activity into synthetic fills for cost-basis accounting.
+///
+/// - Conversions: NO legs are sold for the USDC returned plus the market value of the
+///   YES legs received; YES legs are bought at their latest price.
+/// - Splits: the collateral paid is the cost of the YES/NO legs received.
+/// - Merges / redemptions: the collateral received is the proceeds of the legs given up.
+///
+/// Multi-leg amounts are allocated by latest price (evenly when no price is known); redemption
+/// payouts by each leg's resolved payout, so losing legs realize nothing.
+#[substreams::handlers::map]
+fn map_neg_risk_fills(
+    events: pnl::NegRiskEvents,
+    prices_store: StoreGetProto<pnl::TokenPrice>,
+    payouts_store: StoreGetString,
+) -> Result<pnl::OrderFills, substreams::errors::Error> {
+    let mut fills = pnl::OrderFills {
+        block_number: events.block_number,
+        block_timestamp: events.block_timestamp,
+        ..Default::default()
+    };
+
+    let adapter = format_address(&NEG_RISK_ADAPTER);
+
+    for conversion in &events.conversions {
+        let base = pnl::OrderFill {
+            id: conversion.id.clone(),
+            tx_hash: conversion.tx_hash.clone(),
+            log_index: conversion.log_index,
+            block_number: conversion.block_number,
+            timestamp: conversion.timestamp,
+            maker: adapter.clone(),
+            taker: conversion.stakeholder.clone(),
+            fee: "0".to_string(),
+            exchange: "neg_risk_adapter".to_string(),
+            ..Default::default()
+        };
+
+        let yes_costs = leg_values(&conversion.tokens_in, &prices_store);
+        let yes_value = yes_costs.iter().fold(BigInt::from(0), |acc, v| acc + v);
+        let proceeds = BigInt::from_str(&conversion.usdc_returned).unwrap_or_default() + yes_value;
+        let no_proceeds = allocate_by_weight(&proceeds, &leg_values(&conversion.tokens_out, &prices_store));
+
+        fills.fills.extend(adapter_fills(&base, "sell", &conversion.tokens_out, &no_proceeds));
+        fills.fills.extend(adapter_fills(&base, "buy", &conversion.tokens_in, &yes_costs));
+    }
+
+    for change in &events.position_changes {
+        let base = pnl::OrderFill {
+            id: change.id.clone(),
+            tx_hash: change.tx_hash.clone(),
+            log_index: change.log_index,
+            block_number: change.block_number,
+            timestamp: change.timestamp,
+            maker: adapter.clone(),
+            taker: change.stakeholder.clone(),
+            fee: "0".to_string(),
+            exchange: "neg_risk_adapter".to_string(),
+            ..Default::default()
+        };
+        let collateral = BigInt::from_str(&change.amount).unwrap_or_default();
+
+        if change.kind == "split" {
+            let costs = allocate_by_weight(&collateral, &leg_values(&change.tokens_in, &prices_store));
+            fills.fills.extend(adapter_fills(&base, "buy", &change.tokens_in, &costs));
+        } else {
+            let weights = if change.kind == "redemption" {
+                change
+                    .tokens_out
+                    .iter()
+                    .map(|leg| {
+                        let quantity = BigInt::from_str(&leg.amount).unwrap_or_default();
+                        market_value(&quantity, &payouts_store.get_last(&leg.token_id).unwrap_or_default())
+                    })
+                    .collect()
+            } else {
+                leg_values(&change.tokens_out, &prices_store)
+            };
+            let proceeds = allocate_by_weight(&collateral, &weights);
+            fills.fills.extend(adapter_fills(&base, "sell", &change.tokens_out, &proceeds));
+        }
+    }
+
+    Ok(fills)
+}
+
+/// Converts outcome tokens an FPMM pool sends to a funder into synthetic buys, so they carry a
+/// cost basis: tokens sent back on addFunding and tokens withdrawn on removeFunding, each at the
+/// pool's marginal price (the value already netted out of the deposit or credited to LP P&L).
+#[substreams::handlers::map]
+fn map_fpmm_fills(funding: pnl::FpmmFundingEvents) -> Result<pnl::OrderFills, substreams::errors::Error> {
+    let mut fills = pnl::OrderFills {
+        block_number: funding.block_number,
+        block_timestamp: funding.block_timestamp,
+        ..Default::default()
+    };
+
+    for event in &funding.events {
+        let amounts: Vec<BigInt> = event.amounts.iter().map(|a| BigInt::from_str(a).unwrap_or_default()).collect();
+        let costs = fpmm_leg_costs(&amounts, &event.tokens_received);
+        let (legs, costs): (Vec<pnl::TokenLeg>, Vec<BigInt>) = event
+            .tokens_received
+            .iter()
+            .cloned()
+            .zip(costs)
+            .filter(|(leg, _)| leg.amount != "0")
+            .unzip();
+
+        let base = pnl::OrderFill {
+            id: event.id.clone(),
+            tx_hash: event.tx_hash.clone(),
+            log_index: event.log_index,
+            block_number: event.block_number,
+            timestamp: event.timestamp,
+            maker: event.pool_address.clone(),
+            taker: event.funder.clone(),
+            fee: "0".to_string(),
+            exchange: "fpmm".to_string(),
+            ..Default::default()
+        };
+        fills.fills.extend(adapter_fills(&base, "buy", &legs, &costs));
+    }
+
+    Ok(fills)
+}
+
+/// Store user cost basis: key = {user}:{token_id}, value = total cost
+/// Fed by exchange fills and synthetic NegRisk adapter and FPMM funding fills.
+#[substreams::handlers::store]
+fn store_user_cost_basis(
+    fills: pnl::OrderFills,
+    adapter_fills: pnl::OrderFills,
+    fpmm_fills: pnl::OrderFills,
+    store: StoreAddBigInt,
+) {
+    for fill in fills.fills.into_iter().chain(adapter_fills.fills).chain(fpmm_fills.fills) {
+        let amount = BigInt::from_str(&fill.amount).unwrap_or_default();
+
+        if let Some((trader, side)) = fill_trader(&fill) {
+            let key = format!("{}:{}", trader, fill.token_id);
+            if side == "buy" {
+                store.add(0, &key, &amount);
+            } else {
+                let neg_amount = -amount;
+                store.add(0, &key, &neg_amount);
+            }
+        }
+    }
+}
+
+/// Store user buy cost: key = {user}:{token_id}, value = USDC paid on buys (6 decimals)
+/// Fed by exchange fills and synthetic NegRisk adapter and FPMM funding fills.
+#[substreams::handlers::store]
+fn store_user_buy_cost(
+    fills: pnl::OrderFills,
+    adapter_fills: pnl::OrderFills,
+    fpmm_fills: pnl::OrderFills,
+    store: StoreAddBigInt,
+) {
+    for fill in fills.fills.into_iter().chain(adapter_fills.fills).chain(fpmm_fills.fills) {
+        if let Some((trader, "buy")) = fill_trader(&fill) {
+            let key = format!("{}:{}", trader, fill.token_id);
+            store.add(0, &key, &BigInt::from_str(&fill.amount).unwrap_or_default());
+        }
+    }
+}
+
+/// Store user bought shares: key = {user}:{token_id}, value = shares bought (6 decimals)
+/// Together with store_user_buy_cost this gives the average entry price of a position.
+#[substreams::handlers::store]
+fn store_user_bought_shares(
+    fills: pnl::OrderFills,
+    adapter_fills: pnl::OrderFills,
+    fpmm_fills: pnl::OrderFills,
+    store: StoreAddBigInt,
+) {
+    for fill in fills.fills.into_iter().chain(adapter_fills.fills).chain(fpmm_fills.fills) {
+        if let Some((trader, "buy")) = fill_trader(&fill) {
+            let key = format!("{}:{}", trader, fill.token_id);
+            store.add(0, &key, &order_shares(&fill));
+        }
+    }
+}
+
+/// Store user realized P&L: key = {user}, value = realized P&L delta (18 decimals)
+/// Adds up the realizations of map_enriched_fills (exchange and NegRisk adapter fills).
+#[substreams::handlers::store]
+fn store_user_realized_pnl(enriched_fills: pnl::EnrichedFills, store: StoreAddBigInt) {
+    for (fill, pnl) in realized_fills(&enriched_fills) {
+        store.add(0, &fill.trader, &pnl);
+    }
+}
+
+/// Store position realized P&L: key = {user}:{token_id}, value = realized P&L delta (18 decimals)
+/// Adds up the realizations of map_enriched_fills, so it sums to store_user_realized_pnl per user.
+#[substreams::handlers::store]
+fn store_user_position_realized_pnl(enriched_fills: pnl::EnrichedFills, store: StoreAddBigInt) {
+    for (fill, pnl) in realized_fills(&enriched_fills) {
+        store.add(0, format!("{}:{}", fill.trader, fill.token_id), &pnl);
+    }
+}
+
+/// Enriched fills (exchange and synthetic) that realized P&L, with the P&L scaled to 18 decimals
+fn realized_fills(enriched_fills: &pnl::EnrichedFills) -> impl Iterator<Item = (&pnl::EnrichedFill, BigInt)> {
+    let scale = BigInt::from_str("1000000000000").unwrap(); // 10^12
+    enriched_fills
+        .fills
+        .iter()
+        .chain(&enriched_fills.synthetic_fills)
+        .map(move |fill| (fill, BigInt::from_str(&fill.realized_pnl).unwrap_or_default() * &scale))
+        .filter(|(_, pnl)| !pnl.is_zero())
+}
+
+/// Helper to parse price from "0.XXXXXXXXXXXXXXXXXX" format (18 decimals) back to BigInt (scaled by 10^18)
+fn parse_price_decimal(price_str: &str) -> BigInt {
+    let cleaned = price_str.trim_start_matches('0').trim_start_matches('.');
+    let padded = format!("{:0>18}", cleaned);
+    BigInt::from_str(&padded).unwrap_or_default()
+}
+
+/// Helper to format BigInt (18 decimals) to USDC string (6 decimals)
+fn format_usdc_from_bigint(value: &BigInt) -> String {
+    // Convert from 18 decimals to 6 decimals (USDC)
+    let scale = BigInt::from_str("1000000000000").unwrap(); // 10^12
+    let scaled = value / &scale;
+    scaled.to_string()
+}
+
+/// Store user volume: key = {user}, value = volume delta
+#[substreams::handlers::store]
+fn store_user_volume(fills: pnl::OrderFills, store: StoreAddBigInt) {
+    for fill in fills.fills {
+        let amount = BigInt::from_str(&fill.amount).unwrap_or_default();
+
+        // Track volume for both maker and taker
+        if !is_excluded_address(&fill.maker) {
+            store.add(0, &fill.maker.to_lowercase(), &amount);
+        }
+        if !is_excluded_address(&fill.taker) {
+            store.add(0, &fill.taker.to_lowercase(), &amount);
+        }
+    }
+}
+
+/// Store user USDC spent: key = {user}, value = USDC paid on buys as maker or taker (6 decimals)
+#[substreams::handlers::store]
+fn store_user_usdc_spent(fills: pnl::OrderFills, store: StoreAddBigInt) {
+    for fill in fills.fills {
+        let (buyer, _) = fill_counterparties(&fill);
+        if !is_excluded_address(&buyer) {
+            let amount = BigInt::from_str(&fill.amount).unwrap_or_default();
+            store.add(0, &buyer, &amount);
+        }
+    }
+}
+
+/// Store user net investment: key = {user}, value = USDC paid on buys - USDC received on sells (6 decimals)
+#[substreams::handlers::store]
+fn store_user_net_investment(fills: pnl::OrderFills, store: StoreAddBigInt) {
+    for fill in fills.fills {
+        let (buyer, seller) = fill_counterparties(&fill);
+        let amount = BigInt::from_str(&fill.amount).unwrap_or_default();
+        if !is_excluded_address(&buyer) {
+            store.add(0, &buyer, &amount);
+        }
+        if !is_excluded_address(&seller) {
+            store.add(0, &seller, &(BigInt::from(0) - &amount));
+        }
+    }
+}
+
+/// Store user capital deployed: key = {user}, value = peak net investment (USDC, 6 decimals)
+#[substreams::handlers::store]
+fn store_user_capital_deployed(net_investment_deltas: Deltas<DeltaBigInt>, store: StoreMaxBigInt) {
+    for delta in net_investment_deltas.deltas {
+        store.max(0, &delta.key, &delta.new_value);
+    }
+}
+
+/// Store user trade count: key = {user}, value = count delta
+#[substreams::handlers::store]
+fn store_user_trade_count(fills: pnl::OrderFills, store: StoreAddInt64) {
+    for fill in fills.fills {
+        if !is_excluded_address(&fill.maker) {
+            store.add(0, &fill.maker.to_lowercase(), 1);
+        }
+        if !is_excluded_address(&fill.taker) {
+            store.add(0, &fill.taker.to_lowercase(), 1);
+        }
+    }
+}
+
+/// Store proxy owners: key = {proxy}, value = owner address
+#[substreams::handlers::store]
+fn store_proxy_owners(wallets: pnl::ProxyWallets, store: StoreSetIfNotExistsString) {
+    for wallet in wallets.wallets {
+        store.set_if_not_exists(0, &wallet.proxy, &wallet.owner);
+    }
+}
+
+/// Buyer and seller of a fill (fill.side is the taker's side)
+fn fill_counterparties(fill: &pnl::OrderFill) -> (String, String) {
+    let (maker, taker) = (fill.maker.to_lowercase(), fill.taker.to_lowercase());
+    if fill.side == "buy" {
+        (taker, maker)
+    } else {
+        (maker, taker)
+    }
+}
+
+/// Store last sales: key = {seller}:{token_id}, value = LastSale proto (buyer, block)
+#[substreams::handlers::store]
+fn store_last_sales(fills: pnl::OrderFills, store: StoreSetProto<pnl::LastSale>) {
+    for fill in fills.fills {
+        let (buyer, seller) = fill_counterparties(&fill);
+        if is_excluded_address(&buyer) || is_excluded_address(&seller) {
+            continue;
+        }
+
+        let sale = pnl::LastSale {
+            buyer,
+            block_number: fill.block_number,
+        };
+        store.set(0, format!("{}:{}", seller, fill.token_id), &sale);
+    }
+}
+
+/// Store user clean volume: key = {user}, value = volume excluding wash-flagged fills
+#[substreams::handlers::store]
+fn store_user_clean_volume(fills: pnl::OrderFills, wash_trades: pnl::WashTrades, store: StoreAddBigInt) {
+    let flagged = wash_trade_ids(&wash_trades);
+    for fill in fills.fills.iter().filter(|f| !flagged.contains(f.id.as_str())) {
+        let amount = BigInt::from_str(&fill.amount).unwrap_or_default();
+        for user in [&fill.maker, &fill.taker] {
+            if !is_excluded_address(user) {
+                store.add(0, user.to_lowercase(), &amount);
+            }
+        }
+    }
+}
+
+/// Store market clean volume: key = {token_id}, value = volume excluding wash-flagged fills
+#[substreams::handlers::store]
+fn store_market_clean_volume(fills: pnl::OrderFills, wash_trades: pnl::WashTrades, store: StoreAddBigInt) {
+    let flagged = wash_trade_ids(&wash_trades);
+    for fill in fills.fills.iter().filter(|f| !flagged.contains(f.id.as_str())) {
+        let amount = BigInt::from_str(&fill.amount).unwrap_or_default();
+        store.add(0, &fill.token_id, &amount);
+    }
+}
+
+/// Store market volume: key = {token_id}, value = volume
+#[substreams::handlers::store]
+fn store_market_volume(fills: pnl::OrderFills, store: StoreAddBigInt) {
+    for fill in fills.fills {
+        let amount = BigInt::from_str(&fill.amount).unwrap_or_default();
+        store.add(0, &fill.token_id, &amount);
+    }
+}
+
+/// Split a store_user_positions delta into (token_id, old long quantity, new long quantity)
+fn holder_change(delta: &DeltaBigInt) -> Option<(&str, BigInt, BigInt)> {
+    let (_, token_id) = delta.key.split_once(':')?;
+    Some((token_id, long_quantity(&delta.old_value), long_quantity(&delta.new_value)))
+}
+
+/// Store market holder count: key = {token_id}, value = addresses with a positive position
+#[substreams::handlers::store]
+fn store_market_holder_count(positions_deltas: Deltas<DeltaBigInt>, store: StoreAddInt64) {
+    let zero = BigInt::from(0);
+    for delta in &positions_deltas.deltas {
+        if let Some((token_id, old, new)) = holder_change(delta) {
+            match (old > zero, new > zero) {
+                (false, true) => store.add(delta.ordinal, token_id, 1),
+                (true, false) => store.add(delta.ordinal, token_id, -1),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Store market held shares: key = {token_id}, value = sum of positive positions
+#[substreams::handlers::store]
+fn store_market_holder_shares(positions_deltas: Deltas<DeltaBigInt>, store: StoreAddBigInt) {
+    for delta in &positions_deltas.deltas {
+        if let Some((token_id, old, new)) = holder_change(delta) {
+            store.add(delta.ordinal, token_id, &(new - old));
+        }
+    }
+}
+
+/// Store market holder squares: key = {token_id}, value = sum of squared positive positions (for HHI)
+#[substreams::handlers::store]
+fn store_market_holder_squares(positions_deltas: Deltas<DeltaBigInt>, store: StoreAddBigInt) {
+    for delta in &positions_deltas.deltas {
+        if let Some((token_id, old, new)) = holder_change(delta) {
+            store.add(delta.ordinal, token_id, &(&new * &new - &old * &old));
+        }
+    }
+}
+
+/// Store latest prices: key = {token_id}, value = TokenPrice proto
+#[substreams::handlers::store]
+fn store_latest_prices(fills: pnl::OrderFills, store: StoreSetProto<pnl::TokenPrice>) {
+    for fill in fills.fills {
+        let price = pnl::TokenPrice {
+            token_id: fill.token_id.clone(),
+            price: fill.price.clone(),
+            block_number: fill.block_number,
+            timestamp: fill.timestamp,
+            volume_24h: String::new(),
+        };
+        store.set(0, &fill.token_id, &price);
+    }
+}
+
+/// Store LP share balances: key = {user}:{pool}, value = shares delta
+#[substreams::handlers::store]
+fn store_lp_shares(funding: pnl::FpmmFundingEvents, store: StoreAddBigInt) {
+    for event in funding.events {
+        let key = format!("{}:{}", event.funder.to_lowercase(), event.pool_address.to_lowercase());
+        let shares = BigInt::from_str(&event.shares).unwrap_or_default();
+
+        if event.kind == "added" {
+            store.add(0, &key, &shares);
+        } else {
+            store.add(0, &key, &-shares);
+        }
+    }
+}
+
+/// Store gross LP deposits: key = {user}:{pool}, value = USDC deposited
+#[substreams::handlers::store]
+fn store_lp_deposits(funding: pnl::FpmmFundingEvents, store: StoreAddBigInt) {
+    for event in funding.events {
+        if event.kind == "added" {
+            let key = format!("{}:{}", event.funder.to_lowercase(), event.pool_address.to_lowercase());
+            let deposited = BigInt::from_str(&event.usdc_deposited).unwrap_or_default();
+            store.add(0, &key, &deposited);
+        }
+    }
+}
+
+/// The maker order's side: a fill where the taker sells is a maker buy, and vice versa
+fn order_side(fill: &pnl::OrderFill) -> &'static str {
+    if fill.side == "sell" {
+        "buy"
+    } else {
+        "sell"
+    }
+}
+
+/// The user a fill trades for, and that user's side (None for protocol contracts).
+/// Exchange fills belong to the filled order's owner, the maker, on the order's side: in MINT and
+/// MERGE matches the taker's leg is a different token, carried by its own fill (taker = exchange).
+/// Synthetic NegRisk adapter and FPMM fills belong to the stakeholder, the taker.
+fn fill_trader(fill: &pnl::OrderFill) -> Option<(String, &str)> {
+    let (trader, side) = if matches!(fill.exchange.as_str(), "ctf" | "neg_risk") {
+        (&fill.maker, order_side(fill))
+    } else {
+        (&fill.taker, fill.side.as_str())
+    };
+    (!is_excluded_address(trader)).then(|| (trader.to_lowercase(), side))
+}
+
+/// Outcome tokens exchanged in a fill (the non-USDC leg)
+fn order_shares(fill: &pnl::OrderFill) -> BigInt {
+    let shares = if fill.maker_asset_id == "0" {
+        &fill.taker_amount_filled
+    } else {
+        &fill.maker_amount_filled
+    };
+    BigInt::from_str(shares).unwrap_or_default()
+}
+
+/// Store orders: key = {order_hash}, value = Order proto (static fields from the first fill)
+#[substreams::handlers::store]
+fn store_orders(fills: pnl::OrderFills, store: StoreSetIfNotExistsProto<pnl::Order>) {
+    for fill in fills.fills {
+        if fill.order_hash.is_empty() {
+            continue;
+        }
+        let order = pnl::Order {
+            order_hash: fill.order_hash.clone(),
+            maker: fill.maker.to_lowercase(),
+            token_id: fill.token_id.clone(),
+            side: order_side(&fill).to_string(),
+            exchange: fill.exchange.clone(),
+            size: fill.order_size.clone(),
+            first_fill_block: fill.block_number,
+            ..Default::default()
+        };
+        store.set_if_not_exists(0, &fill.order_hash, &order);
+    }
+}
+
+/// Store order filled shares: key = {order_hash}, value = outcome tokens filled
+#[substreams::handlers::store]
+fn store_order_shares(fills: pnl::OrderFills, store: StoreAddBigInt) {
+    for fill in fills.fills {
+        if !fill.order_hash.is_empty() {
+            store.add(0, &fill.order_hash, &order_shares(&fill));
+        }
+    }
+}
+
+/// Store order filled USDC: key = {order_hash}, value = USDC filled
+#[substreams::handlers::store]
+fn store_order_usdc(fills: pnl::OrderFills, store: StoreAddBigInt) {
+    for fill in fills.fills {
+        if !fill.order_hash.is_empty() {
+            let amount = BigInt::from_str(&fill.amount).unwrap_or_default();
+            store.add(0, &fill.order_hash, &amount);
+        }
+    }
+}
+
+/// Store order fill count: key = {order_hash}, value = number of fills
+#[substreams::handlers::store]
+fn store_order_fill_count(fills: pnl::OrderFills, store: StoreAddInt64) {
+    for fill in fills.fills {
+        if !fill.order_hash.is_empty() {
+            store.add(0, &fill.order_hash, 1);
+        }
+    }
+}
+
+/// Store exchange pause state: key = {exchange}, value = block trading was paused at (0 = trading)
+#[substreams::handlers::store]
+fn store_exchange_paused(events: pnl::ExchangeEvents, store: StoreSetInt64) {
+    for event in events.events {
+        match event.event_type.as_str() {
+            "trading_paused" => store.set(0, &event.exchange, &(event.block_number as i64)),
+            "trading_unpaused" => store.set(0, &event.exchange, &0),
+            _ => {}
+        }
+    }
+}
+
+/// Store gross LP shares minted: key = {user}:{pool}, value = shares minted
+#[substreams::handlers::store]
+fn store_lp_shares_minted(funding: pnl::FpmmFundingEvents, store: StoreAddBigInt) {
+    for event in funding.events {
+        if event.kind == "added" {
+            let key = format!("{}:{}", event.funder.to_lowercase(), event.pool_address.to_lowercase());
+            let shares = BigInt::from_str(&event.shares).unwrap_or_default();
+            store.add(0, &key, &shares);
+        }
+    }
+}
+
+/// Store NegRisk conditions: key = {condition_id}, value = NegRisk market id
+/// The adapter prepares every NegRisk condition, so it is the condition's oracle.
+#[substreams::handlers::store]
+fn store_neg_risk_conditions(registry: pnl::MarketRegistry, store: StoreSetString) {
+    let adapter = format_address(&NEG_RISK_ADAPTER);
+    for condition in registry.conditions {
+        if condition.oracle.eq_ignore_ascii_case(&adapter) {
+            let question_bytes = Hex::decode(condition.question_id.trim_start_matches("0x")).unwrap_or_default();
+            store.set(0, &condition.condition_id, &neg_risk_market_id(&question_bytes));
+        }
+    }
+}
+
+/// Store market tokens: key = {token_id}, value = MarketToken proto (condition, complement, YES/NO)
+/// YES / NO come from the CTF position ids (see registration_outcomes).
+#[substreams::handlers::store]
+fn store_market_tokens(registry: pnl::MarketRegistry, store: StoreSetIfNotExistsProto<pnl::MarketToken>) {
+    for registration in registry.tokens {
+        let (yes_token_id, no_token_id) = registration_outcomes(&registration);
+        for (token_id, complement_token_id, is_yes) in [
+            (&yes_token_id, &no_token_id, true),
+            (&no_token_id, &yes_token_id, false),
+        ] {
+            let token = pnl::MarketToken {
+                token_id: token_id.clone(),
+                complement_token_id: complement_token_id.clone(),
+                condition_id: registration.condition_id.clone(),
+                is_yes,
+            };
+            store.set_if_not_exists(0, token_id, &token);
+        }
+    }
+}
+
+/// Store condition tokens: key = {condition_id}, value = MarketToken proto of the YES token
+#[substreams::handlers::store]
+fn store_condition_tokens(registry: pnl::MarketRegistry, store: StoreSetIfNotExistsProto<pnl::MarketToken>) {
+    for registration in registry.tokens {
+        let (yes_token_id, no_token_id) = registration_outcomes(&registration);
+        let token = pnl::MarketToken {
+            token_id: yes_token_id,
+            complement_token_id: no_token_id,
+            condition_id: registration.condition_id.clone(),
+            is_yes: true,
+        };
+        store.set_if_not_exists(0, &registration.condition_id, &token);
+    }
+}
+
+/// Store token payouts: key = {token_id}, value = payout per share of a resolved condition (0-1, 18 decimals)
+#[substreams::handlers::store]
+fn store_token_payouts(resolutions: pnl::ConditionResolutions, store: StoreSetString) {
+    for resolution in resolutions.resolutions {
+        if !resolution.yes_token_id.is_empty() {
+            store.set(0, &resolution.yes_token_id, &resolution.yes_payout);
+        }
+        if !resolution.no_token_id.is_empty() {
+            store.set(0, &resolution.no_token_id, &resolution.no_payout);
+        }
+    }
+}
+
+/// Store NegRisk tokens: key = {token_id}, value = NegRiskToken proto
+/// YES / NO come from the CTF position ids (see registration_outcomes).
+#[substreams::handlers::store]
+fn store_neg_risk_tokens(
+    registry: pnl::MarketRegistry,
+    conditions_store: StoreGetString,
+    store: StoreSetIfNotExistsProto<pnl::NegRiskToken>,
+) {
+    for registration in registry.tokens {
+        if let Some(market_id) = conditions_store.get_last(&registration.condition_id) {
+            let (yes_token_id, no_token_id) = registration_outcomes(&registration);
+            for (token_id, is_yes) in [(&yes_token_id, true), (&no_token_id, false)] {
+                let token = pnl::NegRiskToken {
+                    token_id: token_id.clone(),
+                    market_id: market_id.clone(),
+                    condition_id: registration.condition_id.clone(),
+                    is_yes,
+                };
+                store.set_if_not_exists(0, token_id, &token);
+            }
+        }
+    }
+}
+
+/// Store NegRisk question count: key = {market_id}, value = questions prepared in the event
+#[substreams::handlers::store]
+fn store_neg_risk_question_count(events: pnl::NegRiskEvents, store: StoreAddInt64) {
+    for question in events.questions {
+        store.add(0, &question.market_id, 1);
+    }
+}
+
+/// Store known users: key = {user}, value = block first seen trading or as a Safe proxy
+/// Cash flows are only tracked for these addresses.
+#[substreams::handlers::store]
+fn store_known_users(fills: pnl::OrderFills, wallets: pnl::ProxyWallets, store: StoreSetIfNotExistsInt64) {
+    for fill in &fills.fills {
+        for user in [&fill.maker, &fill.taker] {
+            if !is_excluded_address(user) {
+                store.set_if_not_exists(0, user.to_lowercase(), &(fill.block_number as i64));
+            }
+        }
+    }
+    for wallet in wallets.wallets {
+        store.set_if_not_exists(0, &wallet.proxy, &(wallet.block_number as i64));
+    }
+}
+
+/// Store user cash balance: key = {user}, value = USDC held (6 decimals) since first seen
+#[substreams::handlers::store]
+fn store_user_cash_balance(cash_flows: pnl::UserCashFlows, store: StoreAddBigInt) {
+    for flow in cash_flows.flows {
+        let amount = BigInt::from_str(&flow.amount).unwrap_or_default();
+        store.add(0, &flow.user_address, &amount);
+    }
+}
+
+/// Store user net deposits: key = {user}, value = deposits - withdrawals since first seen (USDC, 6 decimals)
+#[substreams::handlers::store]
+fn store_user_net_deposits(cash_flows: pnl::UserCashFlows, store: StoreAddBigInt) {
+    for flow in cash_flows.flows {
+        if matches!(flow.flow_type.as_str(), "deposit" | "withdrawal") {
+            let amount = BigInt::from_str(&flow.amount).unwrap_or_default();
+            store.add(0, &flow.user_address, &amount);
+        }
+    }
+}
+
+/// Store position values: key = {user}:{token_id}, value = long quantity at the latest price (USDC, 6 decimals).
+/// A position is re-marked whenever its quantity changes.
+#[substreams::handlers::store]
+fn store_position_values(
+    positions_deltas: Deltas<DeltaBigInt>,
+    prices_store: StoreGetProto<pnl::TokenPrice>,
+    store: StoreSetBigInt,
+) {
+    for delta in positions_deltas.deltas {
+        if let Some((_, token_id)) = delta.key.split_once(':') {
+            let price = prices_store.get_last(token_id).map(|p| p.price).unwrap_or_default();
+            store.set(0, &delta.key, &market_value(&long_quantity(&delta.new_value), &price));
+        }
+    }
+}
+
+/// Store user position value: key = {user}, value = sum of marked position values (USDC, 6 decimals)
+#[substreams::handlers::store]
+fn store_user_position_value(position_value_deltas: Deltas<DeltaBigInt>, store: StoreAddBigInt) {
+    for delta in position_value_deltas.deltas {
+        if let Some((user, _)) = delta.key.split_once(':') {
+            store.add(0, user, &(delta.new_value - delta.old_value));
+        }
+    }
+}
+
+/// Store user log return: key = {user}, value = sum of ln(1 + r) over blocks (time-weighted return = e^x - 1)
+#[substreams::handlers::store]
+fn store_user_log_return(navs: pnl::UserNavs, store: StoreAddFloat64) {
+    for nav in navs.navs {
+        if nav.log_return != 0.0 {
+            store.add(0, &nav.user_address, nav.log_return);
+        }
+    }
+}
+
+/// Store user maker fills: key = {user}, value = fills on the maker side
+/// A fill whose taker is an exchange is the taker order's own fill; its maker took liquidity.
+#[substreams::handlers::store]
+fn store_user_maker_fills(fills: pnl::OrderFills, store: StoreAddInt64) {
+    for fill in fills.fills {
+        if !is_excluded_address(&fill.maker) && !is_exchange_address(&fill.taker) {
+            store.add(0, fill.maker.to_lowercase(), 1);
+        }
+    }
+}
+
+/// Store user NegRisk volume: key = {user}, value = volume on the NegRisk exchange (USDC, 6 decimals)
+#[substreams::handlers::store]
+fn store_user_neg_risk_volume(fills: pnl::OrderFills, store: StoreAddBigInt) {
+    for fill in fills.fills {
+        if fill.exchange != "neg_risk" {
+            continue;
+        }
+        let amount = BigInt::from_str(&fill.amount).unwrap_or_default();
+        for user in [&fill.maker, &fill.taker] {
+            if !is_excluded_address(user) {
+                store.add(0, user.to_lowercase(), &amount);
+            }
+        }
+    }
+}
+
+/// Store user first trade: key = {user}, value = unix timestamp of the first fill
+#[substreams::handlers::store]
+fn store_user_first_trade_at(fills: pnl::OrderFills, store: StoreSetIfNotExistsInt64) {
+    for fill in fills.fills {
+        let Some(timestamp) = fill.timestamp.as_ref() else {
+            continue;
+        };
+        for user in [&fill.maker, &fill.taker] {
+            if !is_excluded_address(user) {
+                store.set_if_not_exists(0, user.to_lowercase(), &timestamp.seconds);
+            }
+        }
+    }
+}
+
+/// Store user closed positions: key = {user}, value = holding episodes closed
+#[substreams::handlers::store]
+fn store_user_closed_positions(lifecycles: pnl::PositionLifecycles, store: StoreAddInt64) {
+    for lifecycle in lifecycles.lifecycles {
+        if lifecycle.closed_block != 0 {
+            store.add(0, &lifecycle.user_address, 1);
+        }
+    }
+}
+
+/// Store user holding time: key = {user}, value = total seconds held across closed episodes
+#[substreams::handlers::store]
+fn store_user_holding_seconds(lifecycles: pnl::PositionLifecycles, store: StoreAddInt64) {
+    for lifecycle in lifecycles.lifecycles {
+        if lifecycle.closed_block != 0 {
+            store.add(0, &lifecycle.user_address, lifecycle.holding_seconds);
+        }
+    }
+}
+
+/// Store user arbitrage count: key = {user}, value = complete-set arbitrage trades
+#[substreams::handlers::store]
+fn store_user_arbitrage_count(arbitrage: pnl::ArbitrageTrades, store: StoreAddInt64) {
+    for trade in arbitrage.trades {
+        store.add(0, &trade.user_address, 1);
+    }
+}
+
+/// Store user exits: key = {user}, value = fills that reduced, closed or flipped a position
+#[substreams::handlers::store]
+fn store_user_exits(enriched_fills: pnl::EnrichedFills, store: StoreAddInt64) {
+    for fill in enriched_fills.fills {
+        if matches!(fill.position_action.as_str(), "reduce" | "close" | "flip") {
+            store.add(0, &fill.trader, 1);
+        }
+    }
+}
+
+/// Store user winning exits: key = {user}, value = exits that realized a profit
+#[substreams::handlers::store]
+fn store_user_winning_exits(enriched_fills: pnl::EnrichedFills, store: StoreAddInt64) {
+    let zero = BigInt::from(0);
+    for fill in enriched_fills.fills {
+        let exit = matches!(fill.position_action.as_str(), "reduce" | "close" | "flip");
+        if exit && BigInt::from_str(&fill.realized_pnl).unwrap_or_default() > zero {
+            store.add(0, &fill.trader, 1);
+        }
+    }
+}
+
+/// Store smart money flow: key = {5m|1h}:{bucket_start}:{token_id}, value = win-rate weighted
+/// buys - sells in the bucket (USDC, 6 decimals). Buckets older than 24h are deleted.
+#[substreams::handlers::store]
+fn store_smart_flow(
+    fills: pnl::OrderFills,
+    exits_store: StoreGetInt64,
+    winning_exits_store: StoreGetInt64,
+    store: StoreAddBigInt,
+) {
+    if let Some(now) = fills.block_timestamp.as_ref() {
+        delete_expired_flow_buckets(&store, now.seconds);
+    }
+
+    for fill in fills.fills {
+        let Some(timestamp) = fill.timestamp.as_ref() else {
+            continue;
+        };
+        let amount = BigInt::from_str(&fill.amount).unwrap_or_default();
+        let (buyer, seller) = fill_counterparties(&fill);
+        let weight = |user: &str| {
+            smart_money_weight(
+                &amount,
+                exits_store.get_last(user).unwrap_or(0),
+                winning_exits_store.get_last(user).unwrap_or(0),
+            )
+        };
+        let flow = weight(&buyer) - weight(&seller);
+        if flow.is_zero() {
+            continue;
+        }
+
+        add_to_flow_buckets(&store, timestamp.seconds, &fill.token_id, &flow);
+    }
+}
+
+/// Key ({buyer}:{condition_id}) and USDC paid of a buy of either outcome of `token`'s condition.
+/// None for protocol buyers and for taker orders' own fills (taker = exchange), which repeat the
+/// maker fills where the same user is the taker.
+fn market_spent_entry(fill: &pnl::OrderFill, token: &pnl::MarketToken) -> Option<(String, BigInt)> {
+    let (buyer, _) = fill_counterparties(fill);
+    if is_excluded_address(&buyer) || is_exchange_address(&fill.taker) {
+        return None;
+    }
+    let amount = BigInt::from_str(&fill.amount).unwrap_or_default();
+    Some((format!("{}:{}", buyer, token.condition_id), amount))
+}
+
+/// Store user market spent: key = {user}:{condition_id}, value = USDC paid on buys of either outcome (6 decimals)
+#[substreams::handlers::store]
+fn store_user_market_spent(
+    fills: pnl::OrderFills,
+    market_tokens_store: StoreGetProto<pnl::MarketToken>,
+    store: StoreAddBigInt,
+) {
+    for fill in fills.fills {
+        let Some(token) = market_tokens_store.get_last(&fill.token_id) else {
+            continue;
+        };
+        if let Some((key, amount)) = market_spent_entry(&fill, &token) {
+            store.add(0, key, &amount);
+        }
+    }
+}
+
+/// Store price checkpoints: key = {block_number / PRICE_CHECKPOINT_BLOCKS}:{token_id}, value = last price in the span
+/// Spans older than PRICE_CHECKPOINT_RETENTION are deleted (with the one before, in case no fills came during a span).
+#[substreams::handlers::store]
+fn store_price_checkpoints(fills: pnl::OrderFills, store: StoreSetString) {
+    if fills.fills.is_empty() {
+        return;
+    }
+    if let Some(expired) = (fills.block_number / PRICE_CHECKPOINT_BLOCKS).checked_sub(PRICE_CHECKPOINT_RETENTION) {
+        for span in [Some(expired), expired.checked_sub(1)].into_iter().flatten() {
+            store.delete_prefix(0, &format!("{}:", span));
+        }
+    }
+
+    for fill in fills.fills {
+        let key = format!("{}:{}", fill.block_number / PRICE_CHECKPOINT_BLOCKS, fill.token_id);
+        store.set(0, &key, &fill.price);
+    }
+}
+
+/// Store impact price flow: key = {5m|1h}:{bucket_start}:{token_id}, value = sum of price change x signed flow
+/// Buckets older than 24h are deleted.
+#[substreams::handlers::store]
+fn store_impact_price_flow(fills: pnl::OrderFills, price_deltas: Deltas<DeltaProto<pnl::TokenPrice>>, store: StoreAddBigInt) {
+    if let Some(now) = fills.block_timestamp.as_ref() {
+        delete_expired_flow_buckets(&store, now.seconds);
+    }
+    for (fill, _, change) in fill_price_changes(&fills.fills, &price_deltas) {
+        let Some(timestamp) = fill.timestamp.as_ref() else {
+            continue;
+        };
+        let price_flow = change * signed_flow(fill);
+        add_to_flow_buckets(&store, timestamp.seconds, &fill.token_id, &price_flow);
+    }
+}
+
+/// Store impact flow squared: key = {5m|1h}:{bucket_start}:{token_id}, value = sum of signed flow squared
+/// Buckets older than 24h are deleted.
+#[substreams::handlers::store]
+fn store_impact_flow_squared(fills: pnl::OrderFills, price_deltas: Deltas<DeltaProto<pnl::TokenPrice>>, store: StoreAddBigInt) {
+    if let Some(now) = fills.block_timestamp.as_ref() {
+        delete_expired_flow_buckets(&store, now.seconds);
+    }
+    for (fill, _, _) in fill_price_changes(&fills.fills, &price_deltas) {
+        let Some(timestamp) = fill.timestamp.as_ref() else {
+            continue;
+        };
+        let flow = signed_flow(fill);
+        let flow_squared = &flow * &flow;
+        add_to_flow_buckets(&store, timestamp.seconds, &fill.token_id, &flow_squared);
+    }
+}
+
+/// Store user arbitrage profit: key = {user}, value = locked-in arbitrage profit (USDC, 6 decimals)
+#[substreams::handlers::store]
+fn store_user_arbitrage_profit(arbitrage: pnl::ArbitrageTrades, store: StoreAddBigInt) {
+    for trade in arbitrage.trades {
+        let profit = BigInt::from_str(&trade.profit).unwrap_or_default();
+        store.add(0, &trade.user_address, &profit);
+    }
+}
+
+/// Implied YES price (18 decimals) from a fill on either outcome of a binary question
+fn implied_yes_price(price: &str, is_yes: bool) -> BigInt {
+    let price = parse_price_decimal(price);
+    if is_yes {
+        price
+    } else {
+        BigInt::from_str("1000000000000000000").unwrap() - price
+    }
+}
+
+/// Store NegRisk YES prices: key = {market_id}:{condition_id}, value = latest implied YES price
+#[substreams::handlers::store]
+fn store_neg_risk_yes_prices(
+    fills: pnl::OrderFills,
+    tokens_store: StoreGetProto<pnl::NegRiskToken>,
+    store: StoreSetBigInt,
+) {
+    for fill in fills.fills {
+        if let Some(token) = tokens_store.get_last(&fill.token_id) {
+            let key = format!("{}:{}", token.market_id, token.condition_id);
+            store.set(0, &key, &implied_yes_price(&fill.price, token.is_yes));
+        }
+    }
+}
+
+/// Store NegRisk YES price sums: key = {market_id}, value = sum of YES prices across questions
+#[substreams::handlers::store]
+fn store_neg_risk_price_sum(yes_price_deltas: Deltas<DeltaBigInt>, store: StoreAddBigInt) {
+    for delta in yes_price_deltas.deltas {
+        if let Some(market_id) = delta.key.split(':').next() {
+            let change = &delta.new_value - &delta.old_value;
+            store.add(0, market_id, &change);
+        }
+    }
+}
+
+/// Store NegRisk event volume: key = {market_id}, value = volume across all outcomes
+#[substreams::handlers::store]
+fn store_neg_risk_volume(
+    fills: pnl::OrderFills,
+    tokens_store: StoreGetProto<pnl::NegRiskToken>,
+    store: StoreAddBigInt,
+) {
+    for fill in fills.fills {
+        if let Some(token) = tokens_store.get_last(&fill.token_id) {
+            let amount = BigInt::from_str(&fill.amount).unwrap_or_default();
+            store.add(0, &token.market_id, &amount);
+        }
+    }
+}
+
+/// Long side of a position quantity (short or empty positions hold nothing)
+fn long_quantity(quantity: &BigInt) -> BigInt {
+    if quantity > &BigInt::from(0) {
+        quantity.clone()
+    } else {
+        BigInt::from(0)
+    }
+}
+
+/// Store NegRisk open interest: key = {market_id}, value = YES shares held across questions
+#[substreams::handlers::store]
+fn store_neg_risk_open_interest(
+    positions_deltas: Deltas<DeltaBigInt>,
+    tokens_store: StoreGetProto<pnl::NegRiskToken>,
+    store: StoreAddBigInt,
+) {
+    for delta in positions_deltas.deltas {
+        let parts: Vec<&str> = delta.key.split(':').collect();
+        if parts.len() != 2 {
+            continue;
+        }
+        if let Some(token) = tokens_store.get_last(parts[1]) {
+            if token.is_yes {
+                let change = long_quantity(&delta.new_value) - long_quantity(&delta.old_value);
+                store.add(0, &token.market_id, &change);
+            }
+        }
+    }
+}
+
+/// Store NegRisk user P&L: key = {market_id}:{user}, value = realized P&L delta (18 decimals)
+/// Adds up the realizations of map_enriched_fills on the event's outcome tokens.
+#[substreams::handlers::store]
+fn store_neg_risk_user_pnl(
+    enriched_fills: pnl::EnrichedFills,
+    tokens_store: StoreGetProto<pnl::NegRiskToken>,
+    store: StoreAddBigInt,
+) {
+    for (fill, pnl) in realized_fills(&enriched_fills) {
+        if let Some(token) = tokens_store.get_last(&fill.token_id) {
+            store.add(0, format!("{}:{}", token.market_id, fill.trader), &pnl);
+        }
+    }
+}
+
+//==============================================
+// LAYER 3: Analytics
+//==============================================
+
+/// Signed ratio of two amounts in the same unit, as an 18-decimal string ("0" when the denominator is not positive)
+fn ratio_decimal(numerator: &BigInt, denominator: &BigInt) -> String {
+    if denominator <= &BigInt::from(0) {
+        return "0".to_string();
+    }
+    let one = BigInt::from_str("1000000000000000000").unwrap(); // 10^18
+    format_signed_price(&(numerator * &one / denominator))
+}
+
+/// Calculate total fees paid by a user from order fills
+fn calculate_user_fees(fills: &pnl::OrderFills, user: &str) -> BigInt {
+    let mut total_fees = BigInt::from(0);
+    for fill in &fills.fills {
+        if fill.taker.eq_ignore_ascii_case(user) || fill.maker.eq_ignore_ascii_case(user) {
+            let fee = BigInt::from_str(&fill.fee).unwrap_or_default();
+            total_fees = total_fees + fee;
+        }
+    }
+    total_fees
+}
+
+/// Compute user P&L updates
+#[substreams::handlers::map]
+fn map_user_pnl(
+    fills: pnl::OrderFills,
+    positions_deltas: Deltas<DeltaBigInt>,
+    positions_store: StoreGetBigInt,
+    cost_basis_store: StoreGetBigInt,
+    realized_pnl_store: StoreGetBigInt,
+    prices_store: StoreGetProto<pnl::TokenPrice>,
+    volume_store: StoreGetBigInt,
+    trade_count_store: StoreGetInt64,
+    lp_pnl_deltas: Deltas<DeltaBigInt>,
+    lp_pnl_store: StoreGetBigInt,
+    usdc_spent_store: StoreGetBigInt,
+    capital_deployed_store: StoreGetBigInt,
+) -> Result<pnl::UserPnLUpdates, substreams::errors::Error> {
+    let mut updates = pnl::UserPnLUpdates {
+        block_number: fills.block_number,
+        ..Default::default()
+    };
+
+    // Track users with position changes
+    let mut affected_users: std::collections::HashSet<String> = std::collections::HashSet::new();
+
+    for delta in &positions_deltas.deltas {
+        let parts: Vec<&str> = delta.key.split(':').collect();
+        if parts.len() == 2 {
+            affected_users.insert(parts[0].to_string());
+        }
+    }
+
+    // Also track users from fills
+    for fill in &fills.fills {
+        if !is_excluded_address(&fill.taker) {
+            affected_users.insert(fill.taker.to_lowercase());
+        }
+        if !is_excluded_address(&fill.maker) {
+            affected_users.insert(fill.maker.to_lowercase());
+        }
+    }
+
+    // Also track liquidity providers whose LP P&L changed
+    for delta in &lp_pnl_deltas.deltas {
+        affected_users.insert(delta.key.clone());
+    }
+
+    // Generate updates for affected users
+    for user in affected_users {
+        let realized = realized_pnl_store
+            .get_last(&user)
+            .map(|v| v.to_string())
+            .unwrap_or_else(|| "0".to_string());
+
+        // Calculate unrealized P&L: sum((current_price - avg_entry_price) * quantity)
+        // for all open positions. We iterate through the positions_deltas to know which
+        // positions exist for this user, then use positions_store to get current values.
+        let mut unrealized_pnl_total = BigInt::from(0);
+
+        for delta in &positions_deltas.deltas {
+            let parts: Vec<&str> = delta.key.split(':').collect();
+            if parts.len() == 2 && parts[0] == user {
+                let token_id = parts[1];
+
+                // Get current position quantity from positions_store
+                let quantity = positions_store
+                    .get_last(&delta.key)
+                    .unwrap_or_else(|| BigInt::from(0));
+
+                // Only calculate unrealized P&L for open positions (quantity > 0)
+                if quantity > BigInt::from(0) {
+                    // Get cost basis for this position
+                    let cost_basis = cost_basis_store
+                        .get_last(&delta.key)
+                        .unwrap_or_else(|| BigInt::from(0));
+
+                    // Calculate average entry price
+                    let avg_entry_price = if !quantity.is_zero() {
+                        &cost_basis / &quantity
+                    } else {
+                        BigInt::from(0)
+                    };
+
+                    // Get current price from prices_store
+                    if let Some(token_price) = prices_store.get_last(token_id) {
+                        let current_price = parse_price_decimal(&token_price.price);
+
+                        // Calculate unrealized P&L: (current_price - avg_entry_price) * quantity
+                        let position_pnl = (&current_price - &avg_entry_price) * &quantity;
+                        unrealized_pnl_total = unrealized_pnl_total + position_pnl;
+                    }
+                }
+            }
+        }
+
+        let volume = volume_store
+            .get_last(&user)
+            .unwrap_or_else(|| BigInt::from(0));
+        let total_volume = volume.to_string();
+
+        let total_trades = trade_count_store
+            .get_last(&user)
+            .unwrap_or(0) as u64;
+
+        let total_fees = calculate_user_fees(&fills, &user);
+
+        // Liquidity P&L is kept in USDC (6 decimals); scale it to match realized/unrealized
+        let liquidity_pnl = lp_pnl_store
+            .get_last(&user)
+            .unwrap_or_else(|| BigInt::from(0));
+        let liquidity_scaled = &liquidity_pnl * BigInt::from_str("1000000000000").unwrap(); // 10^12
+
+        let realized_bigint = BigInt::from_str(&realized).unwrap_or_default();
+        let total_pnl = &realized_bigint + &unrealized_pnl_total + &liquidity_scaled;
+
+        // Capital efficiency: P&L per USDC of peak net investment and per USDC traded
+        let usdc_spent = usdc_spent_store.get_last(&user).unwrap_or_else(|| BigInt::from(0));
+        let capital_deployed = capital_deployed_store.get_last(&user).unwrap_or_else(|| BigInt::from(0));
+        let total_pnl_usdc = &total_pnl / BigInt::from_str("1000000000000").unwrap(); // 10^12
+
+        updates.updates.push(pnl::UserPnLUpdate {
+            user_address: user.clone(),
+            realized_pnl: realized,
+            unrealized_pnl: unrealized_pnl_total.to_string(),
+            total_pnl: total_pnl.to_string(),
+            total_volume,
+            total_trades,
+            total_fees_paid: total_fees.to_string(),
+            win_count: 0,
+            loss_count: 0,
+            win_rate: "0".to_string(),
+            max_drawdown: "0".to_string(),
+            largest_win: "0".to_string(),
+            largest_loss: "0".to_string(),
+            first_trade_at: None,
+            last_trade_at: Some(fills.block_timestamp.clone().unwrap_or_default()),
+            positions: vec![],
+            liquidity_pnl: liquidity_pnl.to_string(),
+            roi: ratio_decimal(&total_pnl_usdc, &capital_deployed),
+            return_per_volume: ratio_decimal(&total_pnl_usdc, &volume),
+            capital_deployed: capital_deployed.to_string(),
+            usdc_spent: usdc_spent.to_string(),
+        });
+    }
+
+    Ok(updates)
+}
+
+/// Value of `key` at the start of the block: the first delta's old value, else the stored value
+fn block_start_value(deltas: &Deltas<DeltaBigInt>, store: &StoreGetBigInt, key: &str) -> BigInt {
+    deltas
+        .deltas
+        .iter()
+        .find(|d| d.key == key)
+        .map(|d| d.old_value.clone())
+        .unwrap_or_else(|| store.get_last(key).unwrap_or_else(|| BigInt::from(0)))
+}
+
+/// Classify what a fill did to a position
+fn position_action(before: &BigInt, after: &BigInt) -> &'static str {
+    let zero = BigInt::from(0);
+    if before.is_zero() {
+        "open"
+    } else if after.is_zero() {
+        "close"
+    } else if (before > &zero) != (after > &zero) {
+        "flip"
+    } else if (after > before) == (before > &zero) {
+        "add"
+    } else {
+        "reduce"
+    }
+}
+
+/// Realized P&L of a sell against a long position at `avg_entry_price` (18 decimals).
+/// Only the shares that close the long position realize P&L; the rest opens a short.
+fn fill_realized_pnl(position_before: &BigInt, shares: &BigInt, proceeds: &BigInt, avg_entry_price: &BigInt) -> BigInt {
+    let closing = if shares < position_before { shares.clone() } else { long_quantity(position_before) };
+    if closing.is_zero() || shares.is_zero() {
+        return BigInt::from(0);
+    }
+    let scale = BigInt::from_str("1000000000000000000").unwrap(); // 10^18
+    (proceeds * &closing) / shares - (&closing * avg_entry_price) / &scale
+}
+
+/// Average entry price (18 decimals) of `shares` bought for `cost` USDC (both 6 decimals)
+fn avg_entry_price(cost: &BigInt, shares: &BigInt) -> BigInt {
+    if shares.is_zero() {
+        return BigInt::from(0);
+    }
+    let scale = BigInt::from_str("1000000000000000000").unwrap(); // 10^18
+    cost * &scale / shares
+}
+
+/// Annotate each fill with the trader's position before/after, entry price and realized P&L.
+/// Fills are replayed from the block-start position, buy cost and bought shares: exchange fills,
+/// then synthetic NegRisk adapter and FPMM funding fills, each in log order. This is the one
+/// realization of P&L; the realized P&L stores add up its rows.
+///
+/// The trader is the filled order's owner (see fill_trader), so each maker and the taker order's
+/// own fill are attributed once, on the token each actually traded.
+/// The entry price averages all buys of the position.
+#[substreams::handlers::map]
+fn map_enriched_fills(
+    fills: pnl::OrderFills,
+    adapter_fills: pnl::OrderFills,
+    fpmm_fills: pnl::OrderFills,
+    positions_deltas: Deltas<DeltaBigInt>,
+    positions_store: StoreGetBigInt,
+    buy_cost_deltas: Deltas<DeltaBigInt>,
+    buy_cost_store: StoreGetBigInt,
+    bought_shares_deltas: Deltas<DeltaBigInt>,
+    bought_shares_store: StoreGetBigInt,
+) -> Result<pnl::EnrichedFills, substreams::errors::Error> {
+    let mut enriched = pnl::EnrichedFills {
+        block_number: fills.block_number,
+        block_timestamp: fills.block_timestamp,
+        ..Default::default()
+    };
+
+    // Running (quantity, buy cost, bought shares) per {user}:{token_id} within the block
+    let mut running: std::collections::HashMap<String, (BigInt, BigInt, BigInt)> = std::collections::HashMap::new();
+
+    let sources = [(&fills.fills, false), (&adapter_fills.fills, true), (&fpmm_fills.fills, true)];
+    for (fill, synthetic) in sources.into_iter().flat_map(|(fills, synthetic)| fills.iter().map(move |f| (f, synthetic))) {
+        let Some((trader, side)) = fill_trader(fill) else {
+            continue;
+        };
+
+        let key = format!("{}:{}", trader, fill.token_id);
+        let (quantity, buy_cost, bought_shares) = running.entry(key.clone()).or_insert_with(|| {
+            (
+                block_start_value(&positions_deltas, &positions_store, &key),
+                block_start_value(&buy_cost_deltas, &buy_cost_store, &key),
+                block_start_value(&bought_shares_deltas, &bought_shares_store, &key),
+            )
+        });
+
+        let shares = order_shares(fill);
+        let usdc = BigInt::from_str(&fill.amount).unwrap_or_default();
+        let avg_entry_price = avg_entry_price(buy_cost, bought_shares);
+
+        let position_before = quantity.clone();
+        let realized_pnl = if side == "sell" {
+            *quantity = &*quantity - &shares;
+            fill_realized_pnl(&position_before, &shares, &usdc, &avg_entry_price)
+        } else {
+            *quantity = &*quantity + &shares;
+            *buy_cost = &*buy_cost + &usdc;
+            *bought_shares = &*bought_shares + &shares;
+            BigInt::from(0)
+        };
+
+        let rows = if synthetic { &mut enriched.synthetic_fills } else { &mut enriched.fills };
+        rows.push(pnl::EnrichedFill {
+            fill_id: fill.id.clone(),
+            token_id: fill.token_id.clone(),
+            trader,
+            trader_side: side.to_string(),
+            shares: shares.to_string(),
+            position_action: position_action(&position_before, quantity).to_string(),
+            position_before: position_before.to_string(),
+            position_after: quantity.to_string(),
+            avg_entry_price: format_signed_price(&avg_entry_price),
+            realized_pnl: realized_pnl.to_string(),
+        });
+    }
+
+    Ok(enriched)
+}
+
+/// How a holding episode ended
+fn exit_type(sold: bool, redemption_payout: Option<&BigInt>, merged: bool) -> &'static str {
+    match redemption_payout {
+        _ if sold => "sold",
+        Some(payout) if payout.is_zero() => "expired_worthless",
+        Some(_) => "redeemed",
+        None if merged => "merged",
+        None => "transferred",
+    }
+}
+
+/// Add `value` to a decimal string field
+fn add_to_decimal(field: &mut String, value: &BigInt) {
+    let current = BigInt::from_str(field).unwrap_or_default();
+    *field = (current + value).to_string();
+}
+
+/// Lifecycle row for the episode of {user}:{token_id} opened at `opened_block`
+fn lifecycle_entry<'a>(
+    lifecycles: &'a mut std::collections::BTreeMap<String, pnl::PositionLifecycle>,
+    user: &str,
+    token_id: &str,
+    opened_block: u64,
+) -> &'a mut pnl::PositionLifecycle {
+    let id = format!("{}:{}:{}", user, token_id, opened_block);
+    lifecycles.entry(id.clone()).or_insert_with(|| pnl::PositionLifecycle {
+        id,
+        user_address: user.to_string(),
+        token_id: token_id.to_string(),
+        opened_block,
+        bought_shares: "0".to_string(),
+        sold_shares: "0".to_string(),
+        realized_pnl: "0".to_string(),
+        max_size: "0".to_string(),
+        ..Default::default()
+    })
+}
+
+/// Track holding episodes (open -> close -> outcome) per {user}:{token_id}.
+/// Episodes open and close on quantity crossing zero in store_user_positions; fills are
+/// attributed to the episode they traded in, and the exit type comes from the closing block's activity.
+/// Redemptions and merges count only when they are of the token's condition (any of the user's, for
+/// tokens that were never registered on an exchange).
+#[substreams::handlers::map]
+fn map_position_lifecycles(
+    transfers: pnl::TokenTransfers,
+    positions_deltas: Deltas<DeltaBigInt>,
+    episode_deltas: Deltas<DeltaProto<pnl::PositionEpisode>>,
+    episodes_store: StoreGetProto<pnl::PositionEpisode>,
+    enriched_fills: pnl::EnrichedFills,
+    ctf_events: pnl::CtfEvents,
+    neg_risk_events: pnl::NegRiskEvents,
+    market_tokens_store: StoreGetProto<pnl::MarketToken>,
+) -> Result<pnl::PositionLifecycles, substreams::errors::Error> {
+    let block_number = transfers.block_number;
+    let zero = BigInt::from(0);
+    let mut lifecycles: std::collections::BTreeMap<String, pnl::PositionLifecycle> = std::collections::BTreeMap::new();
+
+    // Episode open at the start of the block, and after it
+    let block_start_episode = |key: &str| {
+        episode_deltas
+            .deltas
+            .iter()
+            .find(|d| d.key == key)
+            .map(|d| d.old_value.opened_block)
+            .unwrap_or_else(|| episodes_store.get_last(key).map(|e| e.opened_block).unwrap_or(0))
+    };
+    let current_episode = |key: &str| episodes_store.get_last(key).map(|e| e.opened_block).unwrap_or(0);
+
+    // Fills: opening fills belong to the episode opened this block, the rest to the block-start episode
+    for fill in &enriched_fills.fills {
+        let key = format!("{}:{}", fill.trader, fill.token_id);
+        let before = BigInt::from_str(&fill.position_before).unwrap_or_default();
+        let opened_block = if before <= zero {
+            current_episode(&key)
+        } else {
+            block_start_episode(&key)
+        };
+        if opened_block == 0 {
+            continue;
+        }
+
+        let shares = BigInt::from_str(&fill.shares).unwrap_or_default();
+        let realized = BigInt::from_str(&fill.realized_pnl).unwrap_or_default();
+        let lifecycle = lifecycle_entry(&mut lifecycles, &fill.trader, &fill.token_id, opened_block);
+        if fill.trader_side == "buy" {
+            add_to_decimal(&mut lifecycle.bought_shares, &shares);
+        } else {
+            add_to_decimal(&mut lifecycle.sold_shares, &shares);
+        }
+        add_to_decimal(&mut lifecycle.realized_pnl, &realized);
+    }
+
+    // Max size: largest quantity reached this block within the open episode
+    for delta in &positions_deltas.deltas {
+        if let Some((user, token_id)) = delta.key.split_once(':') {
+            let opened_block = match current_episode(&delta.key) {
+                0 => block_start_episode(&delta.key),
+                opened_block => opened_block,
+            };
+            if opened_block == 0 || delta.new_value <= zero {
+                continue;
+            }
+            let lifecycle = lifecycle_entry(&mut lifecycles, user, token_id, opened_block);
+            if delta.new_value > BigInt::from_str(&lifecycle.max_size).unwrap_or_default() {
+                lifecycle.max_size = delta.new_value.to_string();
+            }
+        }
+    }
+
+    // Opens and closes
+    for delta in &episode_deltas.deltas {
+        let (user, token_id) = match delta.key.split_once(':') {
+            Some(parts) => parts,
+            None => continue,
+        };
+
+        if delta.new_value.opened_block > 0 {
+            let lifecycle = lifecycle_entry(&mut lifecycles, user, token_id, delta.new_value.opened_block);
+            lifecycle.opened_at = delta.new_value.opened_at;
+        } else if delta.old_value.opened_block > 0 {
+            let sold = enriched_fills.fills.iter().any(|f| {
+                f.trader_side == "sell"
+                    && f.trader == user
+                    && f.token_id == token_id
+                    && BigInt::from_str(&f.position_after).unwrap_or_default() <= zero
+            });
+            let token_condition = market_tokens_store.get_last(token_id).map(|t| t.condition_id);
+            // (kind, amount) of the user's position changes on the token's condition
+            let changes: Vec<(&String, &String)> = ctf_events
+                .position_changes
+                .iter()
+                .map(|c| (&c.stakeholder, &c.condition_id, &c.kind, &c.amount))
+                .chain(
+                    neg_risk_events
+                        .position_changes
+                        .iter()
+                        .map(|c| (&c.stakeholder, &c.condition_id, &c.kind, &c.amount)),
+                )
+                .filter(|(stakeholder, condition_id, _, _)| {
+                    stakeholder.eq_ignore_ascii_case(user)
+                        && token_condition.as_ref().is_none_or(|c| c.eq_ignore_ascii_case(condition_id))
+                })
+                .map(|(_, _, kind, amount)| (kind, amount))
+                .collect();
+            let redemptions: Vec<BigInt> = changes
+                .iter()
+                .filter(|(kind, _)| kind.as_str() == "redemption")
+                .map(|(_, amount)| BigInt::from_str(amount).unwrap_or_default())
+                .collect();
+            let redemption_payout = if redemptions.is_empty() {
+                None
+            } else {
+                Some(redemptions.iter().fold(BigInt::from(0), |total, payout| total + payout))
+            };
+            let merged = changes.iter().any(|(kind, _)| kind.as_str() == "merge");
+
+            let opened_at = delta.old_value.opened_at;
+            let lifecycle = lifecycle_entry(&mut lifecycles, user, token_id, delta.old_value.opened_block);
+            lifecycle.closed_block = block_number;
+            lifecycle.closed_at = transfers.block_timestamp;
+            lifecycle.holding_seconds = match (&transfers.block_timestamp, &opened_at) {
+                (Some(closed), Some(opened)) => closed.seconds - opened.seconds,
+                _ => 0,
+            };
+            lifecycle.exit_type = exit_type(sold, redemption_payout.as_ref(), merged).to_string();
+        }
+    }
+
+    Ok(pnl::PositionLifecycles {
+        lifecycles: lifecycles.into_values().collect(),
+        block_number,
+    })
+}
+
+/// Market value of a quantity at an 18-decimal price string, in USDC (6 decimals)
+fn market_value(quantity: &BigInt, price: &str) -> BigInt {
+    let scale = BigInt::from_str("1000000000000000000").unwrap(); // 10^18
+    (quantity * parse_price_decimal(price)) / &scale
+}
+
+/// Payout fraction per outcome slot (numerator / sum of numerators), as 18-decimal strings
+fn payout_fractions(payout_numerators: &[String]) -> Vec<String> {
+    let numerators: Vec<BigInt> = payout_numerators
+        .iter()
+        .map(|n| BigInt::from_str(n).unwrap_or_default())
+        .collect();
+    let denominator = numerators.iter().fold(BigInt::from(0), |acc, n| acc + n);
+    numerators.iter().map(|n| format_price_decimal(n, &denominator)).collect()
+}
+
+/// Number of equal-width price buckets in calibration_buckets (deciles)
+const CALIBRATION_BUCKETS: u64 = 10;
+
+/// Calibration bucket of an 18-decimal price: floor(price * buckets), prices of 1 go to the top bucket
+fn calibration_bucket(price: &str) -> u64 {
+    let scale = BigInt::from_str("1000000000000000000").unwrap(); // 10^18
+    let bucket = (parse_price_decimal(price) * BigInt::from(CALIBRATION_BUCKETS)) / &scale;
+    bucket.to_u64().min(CALIBRATION_BUCKETS - 1)
+}
+
+/// Resolved conditions linked to their YES (outcome slot 0) and NO (slot 1) tokens
+#[substreams::handlers::map]
+fn map_condition_resolutions(
+    ctf_events: pnl::CtfEvents,
+    condition_tokens_store: StoreGetProto<pnl::MarketToken>,
+    prices_store: StoreGetProto<pnl::TokenPrice>,
+) -> Result<pnl::ConditionResolutions, substreams::errors::Error> {
+    let mut resolutions = pnl::ConditionResolutions {
+        block_number: ctf_events.block_number,
+        block_timestamp: ctf_events.block_timestamp,
+        ..Default::default()
+    };
+
+    for resolution in ctf_events.resolutions {
+        let token = condition_tokens_store.get_last(&resolution.condition_id).unwrap_or_default();
+        let fractions = payout_fractions(&resolution.payout_numerators);
+        let payout = |slot: usize| fractions.get(slot).cloned().unwrap_or_else(|| "0.000000000000000000".to_string());
+        let last_price = |token_id: &str| prices_store.get_last(token_id).map(|p| p.price).unwrap_or_default();
+
+        resolutions.resolutions.push(pnl::ResolvedCondition {
+            yes_price: last_price(&token.token_id),
+            no_price: last_price(&token.complement_token_id),
+            yes_payout: payout(0),
+            no_payout: payout(1),
+            condition_id: resolution.condition_id,
+            question_id: resolution.question_id,
+            oracle: resolution.oracle,
+            payout_numerators: resolution.payout_numerators,
+            yes_token_id: token.token_id,
+            no_token_id: token.complement_token_id,
+        });
+    }
+
+    Ok(resolutions)
+}
+
+/// Open interest for every token minted or burned in this block, with its condition's locked collateral
+#[substreams::handlers::map]
+fn map_open_interest(
+    transfers: pnl::TokenTransfers,
+    token_oi_deltas: Deltas<DeltaBigInt>,
+    condition_oi_store: StoreGetBigInt,
+    market_tokens_store: StoreGetProto<pnl::MarketToken>,
+) -> Result<pnl::OpenInterests, substreams::errors::Error> {
+    let mut open_interest = pnl::OpenInterests {
+        block_number: transfers.block_number,
+        block_timestamp: transfers.block_timestamp,
+        ..Default::default()
+    };
+
+    // Last delta per token carries its end-of-block value
+    let mut tokens: std::collections::BTreeMap<&str, &BigInt> = std::collections::BTreeMap::new();
+    for delta in &token_oi_deltas.deltas {
+        tokens.insert(&delta.key, &delta.new_value);
+    }
+
+    for (token_id, shares) in tokens {
+        let condition_id = market_tokens_store
+            .get_last(token_id)
+            .map(|token| token.condition_id)
+            .unwrap_or_default();
+        let collateral_locked = condition_oi_store.get_last(&condition_id).unwrap_or_else(|| BigInt::from(0));
+
+        open_interest.tokens.push(pnl::TokenOpenInterest {
+            token_id: token_id.to_string(),
+            condition_id,
+            open_interest: shares.to_string(),
+            collateral_locked: collateral_locked.to_string(),
+        });
+    }
+
+    Ok(open_interest)
+}
+
+/// Herfindahl index from the sum of squared positions and the total held: sum((q / total)^2)
+fn herfindahl_index(squares: &BigInt, total_shares: &BigInt) -> String {
+    format_price_decimal(squares, &(total_shares * total_shares))
+}
+
+/// Holder count and concentration for every token whose positions changed in this block
+#[substreams::handlers::map]
+fn map_market_holders(
+    fills: pnl::OrderFills,
+    positions_deltas: Deltas<DeltaBigInt>,
+    holder_count_store: StoreGetInt64,
+    holder_shares_store: StoreGetBigInt,
+    holder_squares_store: StoreGetBigInt,
+) -> Result<pnl::MarketHolders, substreams::errors::Error> {
+    let mut holders = pnl::MarketHolders {
+        block_number: fills.block_number,
+        block_timestamp: fills.block_timestamp,
+        ..Default::default()
+    };
+
+    let tokens: std::collections::BTreeSet<&str> = positions_deltas
+        .deltas
+        .iter()
+        .filter_map(|delta| delta.key.split_once(':').map(|(_, token_id)| token_id))
+        .collect();
+
+    for token_id in tokens {
+        let total_shares = holder_shares_store.get_last(token_id).unwrap_or_else(|| BigInt::from(0));
+        let squares = holder_squares_store.get_last(token_id).unwrap_or_else(|| BigInt::from(0));
+
+        holders.markets.push(pnl::MarketHolderStats {
+            token_id: token_id.to_string(),
+            holder_count: holder_count_store.get_last(token_id).unwrap_or(0),
+            hhi: herfindahl_index(&squares, &total_shares),
+            total_shares: total_shares.to_string(),
+        });
+    }
+
+    Ok(holders)
+}
+
+/// Classify a user's USDC transfer by its counterparty
+fn cash_flow_type(counterparty: &str, incoming: bool, is_fee: bool, redeemed: bool) -> &'static str {
+    let exchanges = [format_address(&CTF_EXCHANGE), format_address(&NEG_RISK_EXCHANGE)];
+    let collateral_holders = [format_address(&CONDITIONAL_TOKENS), format_address(&NEG_RISK_ADAPTER)];
+
+    if is_fee && !incoming {
+        "fee"
+    } else if exchanges.iter().any(|a| a.eq_ignore_ascii_case(counterparty)) {
+        "trade_settlement"
+    } else if collateral_holders.iter().any(|a| a.eq_ignore_ascii_case(counterparty)) {
+        match (incoming, redeemed) {
+            (false, _) => "split",
+            (true, true) => "redemption",
+            (true, false) => "merge",
+        }
+    } else if incoming {
+        "deposit"
+    } else {
+        "withdrawal"
+    }
+}
+
+/// USDC transfers of known users, classified as deposits, withdrawals, trade settlements,
+/// splits, merges, redemption payouts or fees (matched to FeeCharged in the same transaction).
+/// Cash history starts at the user's first appearance: USDC held before then is not tracked.
+#[substreams::handlers::map]
+fn map_user_cash_flows(
+    usdc_transfers: pnl::UsdcTransfers,
+    exchange_events: pnl::ExchangeEvents,
+    ctf_events: pnl::CtfEvents,
+    neg_risk_events: pnl::NegRiskEvents,
+    known_users_store: StoreGetInt64,
+) -> Result<pnl::UserCashFlows, substreams::errors::Error> {
+    use std::collections::HashSet;
+
+    let mut cash_flows = pnl::UserCashFlows {
+        block_number: usdc_transfers.block_number,
+        block_timestamp: usdc_transfers.block_timestamp,
+        ..Default::default()
+    };
+
+    // (tx_hash, receiver, amount) of USDC fees (token id 0)
+    let fees: HashSet<(&str, &str, &str)> = exchange_events
+        .events
+        .iter()
+        .filter(|e| e.event_type == "fee_charged" && e.token_id == "0")
+        .map(|e| (e.tx_hash.as_str(), e.account.as_str(), e.amount.as_str()))
+        .collect();
+
+    // (tx_hash, redeemer) of CTF and NegRisk adapter redemptions
+    let redemptions: HashSet<(&str, &str)> = ctf_events
+        .position_changes
+        .iter()
+        .map(|c| (c.tx_hash.as_str(), c.stakeholder.as_str(), c.kind.as_str()))
+        .chain(
+            neg_risk_events
+                .position_changes
+                .iter()
+                .map(|c| (c.tx_hash.as_str(), c.stakeholder.as_str(), c.kind.as_str())),
+        )
+        .filter(|(_, _, kind)| *kind == "redemption")
+        .map(|(tx_hash, redeemer, _)| (tx_hash, redeemer))
+        .collect();
+
+    for transfer in &usdc_transfers.transfers {
+        let from = transfer.from_address.to_lowercase();
+        let to = transfer.to_address.to_lowercase();
+
+        for (user, counterparty, incoming) in [(&from, &to, false), (&to, &from, true)] {
+            if is_excluded_address(user) || known_users_store.get_last(user).is_none() {
+                continue;
+            }
+
+            let is_fee = fees.contains(&(transfer.tx_hash.as_str(), counterparty.as_str(), transfer.amount.as_str()));
+            let redeemed = redemptions.contains(&(transfer.tx_hash.as_str(), user.as_str()));
+            let amount = if incoming {
+                transfer.amount.clone()
+            } else {
+                format!("-{}", transfer.amount)
+            };
+
+            cash_flows.flows.push(pnl::UserCashFlow {
+                id: format!("{}-{}-{}", transfer.tx_hash, transfer.log_index, user),
+                tx_hash: transfer.tx_hash.clone(),
+                log_index: transfer.log_index,
+                block_number: transfer.block_number,
+                user_address: user.clone(),
+                counterparty: counterparty.clone(),
+                flow_type: cash_flow_type(counterparty, incoming, is_fee, redeemed).to_string(),
+                amount,
+            });
+        }
+    }
+
+    Ok(cash_flows)
+}
+
+/// Minimum exits before a trader's win rate weighs their flow
+const MIN_SMART_EXITS: i64 = 10;
+
+/// Bucket sizes of store_smart_flow: 5-minute buckets make up the 1h window, hourly buckets the 24h one
+const SMART_FLOW_BUCKETS: [(&str, i64); 2] = [("5m", 300), ("1h", 3600)];
+
+/// How long flow buckets (smart flow and price impact) are kept before they are deleted (24h, the longest window)
+const FLOW_BUCKET_RETENTION_SECONDS: i64 = 86400;
+
+/// Key of a flow bucket: the bucket start comes first so expired buckets go with one delete_prefix
+fn flow_bucket_key(window: &str, bucket_start: i64, token_id: &str) -> String {
+    format!("{}:{}:{}", window, bucket_start, token_id)
+}
+
+/// Add `value` to the token's current bucket of each size
+fn add_to_flow_buckets(store: &StoreAddBigInt, timestamp: i64, token_id: &str, value: &BigInt) {
+    for (window, bucket_seconds) in SMART_FLOW_BUCKETS {
+        let bucket_start = timestamp - timestamp.rem_euclid(bucket_seconds);
+        store.add(0, flow_bucket_key(window, bucket_start, token_id), value);
+    }
+}
+
+/// Delete the buckets that fell out of the retention at `now`, plus the one before in case no
+/// block with fills came during a whole bucket
+fn delete_expired_flow_buckets(store: &StoreAddBigInt, now: i64) {
+    for (window, bucket_seconds) in SMART_FLOW_BUCKETS {
+        let expired = now - now.rem_euclid(bucket_seconds) - FLOW_BUCKET_RETENTION_SECONDS;
+        for bucket_start in [expired, expired - bucket_seconds] {
+            store.delete_prefix(0, &format!("{}:{}:", window, bucket_start));
+        }
+    }
+}
+
+/// Default alert thresholds for smart money flow (USDC, 6 decimals)
+const DEFAULT_SMART_FLOW_THRESHOLD_1H: i64 = 25_000_000_000;
+const DEFAULT_SMART_FLOW_THRESHOLD_24H: i64 = 100_000_000_000;
+
+/// Fill amount weighted by how far the trader's exit win rate beats a coin flip:
+/// (wins - losses) / exits, zero at or below 50% or with fewer than MIN_SMART_EXITS exits
+fn smart_money_weight(amount: &BigInt, exits: i64, winning_exits: i64) -> BigInt {
+    let edge = 2 * winning_exits - exits;
+    if exits < MIN_SMART_EXITS || edge <= 0 {
+        return BigInt::from(0);
+    }
+    amount * BigInt::from(edge) / BigInt::from(exits)
+}
+
+/// Rolling flow of a token over `buckets` buckets of `bucket_seconds` ending at `now`,
+/// as (value at the start of the block, value with the block's writes)
+fn rolling_flow(
+    deltas: &Deltas<DeltaBigInt>,
+    store: &StoreGetBigInt,
+    window: &str,
+    token_id: &str,
+    now: i64,
+    (bucket_seconds, buckets): (i64, i64),
+) -> (BigInt, BigInt) {
+    let current = now - now.rem_euclid(bucket_seconds);
+    (0..buckets).fold((BigInt::from(0), BigInt::from(0)), |(before, after), i| {
+        let key = flow_bucket_key(window, current - i * bucket_seconds, token_id);
+        (
+            before + block_start_value(deltas, store, &key),
+            after + store.get_last(&key).unwrap_or_else(|| BigInt::from(0)),
+        )
+    })
+}
+
+/// Smart money net flow over 1h and 24h for tokens traded in the block, with an alert whenever
+/// a window's flow crosses the params threshold (threshold_1h, threshold_24h) in either direction
+#[substreams::handlers::map]
+fn map_smart_flow(
+    params: String,
+    fills: pnl::OrderFills,
+    smart_flow_deltas: Deltas<DeltaBigInt>,
+    smart_flow_store: StoreGetBigInt,
+) -> Result<pnl::SmartFlows, substreams::errors::Error> {
+    let threshold_1h: i64 = param_value(&params, "threshold_1h")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_SMART_FLOW_THRESHOLD_1H);
+    let threshold_24h: i64 = param_value(&params, "threshold_24h")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_SMART_FLOW_THRESHOLD_24H);
+
+    let mut smart_flows = pnl::SmartFlows {
+        block_number: fills.block_number,
+        block_timestamp: fills.block_timestamp,
+        ..Default::default()
+    };
+    let Some(now) = fills.block_timestamp.as_ref().map(|t| t.seconds) else {
+        return Ok(smart_flows);
+    };
+
+    let tokens: std::collections::BTreeSet<&str> = fills.fills.iter().map(|f| f.token_id.as_str()).collect();
+    for token_id in tokens {
+        let mut flows = Vec::with_capacity(2);
+        for (label, window, bucket_seconds, buckets, threshold) in [
+            ("1h", "5m", 300, 12, threshold_1h),
+            ("24h", "1h", 3600, 24, threshold_24h),
+        ] {
+            let (before, flow) =
+                rolling_flow(&smart_flow_deltas, &smart_flow_store, window, token_id, now, (bucket_seconds, buckets));
+            let threshold = BigInt::from(threshold);
+            let floor = BigInt::from(0) - &threshold;
+            let crossed_in = flow >= threshold && before < threshold;
+            let crossed_out = flow <= floor && before > floor;
+            if crossed_in || crossed_out {
+                smart_flows.alerts.push(pnl::SmartFlowAlert {
+                    token_id: token_id.to_string(),
+                    window: label.to_string(),
+                    flow: flow.to_string(),
+                    threshold: threshold.to_string(),
+                    direction: if crossed_in { "inflow" } else { "outflow" }.to_string(),
+                });
+            }
+            flows.push(flow);
+        }
+
+        smart_flows.flows.push(pnl::TokenSmartFlow {
+            token_id: token_id.to_string(),
+            flow_1h: flows[0].to_string(),
+            flow_24h: flows[1].to_string(),
+        });
+    }
+
+    Ok(smart_flows)
+}
+
+/// Default wallet age under which buys are screened (7 days)
+const DEFAULT_FRESH_WALLET_SECONDS: i64 = 7 * 86400;
+
+/// Default USDC spent on a condition before a fresh wallet's position is recorded (1,000 USDC)
+const DEFAULT_MIN_SUSPICIOUS_POSITION: i64 = 1_000_000_000;
+
+/// Whether a position is screened: the wallet first traded at most `fresh_wallet_seconds` ago and
+/// has spent at least `min_position` USDC on the condition
+fn is_fresh_wallet_position(
+    wallet_age_seconds: i64,
+    position_usdc: &BigInt,
+    fresh_wallet_seconds: i64,
+    min_position: &BigInt,
+) -> bool {
+    wallet_age_seconds <= fresh_wallet_seconds && position_usdc >= min_position
+}
+
+/// (market share, concentration) of a position: its USDC over the condition's volume, and over
+/// everything the wallet has spent (18 decimals)
+fn position_ratios(position_usdc: &BigInt, market_volume: &BigInt, usdc_spent: &BigInt) -> (String, String) {
+    (ratio_decimal(position_usdc, market_volume), ratio_decimal(position_usdc, usdc_spent))
+}
+
+/// Buys by fresh wallets (params fresh_wallet_seconds) holding at least min_position USDC in the
+/// condition, with the inputs of the suspicion score: wallet age, share of the market's volume and
+/// concentration of the wallet's spending. Time to resolution and outcome are filled on resolution.
+#[substreams::handlers::map]
+fn map_suspicious_positions(
+    params: String,
+    fills: pnl::OrderFills,
+    market_tokens_store: StoreGetProto<pnl::MarketToken>,
+    first_trade_store: StoreGetInt64,
+    market_spent_store: StoreGetBigInt,
+    usdc_spent_store: StoreGetBigInt,
+    market_volume_store: StoreGetBigInt,
+) -> Result<pnl::SuspiciousPositions, substreams::errors::Error> {
+    let fresh_wallet_seconds: i64 = param_value(&params, "fresh_wallet_seconds")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_FRESH_WALLET_SECONDS);
+    let min_position = BigInt::from(
+        param_value(&params, "min_position")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_MIN_SUSPICIOUS_POSITION),
+    );
+
+    let mut suspicious = pnl::SuspiciousPositions {
+        block_number: fills.block_number,
+        block_timestamp: fills.block_timestamp,
+        ..Default::default()
+    };
+    let Some(now) = fills.block_timestamp.as_ref().map(|t| t.seconds) else {
+        return Ok(suspicious);
+    };
+
+    // Latest bought token per (buyer, condition)
+    let mut buys: std::collections::BTreeMap<(String, String), (String, String)> = std::collections::BTreeMap::new();
+    for fill in &fills.fills {
+        let (buyer, _) = fill_counterparties(fill);
+        if is_excluded_address(&buyer) {
+            continue;
+        }
+        if let Some(token) = market_tokens_store.get_last(&fill.token_id) {
+            buys.insert((buyer, token.condition_id), (fill.token_id.clone(), token.complement_token_id));
+        }
+    }
+
+    for ((user, condition_id), (token_id, complement_token_id)) in buys {
+        let Some(first_trade_at) = first_trade_store.get_last(&user) else {
+            continue;
+        };
+        let wallet_age_seconds = now - first_trade_at;
+        let position_usdc = market_spent_store
+            .get_last(format!("{}:{}", user, condition_id))
+            .unwrap_or_else(|| BigInt::from(0));
+        if !is_fresh_wallet_position(wallet_age_seconds, &position_usdc, fresh_wallet_seconds, &min_position) {
+            continue;
+        }
+
+        let market_volume = [&token_id, &complement_token_id]
+            .iter()
+            .fold(BigInt::from(0), |total, token| {
+                total + market_volume_store.get_last(token).unwrap_or_else(|| BigInt::from(0))
+            });
+        let usdc_spent = usdc_spent_store.get_last(&user).unwrap_or_else(|| BigInt::from(0));
+        let (market_share, concentration) = position_ratios(&position_usdc, &market_volume, &usdc_spent);
+
+        suspicious.positions.push(pnl::SuspiciousPosition {
+            market_share,
+            concentration,
+            user_address: user,
+            condition_id,
+            token_id,
+            first_trade_at,
+            wallet_age_seconds,
+            position_usdc: position_usdc.to_string(),
+            market_volume: market_volume.to_string(),
+        });
+    }
+
+    Ok(suspicious)
+}
+
+/// Block span of store_price_checkpoints
+const PRICE_CHECKPOINT_BLOCKS: u64 = 10;
+
+/// Checkpoint spans searched back for a reference price before giving up
+const PRICE_CHECKPOINT_LOOKBACK: u64 = 16;
+
+/// Longest price_move_blocks map_alerts accepts; checkpoints further back are deleted
+const MAX_PRICE_MOVE_BLOCKS: u64 = 1000;
+
+/// Checkpoint spans kept: the longest price move window plus the lookback behind it
+const PRICE_CHECKPOINT_RETENTION: u64 = MAX_PRICE_MOVE_BLOCKS / PRICE_CHECKPOINT_BLOCKS + PRICE_CHECKPOINT_LOOKBACK;
+
+/// Default alert rules (USDC amounts with 6 decimals; 0 disables a rule)
+const DEFAULT_ALERT_MIN_NOTIONAL: i64 = 10_000_000_000;
+const DEFAULT_ALERT_PRICE_MOVE_BPS: i64 = 1000;
+const DEFAULT_ALERT_PRICE_MOVE_BLOCKS: u64 = 150;
+const DEFAULT_ALERT_PNL_THRESHOLD: i64 = 100_000_000_000;
+const DEFAULT_ALERT_MIN_REDEMPTION: i64 = 10_000_000_000;
+
+/// Absolute price move from `reference` to `price` in basis points (0 without a reference)
+fn price_move_bps(reference: &BigInt, price: &BigInt) -> i64 {
+    if reference <= &BigInt::from(0) {
+        return 0;
+    }
+    let change = if price > reference { price - reference } else { reference - price };
+    (change * BigInt::from(10_000) / reference).to_string().parse().unwrap_or(0)
+}
+
+/// Whole-dollar USDC for alert descriptions (input has 6 decimals)
+fn usdc_dollars(value: &BigInt) -> String {
+    (value / BigInt::from(1_000_000)).to_string()
+}
+
+/// Whether `before -> after` moved from inside (-threshold, threshold) to at or beyond either bound
+fn crossed_threshold(before: &BigInt, after: &BigInt, threshold: &BigInt) -> bool {
+    let floor = BigInt::from(0) - threshold;
+    (after >= threshold && before < threshold) || (after <= &floor && before > &floor)
+}
+
+/// Alerts from params-configured rules (0 disables a rule):
+/// - min_notional: fill notional at or above the threshold
+/// - price_move_bps / price_move_blocks: a block's fills move the price at least this far from its
+///   checkpoint price_move_blocks ago (checkpoints every PRICE_CHECKPOINT_BLOCKS blocks, at most
+///   MAX_PRICE_MOVE_BLOCKS back)
+/// - new_market: first trade on a condition (always on)
+/// - pnl_threshold: user realized P&L crosses +/- the threshold
+/// - min_redemption: CTF or NegRisk redemption payout at or above the threshold
+///
+/// Smart money flow crossings from map_smart_flow are passed through.
+#[substreams::handlers::map]
+fn map_alerts(
+    params: String,
+    fills: pnl::OrderFills,
+    ctf_events: pnl::CtfEvents,
+    neg_risk_events: pnl::NegRiskEvents,
+    smart_flows: pnl::SmartFlows,
+    market_tokens_store: StoreGetProto<pnl::MarketToken>,
+    volume_deltas: Deltas<DeltaBigInt>,
+    volume_store: StoreGetBigInt,
+    price_deltas: Deltas<DeltaProto<pnl::TokenPrice>>,
+    checkpoints_store: StoreGetString,
+    realized_pnl_deltas: Deltas<DeltaBigInt>,
+) -> Result<pnl::Alerts, substreams::errors::Error> {
+    let param = |key: &str, default: i64| -> i64 {
+        param_value(&params, key).and_then(|v| v.parse().ok()).unwrap_or(default)
+    };
+    let min_notional = BigInt::from(param("min_notional", DEFAULT_ALERT_MIN_NOTIONAL));
+    let price_move_threshold = param("price_move_bps", DEFAULT_ALERT_PRICE_MOVE_BPS);
+    let price_move_blocks = param_value(&params, "price_move_blocks")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_ALERT_PRICE_MOVE_BLOCKS)
+        .min(MAX_PRICE_MOVE_BLOCKS);
+    let pnl_threshold = BigInt::from(param("pnl_threshold", DEFAULT_ALERT_PNL_THRESHOLD));
+    let min_redemption = BigInt::from(param("min_redemption", DEFAULT_ALERT_MIN_REDEMPTION));
+    let zero = BigInt::from(0);
+
+    let block_number = fills
+        .block_number
+        .max(ctf_events.block_number)
+        .max(neg_risk_events.block_number)
+        .max(smart_flows.block_number);
+    let timestamp = fills
+        .block_timestamp
+        .or(ctf_events.block_timestamp)
+        .or(neg_risk_events.block_timestamp);
+    let mut alerts = pnl::Alerts {
+        block_number,
+        block_timestamp: timestamp,
+        ..Default::default()
+    };
+    let mut push = |alert_type: &str, subject: &str, alert: pnl::Alert| {
+        alerts.alerts.push(pnl::Alert {
+            id: format!("{}:{}:{}", alert_type, subject, block_number),
+            alert_type: alert_type.to_string(),
+            block_number,
+            timestamp,
+            ..alert
+        });
+    };
+
+    // Large trades
+    if min_notional > zero {
+        for fill in &fills.fills {
+            let notional = BigInt::from_str(&fill.amount).unwrap_or_default();
+            if notional >= min_notional {
+                push("large_trade", &fill.id, pnl::Alert {
+                    tx_hash: fill.tx_hash.clone(),
+                    token_id: fill.token_id.clone(),
+                    user_address: fill.taker.to_lowercase(),
+                    value: notional.to_string(),
+                    threshold: min_notional.to_string(),
+                    description: format!("{} {} USDC of {} at {}", fill.side, usdc_dollars(&notional), fill.token_id, fill.price),
+                    ..Default::default()
+                });
+            }
+        }
+    }
+
+    // Price moves against the checkpoint price_move_blocks ago, raised by the fills that cross
+    if price_move_threshold > 0 {
+        let mut seen = std::collections::HashSet::new();
+        for delta in &price_deltas.deltas {
+            if !seen.insert(delta.key.as_str()) {
+                continue;
+            }
+            let Some(price) = price_deltas.deltas.iter().rev().find(|d| d.key == delta.key).map(|d| &d.new_value) else {
+                continue;
+            };
+            let span = block_number.saturating_sub(price_move_blocks) / PRICE_CHECKPOINT_BLOCKS;
+            let reference = (0..PRICE_CHECKPOINT_LOOKBACK)
+                .filter_map(|i| span.checked_sub(i))
+                .find_map(|span| checkpoints_store.get_last(format!("{}:{}", span, delta.key)));
+            let Some(reference) = reference.map(|p| parse_price_decimal(&p)) else {
+                continue;
+            };
+
+            let move_before = price_move_bps(&reference, &parse_price_decimal(&delta.old_value.price));
+            let move_after = price_move_bps(&reference, &parse_price_decimal(&price.price));
+            if move_after >= price_move_threshold && move_before < price_move_threshold {
+                push("price_move", &delta.key, pnl::Alert {
+                    token_id: delta.key.clone(),
+                    value: move_after.to_string(),
+                    threshold: price_move_threshold.to_string(),
+                    description: format!(
+                        "{} moved {} bps to {} within {} blocks",
+                        delta.key, move_after, price.price, price_move_blocks
+                    ),
+                    ..Default::default()
+                });
+            }
+        }
+    }
+
+    // First trade on a condition: neither outcome had volume at the start of the block
+    let mut new_conditions = std::collections::HashSet::new();
+    for fill in &fills.fills {
+        let Some(token) = market_tokens_store.get_last(&fill.token_id) else {
+            continue;
+        };
+        let untraded = [&token.token_id, &token.complement_token_id]
+            .iter()
+            .all(|t| block_start_value(&volume_deltas, &volume_store, t) == zero);
+        if untraded && new_conditions.insert(token.condition_id.clone()) {
+            push("new_market", &token.condition_id, pnl::Alert {
+                tx_hash: fill.tx_hash.clone(),
+                token_id: fill.token_id.clone(),
+                condition_id: token.condition_id.clone(),
+                value: fill.amount.clone(),
+                description: format!("first trade on condition {} at {}", token.condition_id, fill.price),
+                ..Default::default()
+            });
+        }
+    }
+
+    // User realized P&L crossing the threshold (store is 18 decimals, threshold 6)
+    if pnl_threshold > zero {
+        let scaled_threshold = &pnl_threshold * BigInt::from_str("1000000000000").unwrap(); // 10^12
+        let mut seen = std::collections::HashSet::new();
+        for delta in &realized_pnl_deltas.deltas {
+            if !seen.insert(delta.key.as_str()) {
+                continue;
+            }
+            let after = realized_pnl_deltas
+                .deltas
+                .iter()
+                .rev()
+                .find(|d| d.key == delta.key)
+                .map(|d| &d.new_value)
+                .unwrap_or(&delta.new_value);
+            if crossed_threshold(&delta.old_value, after, &scaled_threshold) {
+                let pnl_usdc = format_usdc_from_bigint(after);
+                push("user_pnl", &delta.key, pnl::Alert {
+                    user_address: delta.key.clone(),
+                    threshold: pnl_threshold.to_string(),
+                    description: format!("{} realized P&L reached {} USDC", delta.key, usdc_dollars(&BigInt::from_str(&pnl_usdc).unwrap_or_default())),
+                    value: pnl_usdc,
+                    ..Default::default()
+                });
+            }
+        }
+    }
+
+    // Large redemptions through the CTF or the NegRisk adapter
+    if min_redemption > zero {
+        let redemptions = ctf_events
+            .position_changes
+            .iter()
+            .map(|c| (&c.id, &c.tx_hash, &c.stakeholder, &c.kind, &c.condition_id, &c.amount))
+            .chain(
+                neg_risk_events
+                    .position_changes
+                    .iter()
+                    .map(|c| (&c.id, &c.tx_hash, &c.stakeholder, &c.kind, &c.condition_id, &c.amount)),
+            );
+        for (id, tx_hash, redeemer, kind, condition_id, amount) in redemptions {
+            let payout = BigInt::from_str(amount).unwrap_or_default();
+            if kind == "redemption" && !is_excluded_address(redeemer) && payout >= min_redemption {
+                push("large_redemption", id, pnl::Alert {
+                    tx_hash: tx_hash.clone(),
+                    condition_id: condition_id.clone(),
+                    user_address: redeemer.to_lowercase(),
+                    value: payout.to_string(),
+                    threshold: min_redemption.to_string(),
+                    description: format!("{} redeemed {} USDC on condition {}", redeemer, usdc_dollars(&payout), condition_id),
+                    ..Default::default()
+                });
+            }
+        }
+    }
+
+    // Smart money flow crossings
+    for alert in &smart_flows.alerts {
+        push("smart_money_flow", &format!("{}:{}", alert.token_id, alert.window), pnl::Alert {
+            token_id: alert.token_id.clone(),
+            value: alert.flow.clone(),
+            threshold: alert.threshold.clone(),
+            description: format!("smart money {} of {} USDC on {} over {}", alert.direction, alert.flow, alert.token_id, alert.window),
+            ..Default::default()
+        });
+    }
+
+    Ok(alerts)
+}
+
+/// Taker order flow of a fill: USDC notional, negative when the taker sells
+fn signed_flow(fill: &pnl::OrderFill) -> BigInt {
+    let amount = BigInt::from_str(&fill.amount).unwrap_or_default();
+    if fill.side == "sell" {
+        BigInt::from(0) - amount
+    } else {
+        amount
+    }
+}
+
+/// Fills paired with the token's price before each one and the change the fill caused.
+/// The first fill of a token in the block is compared with the block-start price in store_latest_prices,
+/// later ones with the previous fill; a token's first ever fill has no previous price and is skipped.
+fn fill_price_changes<'a>(
+    fills: &'a [pnl::OrderFill],
+    price_deltas: &Deltas<DeltaProto<pnl::TokenPrice>>,
+) -> Vec<(&'a pnl::OrderFill, BigInt, BigInt)> {
+    let zero = BigInt::from(0);
+    let mut previous: std::collections::HashMap<&str, BigInt> = std::collections::HashMap::new();
+    let mut changes = Vec::new();
+    for fill in fills {
+        let before = previous.remove(fill.token_id.as_str()).unwrap_or_else(|| {
+            price_deltas
+                .deltas
+                .iter()
+                .find(|d| d.key == fill.token_id)
+                .map(|d| parse_price_decimal(&d.old_value.price))
+                .unwrap_or_default()
+        });
+        let price = parse_price_decimal(&fill.price);
+        if before > zero {
+            let change = &price - &before;
+            changes.push((fill, before, change));
+        }
+        previous.insert(fill.token_id.as_str(), price);
+    }
+    changes
+}
+
+/// Kyle's lambda from window sums: regression slope of price change on signed flow, expressed
+/// as the price change per 1,000 USDC of net taker flow (prices have 18 decimals, flow 6)
+fn kyle_lambda(price_flow: &BigInt, flow_squared: &BigInt) -> String {
+    ratio_decimal(price_flow, &(flow_squared * BigInt::from(1_000_000_000)))
+}
+
+/// Price impact of each fill against the token's previous price, and Kyle's lambda over the
+/// rolling 1h (5-minute buckets) and 24h (hourly buckets) windows of tokens traded in the block
+#[substreams::handlers::map]
+fn map_price_impact(
+    fills: pnl::OrderFills,
+    price_deltas: Deltas<DeltaProto<pnl::TokenPrice>>,
+    price_flow_deltas: Deltas<DeltaBigInt>,
+    price_flow_store: StoreGetBigInt,
+    flow_squared_deltas: Deltas<DeltaBigInt>,
+    flow_squared_store: StoreGetBigInt,
+) -> Result<pnl::PriceImpacts, substreams::errors::Error> {
+    let mut impacts = pnl::PriceImpacts {
+        block_number: fills.block_number,
+        block_timestamp: fills.block_timestamp,
+        ..Default::default()
+    };
+
+    for (fill, before, change) in fill_price_changes(&fills.fills, &price_deltas) {
+        let flow = signed_flow(fill);
+        let adverse = if fill.side == "sell" { BigInt::from(0) - &change } else { change.clone() };
+        impacts.fills.push(pnl::FillImpact {
+            fill_id: fill.id.clone(),
+            token_id: fill.token_id.clone(),
+            price_before: format_signed_price(&before),
+            price_change: format_signed_price(&change),
+            notional: fill.amount.clone(),
+            signed_flow: flow.to_string(),
+            slippage_bps: ratio_decimal(&(adverse * BigInt::from(10_000)), &before),
+        });
+    }
+
+    let Some(now) = fills.block_timestamp.as_ref().map(|t| t.seconds) else {
+        return Ok(impacts);
+    };
+    let tokens: std::collections::BTreeSet<&str> = impacts.fills.iter().map(|f| f.token_id.as_str()).collect();
+    for token_id in tokens {
+        let lambda = |window: &str, bucket_seconds: i64, buckets: i64| {
+            let (_, price_flow) =
+                rolling_flow(&price_flow_deltas, &price_flow_store, window, token_id, now, (bucket_seconds, buckets));
+            let (_, flow_squared) =
+                rolling_flow(&flow_squared_deltas, &flow_squared_store, window, token_id, now, (bucket_seconds, buckets));
+            kyle_lambda(&price_flow, &flow_squared)
+        };
+        impacts.markets.push(pnl::MarketImpact {
+            token_id: token_id.to_string(),
+            kyle_lambda_1h: lambda("5m", 300, 12),
+            kyle_lambda_24h: lambda("1h", 3600, 24),
+        });
+    }
+
+    Ok(impacts)
+}
+
+/// Trading style from behavioral features, checked in order: arbitrageur (one complete-set trade per
+/// four fills), market maker (at least 70% of fills as maker), then by average holding time of closed
+/// positions: scalper (under a day), swing (under a week), holder (longer, or nothing closed yet)
+fn trader_style(total_trades: i64, maker_fills: i64, arbitrage_count: i64, avg_holding_seconds: i64) -> &'static str {
+    if total_trades > 0 && arbitrage_count * 4 >= total_trades {
+        "arbitrageur"
+    } else if total_trades > 0 && maker_fills * 10 >= total_trades * 7 {
+        "market_maker"
+    } else if avg_holding_seconds > 0 && avg_holding_seconds < 86400 {
+        "scalper"
+    } else if avg_holding_seconds > 0 && avg_holding_seconds < 7 * 86400 {
+        "swing"
+    } else {
+        "holder"
+    }
+}
+
+/// Behavioral features and style label of users who traded or closed a position in the block
+#[substreams::handlers::map]
+fn map_user_profiles(
+    fills: pnl::OrderFills,
+    lifecycles: pnl::PositionLifecycles,
+    trade_count_store: StoreGetInt64,
+    volume_store: StoreGetBigInt,
+    maker_fills_store: StoreGetInt64,
+    neg_risk_volume_store: StoreGetBigInt,
+    first_trade_store: StoreGetInt64,
+    closed_positions_store: StoreGetInt64,
+    holding_seconds_store: StoreGetInt64,
+    arbitrage_count_store: StoreGetInt64,
+) -> Result<pnl::UserProfiles, substreams::errors::Error> {
+    let block_timestamp = fills
+        .block_timestamp
+        .or_else(|| lifecycles.lifecycles.iter().find_map(|l| l.closed_at));
+    let now = block_timestamp.as_ref().map(|t| t.seconds).unwrap_or(0);
+    let mut profiles = pnl::UserProfiles {
+        block_number: fills.block_number.max(lifecycles.block_number),
+        block_timestamp,
+        ..Default::default()
+    };
+
+    let mut users: std::collections::BTreeSet<String> = std::collections::BTreeSet::new();
     for fill in &fills.fills {
-        if !is_excluded_address(&fill.taker) {
-            affected_users.insert(fill.taker.to_lowercase());
-        }
-        if !is_excluded_address(&fill.maker) {
-            affected_users.insert(fill.maker.to_lowercase());
+        for user in [&fill.maker, &fill.taker] {
+            if !is_excluded_address(user) {
+                users.insert(user.to_lowercase());
+            }
         }
     }
-
-    // Generate updates for affected users
-    for user in affected_users {
-        let realized = realized_pnl_store
+    users.extend(
+        lifecycles
+            .lifecycles
+            .iter()
+            .filter(|l| l.closed_block != 0)
+            .map(|l| l.user_address.clone()),
+    );
+
+    for user in users {
+        let total_trades = trade_count_store.get_last(&user).unwrap_or(0);
+        let maker_fills = maker_fills_store.get_last(&user).unwrap_or(0);
+        let volume = volume_store.get_last(&user).unwrap_or_else(|| BigInt::from(0));
+        let neg_risk_volume = neg_risk_volume_store.get_last(&user).unwrap_or_else(|| BigInt::from(0));
+        let closed_positions = closed_positions_store.get_last(&user).unwrap_or(0);
+        let avg_holding_seconds = if closed_positions > 0 {
+            holding_seconds_store.get_last(&user).unwrap_or(0) / closed_positions
+        } else {
+            0
+        };
+        let active_seconds = first_trade_store
             .get_last(&user)
-            .map(|v| v.to_string())
-            .unwrap_or_else(|| "0".to_string());
+            .map(|first| now - first)
+            .unwrap_or(0)
+            .max(86400);
+        let avg_trade_size = if total_trades > 0 {
+            &volume / BigInt::from(total_trades)
+        } else {
+            BigInt::from(0)
+        };
+        let arbitrage_count = arbitrage_count_store.get_last(&user).unwrap_or(0);
+
+        profiles.profiles.push(pnl::UserProfile {
+            total_trades: total_trades as u64,
+            maker_share: ratio_decimal(&BigInt::from(maker_fills), &BigInt::from(total_trades)),
+            avg_holding_seconds,
+            trades_per_day: ratio_decimal(&BigInt::from(total_trades * 86400), &BigInt::from(active_seconds)),
+            avg_trade_size: avg_trade_size.to_string(),
+            neg_risk_volume_share: ratio_decimal(&neg_risk_volume, &volume),
+            style: trader_style(total_trades, maker_fills, arbitrage_count, avg_holding_seconds).to_string(),
+            user_address: user,
+        });
+    }
 
-        // Calculate unrealized P&L: sum((current_price - avg_entry_price) * quantity)
-        // for all open positions. We iterate through the positions_deltas to know which
-        // positions exist for this user, then use positions_store to get current values.
-        let mut unrealized_pnl_total = BigInt::from(0);
+    Ok(profiles)
+}
 
-        for delta in &positions_deltas.deltas {
-            let parts: Vec<&str> = delta.key.split(':').collect();
-            if parts.len() == 2 && parts[0] == user {
-                let token_id = parts[1];
+/// ln(1 + r) of a NAV move with external flows treated as arriving at the end of the block:
+/// r = (nav_end - net_flows) / nav_start - 1. Returns 0 when the return is undefined
+/// (no positive starting NAV, or the position was wiped out).
+fn nav_log_return(nav_start: &BigInt, nav_end: &BigInt, net_flows: &BigInt) -> f64 {
+    let start: f64 = nav_start.to_string().parse().unwrap_or(0.0);
+    let end: f64 = (nav_end - net_flows).to_string().parse().unwrap_or(0.0);
+    if start <= 0.0 || end <= 0.0 {
+        return 0.0;
+    }
+    (end / start).ln()
+}
 
-                // Get current position quantity from positions_store
-                let quantity = positions_store
-                    .get_last(&delta.key)
-                    .unwrap_or_else(|| BigInt::from(0));
+/// Portfolio NAV (cash + marked positions) of users whose cash, positions or deposits
+/// changed in the block, with the block's return net of deposits and withdrawals
+#[substreams::handlers::map]
+fn map_user_nav(
+    transfers: pnl::TokenTransfers,
+    cash_flows: pnl::UserCashFlows,
+    position_value_deltas: Deltas<DeltaBigInt>,
+    cash_balance_deltas: Deltas<DeltaBigInt>,
+    net_deposits_deltas: Deltas<DeltaBigInt>,
+    position_value_store: StoreGetBigInt,
+    cash_balance_store: StoreGetBigInt,
+    net_deposits_store: StoreGetBigInt,
+) -> Result<pnl::UserNavs, substreams::errors::Error> {
+    let mut navs = pnl::UserNavs {
+        block_number: transfers.block_number.max(cash_flows.block_number),
+        block_timestamp: transfers.block_timestamp.or(cash_flows.block_timestamp),
+        ..Default::default()
+    };
 
-                // Only calculate unrealized P&L for open positions (quantity > 0)
-                if quantity > BigInt::from(0) {
-                    // Get cost basis for this position
-                    let cost_basis = cost_basis_store
-                        .get_last(&delta.key)
-                        .unwrap_or_else(|| BigInt::from(0));
+    let users: std::collections::BTreeSet<&str> = position_value_deltas
+        .deltas
+        .iter()
+        .chain(&cash_balance_deltas.deltas)
+        .chain(&net_deposits_deltas.deltas)
+        .map(|d| d.key.as_str())
+        .collect();
+
+    for user in users {
+        let zero = BigInt::from(0);
+        let position_value = position_value_store.get_last(user).unwrap_or_else(|| zero.clone());
+        let cash_balance = cash_balance_store.get_last(user).unwrap_or_else(|| zero.clone());
+        let net_deposits = net_deposits_store.get_last(user).unwrap_or(zero);
+
+        let nav_start = block_start_value(&position_value_deltas, &position_value_store, user)
+            + block_start_value(&cash_balance_deltas, &cash_balance_store, user);
+        let nav = &position_value + &cash_balance;
+        let net_flows = &net_deposits - block_start_value(&net_deposits_deltas, &net_deposits_store, user);
+
+        navs.navs.push(pnl::UserNav {
+            user_address: user.to_string(),
+            log_return: nav_log_return(&nav_start, &nav, &net_flows),
+            nav: nav.to_string(),
+            cash_balance: cash_balance.to_string(),
+            position_value: position_value.to_string(),
+            net_deposits: net_deposits.to_string(),
+        });
+    }
 
-                    // Calculate average entry price
-                    let avg_entry_price = if !quantity.is_zero() {
-                        &cost_basis / &quantity
-                    } else {
-                        BigInt::from(0)
-                    };
+    Ok(navs)
+}
 
-                    // Get current price from prices_store
-                    if let Some(token_price) = prices_store.get_last(token_id) {
-                        let current_price = parse_price_decimal(&token_price.price);
+/// One user's trading in one token within a transaction
+#[derive(Default)]
+struct ArbitrageLeg {
+    bought_shares: BigInt,
+    bought_usdc: BigInt,
+    sold_shares: BigInt,
+    sold_proceeds: BigInt,
+}
 
-                        // Calculate unrealized P&L: (current_price - avg_entry_price) * quantity
-                        let position_pnl = (&current_price - &avg_entry_price) * &quantity;
-                        unrealized_pnl_total = unrealized_pnl_total + position_pnl;
-                    }
+/// Complete sets formed by (shares, usdc) legs and the profit locked in against the 1 USDC payout.
+/// Returns (sets, usdc for the sets, profit) when the set is profitable.
+fn set_arbitrage(legs: &[(&BigInt, &BigInt)], buying: bool) -> Option<(BigInt, BigInt, BigInt)> {
+    let zero = BigInt::from(0);
+    let sets = legs.iter().map(|(shares, _)| (*shares).clone()).min()?;
+    if sets <= zero {
+        return None;
+    }
+
+    // Each leg's USDC pro-rated to the shares that complete a set
+    let usdc = legs
+        .iter()
+        .fold(BigInt::from(0), |acc, (shares, usdc)| acc + (*usdc * &sets) / *shares);
+    let profit = if buying { &sets - &usdc } else { &usdc - &sets };
+
+    if profit > zero {
+        Some((sets, usdc, profit))
+    } else {
+        None
+    }
+}
+
+/// Detect users who buy every outcome of a condition (YES + NO) or of a NegRisk event
+/// (YES on every question) for less than 1 USDC per set within one transaction, or sell
+/// the set for more, and record the locked-in profit.
+#[substreams::handlers::map]
+fn map_arbitrage_trades(
+    fills: pnl::OrderFills,
+    market_tokens_store: StoreGetProto<pnl::MarketToken>,
+    neg_risk_tokens_store: StoreGetProto<pnl::NegRiskToken>,
+    question_count_store: StoreGetInt64,
+) -> Result<pnl::ArbitrageTrades, substreams::errors::Error> {
+    use std::collections::BTreeMap;
+
+    let mut arbitrage = pnl::ArbitrageTrades {
+        block_number: fills.block_number,
+        block_timestamp: fills.block_timestamp,
+        ..Default::default()
+    };
+
+    // (tx_hash, user) -> token -> leg
+    let mut activity: BTreeMap<(String, String), BTreeMap<String, ArbitrageLeg>> = BTreeMap::new();
+    for fill in &fills.fills {
+        let shares = order_shares(fill);
+        let usdc = BigInt::from_str(&fill.amount).unwrap_or_default();
+        let (buyer, seller) = fill_counterparties(fill);
+
+        for (user, buying) in [(buyer, true), (seller, false)] {
+            if is_excluded_address(&user) {
+                continue;
+            }
+            let leg = activity
+                .entry((fill.tx_hash.clone(), user))
+                .or_default()
+                .entry(fill.token_id.clone())
+                .or_default();
+            if buying {
+                leg.bought_shares += shares.clone();
+                leg.bought_usdc += usdc.clone();
+            } else {
+                leg.sold_shares += shares.clone();
+                leg.sold_proceeds += usdc.clone();
+            }
+        }
+    }
+
+    for ((tx_hash, user), legs) in activity {
+        // Candidate sets: YES + NO of a condition, and YES of every question of a NegRisk event
+        let mut sets: BTreeMap<(String, bool), Vec<&ArbitrageLeg>> = BTreeMap::new();
+        for (token_id, leg) in &legs {
+            if let Some(token) = market_tokens_store.get_last(token_id) {
+                sets.entry((token.condition_id, false)).or_default().push(leg);
+            }
+            if let Some(token) = neg_risk_tokens_store.get_last(token_id).filter(|t| t.is_yes) {
+                sets.entry((token.market_id, true)).or_default().push(leg);
+            }
+        }
+
+        for ((market_id, is_neg_risk), set_legs) in sets {
+            let outcomes = if is_neg_risk {
+                question_count_store.get_last(&market_id).unwrap_or(0)
+            } else {
+                2
+            };
+            if outcomes < 2 || set_legs.len() as i64 != outcomes {
+                continue;
+            }
+
+            let bought: Vec<_> = set_legs.iter().map(|l| (&l.bought_shares, &l.bought_usdc)).collect();
+            let sold: Vec<_> = set_legs.iter().map(|l| (&l.sold_shares, &l.sold_proceeds)).collect();
+
+            for (kind, result) in [("buy_set", set_arbitrage(&bought, true)), ("sell_set", set_arbitrage(&sold, false))] {
+                if let Some((sets, usdc, profit)) = result {
+                    arbitrage.trades.push(pnl::ArbitrageTrade {
+                        id: format!("{}-{}-{}-{}", tx_hash, user, market_id, kind),
+                        tx_hash: tx_hash.clone(),
+                        block_number: fills.block_number,
+                        user_address: user.clone(),
+                        market_id: market_id.clone(),
+                        is_neg_risk,
+                        kind: kind.to_string(),
+                        outcomes: outcomes as u32,
+                        sets: sets.to_string(),
+                        usdc: usdc.to_string(),
+                        profit: profit.to_string(),
+                    });
                 }
             }
         }
+    }
 
-        let total_volume = volume_store
-            .get_last(&user)
-            .map(|v| v.to_string())
-            .unwrap_or_else(|| "0".to_string());
+    Ok(arbitrage)
+}
 
-        let total_trades = trade_count_store
-            .get_last(&user)
-            .unwrap_or(0) as u64;
+/// Default window (blocks) for round-trip and circular wash detection, about one hour on Polygon
+const DEFAULT_WASH_WINDOW_BLOCKS: u64 = 1800;
 
-        let total_fees = calculate_user_fees(&fills, &user);
+/// Fill ids flagged as wash trades
+fn wash_trade_ids(wash_trades: &pnl::WashTrades) -> std::collections::HashSet<&str> {
+    wash_trades.trades.iter().map(|t| t.fill_id.as_str()).collect()
+}
 
-        let realized_bigint = BigInt::from_str(&realized).unwrap_or_default();
-        let total_pnl = &realized_bigint + &unrealized_pnl_total;
+/// Owner of an address: the Safe owner for proxy wallets, the address itself otherwise
+fn wallet_owner(address: &str, proxy_owners_store: &StoreGetString) -> String {
+    proxy_owners_store.get_last(address).unwrap_or_else(|| address.to_string())
+}
 
-        updates.updates.push(pnl::UserPnLUpdate {
-            user_address: user.clone(),
-            realized_pnl: realized,
-            unrealized_pnl: unrealized_pnl_total.to_string(),
-            total_pnl: total_pnl.to_string(),
-            total_volume,
-            total_trades,
-            total_fees_paid: total_fees.to_string(),
-            win_count: 0,
-            loss_count: 0,
-            win_rate: "0".to_string(),
-            max_drawdown: "0".to_string(),
-            largest_win: "0".to_string(),
-            largest_loss: "0".to_string(),
-            first_trade_at: None,
-            last_trade_at: Some(fills.block_timestamp.clone().unwrap_or_default()),
-            positions: vec![],
+/// Flag self-trades, same-owner trades, round trips (A -> B -> A) and three-way circular
+/// flows (A -> B -> C -> A) of the same token within the configured block window.
+/// Sales are read from store_last_sales, so ordering within a block is not considered.
+#[substreams::handlers::map]
+fn map_wash_trades(
+    params: String,
+    fills: pnl::OrderFills,
+    proxy_owners_store: StoreGetString,
+    last_sales_store: StoreGetProto<pnl::LastSale>,
+) -> Result<pnl::WashTrades, substreams::errors::Error> {
+    let window: u64 = param_value(&params, "window_blocks")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_WASH_WINDOW_BLOCKS);
+
+    let mut wash_trades = pnl::WashTrades {
+        block_number: fills.block_number,
+        ..Default::default()
+    };
+
+    // Latest sale of `seller` for this token if it happened within the window
+    let recent_buyer = |seller: &str, token_id: &str| -> Option<String> {
+        last_sales_store
+            .get_last(format!("{}:{}", seller, token_id))
+            .filter(|sale| fills.block_number.saturating_sub(sale.block_number) <= window)
+            .map(|sale| sale.buyer)
+    };
+
+    for fill in &fills.fills {
+        let (buyer, seller) = fill_counterparties(fill);
+        let internal = is_excluded_address(&buyer) || is_excluded_address(&seller);
+
+        let wash_flag = if buyer == seller {
+            Some("self_trade")
+        } else if internal {
+            None
+        } else if wallet_owner(&buyer, &proxy_owners_store) == wallet_owner(&seller, &proxy_owners_store) {
+            Some("same_owner")
+        } else {
+            // The buyer previously sold this token to the seller (A -> B, B -> A), or to a
+            // third party who sold it on to the seller (A -> B -> C, C -> A)
+            match recent_buyer(&buyer, &fill.token_id) {
+                Some(previous) if previous == seller => Some("round_trip"),
+                Some(previous) => recent_buyer(&previous, &fill.token_id)
+                    .filter(|next| *next == seller)
+                    .map(|_| "circular"),
+                None => None,
+            }
+        };
+
+        if let Some(wash_flag) = wash_flag {
+            wash_trades.trades.push(pnl::WashTrade {
+                fill_id: fill.id.clone(),
+                wash_flag: wash_flag.to_string(),
+                buyer,
+                seller,
+                token_id: fill.token_id.clone(),
+                amount: fill.amount.clone(),
+            });
+        }
+    }
+
+    Ok(wash_trades)
+}
+
+/// Roll positions up to (user, condition_id), combining the YES and NO legs.
+/// Unrealized P&L marks the shares still held against their average entry price.
+#[substreams::handlers::map]
+fn map_user_market_pnl(
+    fills: pnl::OrderFills,
+    positions_deltas: Deltas<DeltaBigInt>,
+    position_realized_pnl_deltas: Deltas<DeltaBigInt>,
+    positions_store: StoreGetBigInt,
+    buy_cost_store: StoreGetBigInt,
+    bought_shares_store: StoreGetBigInt,
+    position_realized_pnl_store: StoreGetBigInt,
+    prices_store: StoreGetProto<pnl::TokenPrice>,
+    market_tokens_store: StoreGetProto<pnl::MarketToken>,
+) -> Result<pnl::UserMarketPnLs, substreams::errors::Error> {
+    let mut updates = pnl::UserMarketPnLs {
+        block_number: fills.block_number,
+        block_timestamp: fills.block_timestamp,
+        ..Default::default()
+    };
+
+    // Affected (user, condition) pairs, resolved to their YES / NO tokens
+    let mut markets: std::collections::BTreeMap<(String, String), (String, String)> = std::collections::BTreeMap::new();
+    for delta in positions_deltas.deltas.iter().chain(&position_realized_pnl_deltas.deltas) {
+        if let Some((user, token_id)) = delta.key.split_once(':') {
+            if let Some(token) = market_tokens_store.get_last(token_id) {
+                let legs = if token.is_yes {
+                    (token.token_id, token.complement_token_id)
+                } else {
+                    (token.complement_token_id, token.token_id)
+                };
+                markets.insert((user.to_string(), token.condition_id), legs);
+            }
+        }
+    }
+
+    // Conditions to re-rank in market_leaderboard: changed positions plus repriced tokens
+    let mut condition_ids: std::collections::BTreeSet<String> = markets.keys().map(|(_, c)| c.clone()).collect();
+    for fill in &fills.fills {
+        if let Some(token) = market_tokens_store.get_last(&fill.token_id) {
+            condition_ids.insert(token.condition_id);
+        }
+    }
+    updates.condition_ids = condition_ids.into_iter().collect();
+
+    let zero = BigInt::from(0);
+    let scale = BigInt::from_str("1000000000000000000").unwrap(); // 10^18
+    for ((user, condition_id), (yes_token_id, no_token_id)) in markets {
+        let mut quantities = Vec::with_capacity(2);
+        let mut cost_basis = BigInt::from(0);
+        let mut realized = BigInt::from(0);
+        let mut value = BigInt::from(0);
+
+        for token_id in [&yes_token_id, &no_token_id] {
+            let key = format!("{}:{}", user, token_id);
+            let quantity = positions_store.get_last(&key).unwrap_or_else(|| BigInt::from(0));
+            // Cost of the shares still held: average entry price x long quantity
+            let avg = avg_entry_price(
+                &buy_cost_store.get_last(&key).unwrap_or_else(|| BigInt::from(0)),
+                &bought_shares_store.get_last(&key).unwrap_or_else(|| BigInt::from(0)),
+            );
+            cost_basis += long_quantity(&quantity) * avg / &scale;
+            realized += position_realized_pnl_store.get_last(&key).unwrap_or_else(|| BigInt::from(0));
+            if let Some(price) = prices_store.get_last(token_id) {
+                value += market_value(&long_quantity(&quantity), &price.price);
+            }
+            quantities.push(quantity);
+        }
+
+        let (yes_quantity, no_quantity) = (&quantities[0], &quantities[1]);
+        let complete_sets = if yes_quantity < no_quantity {
+            long_quantity(yes_quantity)
+        } else {
+            long_quantity(no_quantity)
+        };
+
+        updates.updates.push(pnl::UserMarketPnL {
+            user_address: user,
+            condition_id,
+            yes_token_id,
+            no_token_id,
+            yes_quantity: yes_quantity.to_string(),
+            no_quantity: no_quantity.to_string(),
+            net_exposure: (yes_quantity - no_quantity).to_string(),
+            unrealized_pnl: (&value - &cost_basis).to_string(),
+            cost_basis: cost_basis.to_string(),
+            realized_pnl: format_usdc_from_bigint(&realized),
+            is_hedged: complete_sets > zero,
+            complete_sets: complete_sets.to_string(),
         });
     }
 
@@ -583,10 +3903,272 @@ fn map_market_stats(
     Ok(stats)
 }
 
+/// State of an order from the maker amount filled so far and the order's size (None when the
+/// filling call could not be decoded): filled only once the whole size is filled.
+fn order_status(filled: &BigInt, size: Option<&BigInt>, cancelled: bool) -> &'static str {
+    if filled.is_zero() {
+        if cancelled {
+            "cancelled"
+        } else {
+            "open"
+        }
+    } else if size.is_some_and(|size| filled >= size) {
+        "filled"
+    } else {
+        "partially_filled"
+    }
+}
+
+/// Aggregate partial fills per order_hash and apply cancellations
+#[substreams::handlers::map]
+fn map_orders(
+    fills: pnl::OrderFills,
+    cancellations: pnl::OrderCancellations,
+    orders_store: StoreGetProto<pnl::Order>,
+    shares_store: StoreGetBigInt,
+    usdc_store: StoreGetBigInt,
+    fill_count_store: StoreGetInt64,
+) -> Result<pnl::Orders, substreams::errors::Error> {
+    let mut orders = pnl::Orders {
+        block_number: fills.block_number,
+        block_timestamp: fills.block_timestamp,
+        ..Default::default()
+    };
+
+    let cancelled: std::collections::HashSet<&str> = cancellations
+        .cancellations
+        .iter()
+        .map(|c| c.order_hash.as_str())
+        .collect();
+
+    let mut order_hashes: std::collections::BTreeSet<String> = std::collections::BTreeSet::new();
+    for fill in &fills.fills {
+        if !fill.order_hash.is_empty() {
+            order_hashes.insert(fill.order_hash.clone());
+        }
+    }
+    for cancellation in &cancellations.cancellations {
+        order_hashes.insert(cancellation.order_hash.clone());
+    }
+
+    for order_hash in order_hashes {
+        let mut order = orders_store.get_last(&order_hash).unwrap_or_else(|| pnl::Order {
+            order_hash: order_hash.clone(),
+            ..Default::default()
+        });
+
+        let shares = shares_store.get_last(&order_hash).unwrap_or_else(|| BigInt::from(0));
+        let usdc = usdc_store.get_last(&order_hash).unwrap_or_else(|| BigInt::from(0));
+        let fill_count = fill_count_store.get_last(&order_hash).unwrap_or(0);
+        let is_cancelled = cancelled.contains(order_hash.as_str());
+
+        if let Some(cancellation) = cancellations.cancellations.iter().find(|c| c.order_hash == order_hash) {
+            order.cancelled_block = cancellation.block_number;
+            if order.exchange.is_empty() {
+                order.exchange = cancellation.exchange.clone();
+            }
+        }
+        if fills.fills.iter().any(|f| f.order_hash == order_hash) {
+            order.last_fill_block = fills.block_number;
+        }
+
+        order.vwap = format_price_decimal(&usdc, &shares);
+        order.filled_shares = shares.to_string();
+        order.filled_usdc = usdc.to_string();
+        order.fill_count = fill_count as u64;
+        // Sizes are in the maker asset: USDC for buy orders, outcome tokens for sell orders
+        let filled = if order.side == "buy" { &usdc } else { &shares };
+        let size = BigInt::from_str(&order.size).ok();
+        order.status = order_status(filled, size.as_ref(), is_cancelled).to_string();
+
+        orders.orders.push(order);
+    }
+
+    Ok(orders)
+}
+
+/// Deposit cost attributed to burnt LP shares, using the average cost per minted share
+fn lp_cost_removed(shares_burnt: &BigInt, deposited: &BigInt, minted: &BigInt) -> BigInt {
+    if minted.is_zero() {
+        return BigInt::from(0);
+    }
+    (shares_burnt * deposited) / minted
+}
+
+/// Compute LP position updates and realized liquidity P&L per funding event
+#[substreams::handlers::map]
+fn map_lp_pnl(
+    funding: pnl::FpmmFundingEvents,
+    shares_store: StoreGetBigInt,
+    deposits_store: StoreGetBigInt,
+    minted_store: StoreGetBigInt,
+) -> Result<pnl::LpPositionUpdates, substreams::errors::Error> {
+    let mut updates = pnl::LpPositionUpdates {
+        block_number: funding.block_number,
+        ..Default::default()
+    };
+
+    for event in funding.events {
+        let user = event.funder.to_lowercase();
+        let pool = event.pool_address.to_lowercase();
+        let key = format!("{}:{}", user, pool);
+
+        let shares = shares_store.get_last(&key).unwrap_or_else(|| BigInt::from(0));
+        let deposited = deposits_store.get_last(&key).unwrap_or_else(|| BigInt::from(0));
+
+        let mut update = pnl::LpPositionUpdate {
+            user_address: user,
+            pool_address: pool,
+            shares: shares.to_string(),
+            usdc_deposited: deposited.to_string(),
+            fees_earned: "0".to_string(),
+            removed_value: "0".to_string(),
+            cost_removed: "0".to_string(),
+            realized_pnl: "0".to_string(),
+            timestamp: event.timestamp,
+        };
+
+        if event.kind == "removed" {
+            let minted = minted_store.get_last(&key).unwrap_or_else(|| BigInt::from(0));
+            let shares_burnt = BigInt::from_str(&event.shares).unwrap_or_default();
+            let fees = BigInt::from_str(&event.fees_withdrawn).unwrap_or_default();
+            let removed_value = BigInt::from_str(&event.removed_value).unwrap_or_default();
+
+            let cost_removed = lp_cost_removed(&shares_burnt, &deposited, &minted);
+            let realized = &fees + &removed_value - &cost_removed;
+
+            update.fees_earned = fees.to_string();
+            update.removed_value = removed_value.to_string();
+            update.cost_removed = cost_removed.to_string();
+            update.realized_pnl = realized.to_string();
+        }
+
+        updates.updates.push(update);
+    }
+
+    Ok(updates)
+}
+
+/// Format a signed 18-decimal value, e.g. -0.05 -> "-0.050000000000000000"
+fn format_signed_price(value: &BigInt) -> String {
+    let one = BigInt::from_str("1000000000000000000").unwrap();
+    if value < &BigInt::from(0) {
+        format!("-{}", format_price_decimal(&(BigInt::from(0) - value), &one))
+    } else {
+        format_price_decimal(value, &one)
+    }
+}
+
+/// Aggregate NegRisk stats per event (market group) across all of its outcomes
+#[substreams::handlers::map]
+fn map_neg_risk_event_stats(
+    fills: pnl::OrderFills,
+    volume_deltas: Deltas<DeltaBigInt>,
+    open_interest_deltas: Deltas<DeltaBigInt>,
+    price_sum_deltas: Deltas<DeltaBigInt>,
+    user_pnl_deltas: Deltas<DeltaBigInt>,
+    volume_store: StoreGetBigInt,
+    open_interest_store: StoreGetBigInt,
+    price_sum_store: StoreGetBigInt,
+) -> Result<pnl::NegRiskEventStats, substreams::errors::Error> {
+    let mut stats = pnl::NegRiskEventStats {
+        block_number: fills.block_number,
+        block_timestamp: fills.block_timestamp,
+        ..Default::default()
+    };
+
+    // Realized P&L changes per event, and per-user totals (both converted to USDC 6 decimals)
+    let mut pnl_changes: std::collections::BTreeMap<String, BigInt> = std::collections::BTreeMap::new();
+    for delta in &user_pnl_deltas.deltas {
+        if let Some((market_id, user)) = delta.key.split_once(':') {
+            let change = pnl_changes.entry(market_id.to_string()).or_insert_with(|| BigInt::from(0));
+            *change = &*change + (&delta.new_value - &delta.old_value);
+
+            stats.users.push(pnl::NegRiskEventUserPnL {
+                market_id: market_id.to_string(),
+                user_address: user.to_string(),
+                realized_pnl: format_usdc_from_bigint(&delta.new_value),
+            });
+        }
+    }
+
+    let mut markets: std::collections::BTreeSet<String> = pnl_changes.keys().cloned().collect();
+    for delta in volume_deltas
+        .deltas
+        .iter()
+        .chain(&open_interest_deltas.deltas)
+        .chain(&price_sum_deltas.deltas)
+    {
+        markets.insert(delta.key.clone());
+    }
+
+    for market_id in markets {
+        let volume = volume_store.get_last(&market_id).unwrap_or_else(|| BigInt::from(0));
+        let open_interest = open_interest_store.get_last(&market_id).unwrap_or_else(|| BigInt::from(0));
+        let price_sum = price_sum_store.get_last(&market_id).unwrap_or_else(|| BigInt::from(0));
+        let overround = &price_sum - BigInt::from_str("1000000000000000000").unwrap();
+        let pnl_change = pnl_changes.get(&market_id).cloned().unwrap_or_else(|| BigInt::from(0));
+
+        stats.events.push(pnl::NegRiskEventStat {
+            total_volume: volume.to_string(),
+            open_interest: open_interest.to_string(),
+            yes_price_sum: format_signed_price(&price_sum),
+            overround: format_signed_price(&overround),
+            realized_pnl_change: format_usdc_from_bigint(&pnl_change),
+            market_id,
+        });
+    }
+
+    Ok(stats)
+}
+
+/// Store user liquidity P&L: key = {user}, value = realized LP P&L delta (6 decimals)
+#[substreams::handlers::store]
+fn store_user_lp_pnl(lp_updates: pnl::LpPositionUpdates, store: StoreAddBigInt) {
+    for update in lp_updates.updates {
+        let realized = BigInt::from_str(&update.realized_pnl).unwrap_or_default();
+        if !realized.is_zero() {
+            store.add(0, &update.user_address, &realized);
+        }
+    }
+}
+
 //==============================================
 // LAYER 4: SQL Sink
 //==============================================
 
+/// Default number of winners and losers kept per condition in market_leaderboard
+const DEFAULT_TOP_LEADERBOARD: i64 = 100;
+
+/// Periods user_period_pnl is bucketed by
+const PNL_PERIODS: [&str; 3] = ["day", "week", "month"];
+
+/// Unix start of the UTC day, ISO week (Monday) or month containing `secs`
+fn period_start(secs: i64, period: &str) -> i64 {
+    let day_start = secs - secs.rem_euclid(86400);
+    match period {
+        // 1970-01-01 was a Thursday, three days after the Monday that starts its ISO week
+        "week" => day_start - (secs.div_euclid(86400) + 3).rem_euclid(7) * 86400,
+        "month" => {
+            let day_of_month: i64 = unix_to_timestamp(secs)[8..10].parse().unwrap_or(1);
+            day_start - (day_of_month - 1) * 86400
+        }
+        _ => day_start,
+    }
+}
+
+/// Default number of holders listed per market in market_top_holders
+const DEFAULT_TOP_HOLDERS: i64 = 100;
+
+/// Look up a `key=value` entry in `&`-separated module params
+fn param_value<'a>(params: &'a str, key: &str) -> Option<&'a str> {
+    params
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(k, _)| k.trim() == key)
+        .map(|(_, v)| v.trim())
+}
+
 /// Output database changes for SQL sink
 #[substreams::handlers::map]
 fn db_out(
@@ -597,19 +4179,58 @@ fn db_out(
     positions_deltas: Deltas<DeltaBigInt>,
     positions_store: StoreGetBigInt,
     cost_basis_store: StoreGetBigInt,
+    buy_cost_store: StoreGetBigInt,
+    bought_shares_store: StoreGetBigInt,
     prices_store: StoreGetProto<pnl::TokenPrice>,
     realized_pnl_deltas: Deltas<DeltaBigInt>,
     volume_deltas: Deltas<DeltaBigInt>,
     trade_count_deltas: Deltas<DeltaInt64>,
+    lp_updates: pnl::LpPositionUpdates,
+    lp_pnl_deltas: Deltas<DeltaBigInt>,
+    registry: pnl::MarketRegistry,
+    neg_risk_events: pnl::NegRiskEvents,
+    neg_risk_stats: pnl::NegRiskEventStats,
+    orders: pnl::Orders,
+    exchange_events: pnl::ExchangeEvents,
+    paused_deltas: Deltas<DeltaInt64>,
+    enriched_fills: pnl::EnrichedFills,
+    lifecycles: pnl::PositionLifecycles,
+    position_realized_pnl_deltas: Deltas<DeltaBigInt>,
+    user_market_pnl: pnl::UserMarketPnLs,
+    market_holders: pnl::MarketHolders,
+    open_interest: pnl::OpenInterests,
+    resolutions: pnl::ConditionResolutions,
+    wash_trades: pnl::WashTrades,
+    user_clean_volume_deltas: Deltas<DeltaBigInt>,
+    market_clean_volume_deltas: Deltas<DeltaBigInt>,
+    arbitrage: pnl::ArbitrageTrades,
+    arbitrage_profit_deltas: Deltas<DeltaBigInt>,
+    cash_flows: pnl::UserCashFlows,
+    cash_balance_deltas: Deltas<DeltaBigInt>,
+    navs: pnl::UserNavs,
+    log_return_store: StoreGetFloat64,
+    profiles: pnl::UserProfiles,
+    smart_flows: pnl::SmartFlows,
+    suspicious: pnl::SuspiciousPositions,
+    alerts: pnl::Alerts,
+    price_impacts: pnl::PriceImpacts,
 ) -> Result<DatabaseChanges, substreams::errors::Error> {
     let mut tables = Tables::new();
 
     // Parse params
-    let min_trade_size: i64 = params
-        .split('=')
-        .nth(1)
+    let min_trade_size: i64 = param_value(&params, "min_trade_size")
         .and_then(|v| v.parse().ok())
         .unwrap_or(0);
+    let top_holders: i64 = param_value(&params, "top_holders")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_TOP_HOLDERS);
+    let top_leaderboard: i64 = param_value(&params, "top_leaderboard")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_TOP_LEADERBOARD);
+    let nav_bucket_seconds = match param_value(&params, "nav_bucket") {
+        Some("hour") => 3600,
+        _ => 86400,
+    };
 
     // Insert trades
     for fill in &fills.fills {
@@ -626,7 +4247,7 @@ fn db_out(
             .map(|t| unix_to_timestamp(t.seconds))
             .unwrap_or_else(|| "1970-01-01 00:00:00".to_string());
 
-        tables
+        let row = tables
             .create_row("trades", &fill.id)
             .set("block_number", fill.block_number)
             .set("block_timestamp", &timestamp)
@@ -641,6 +4262,27 @@ fn db_out(
             .set("fee", &fill.fee)
             .set("exchange", &fill.exchange)
             .set("order_hash", &fill.order_hash);
+
+        if let Some(wash) = wash_trades.trades.iter().find(|w| w.fill_id == fill.id) {
+            row.set("wash_flag", &wash.wash_flag);
+        }
+
+        // Per-fill attribution: what this fill did to the trader's position and P&L
+        if let Some(enriched) = enriched_fills.fills.iter().find(|e| e.fill_id == fill.id) {
+            row.set("trader", &enriched.trader)
+                .set("position_before", &enriched.position_before)
+                .set("position_after", &enriched.position_after)
+                .set("avg_entry_price", &enriched.avg_entry_price)
+                .set("realized_pnl", &enriched.realized_pnl)
+                .set("position_action", &enriched.position_action);
+        }
+
+        // Price impact against the token's previous price
+        if let Some(impact) = price_impacts.fills.iter().find(|i| i.fill_id == fill.id) {
+            row.set("price_before", &impact.price_before)
+                .set("price_change", &impact.price_change)
+                .set("slippage_bps", &impact.slippage_bps);
+        }
     }
 
     // Upsert user P&L using delta operations for efficiency
@@ -667,6 +4309,11 @@ fn db_out(
         affected_users.insert(update.user_address.clone());
     }
 
+    // Track liquidity providers from lp_pnl deltas
+    for delta in &lp_pnl_deltas.deltas {
+        affected_users.insert(delta.key.clone());
+    }
+
     // Get timestamp for first_trade_at and last_trade_at
     let timestamp = fills.block_timestamp
         .as_ref()
@@ -695,6 +4342,10 @@ fn db_out(
         if let Some(update) = user_pnl.updates.iter().find(|u| u.user_address == user) {
             row = row.set("unrealized_pnl", &update.unrealized_pnl);
             row = row.set("total_pnl", &update.total_pnl);
+            row = row.set("capital_deployed", &update.capital_deployed);
+            row = row.set("usdc_spent", &update.usdc_spent);
+            row = row.set("roi", &update.roi);
+            row = row.set("return_per_volume", &update.return_per_volume);
         }
 
         // total_volume: use .add() to send only the change
@@ -716,6 +4367,14 @@ fn db_out(
             row = row.add("total_trades", delta.ordinal as i64);
         }
 
+        // liquidity_pnl: use .add() (already in USDC 6-decimal format)
+        if let Some(delta) = lp_pnl_deltas.deltas.iter().find(|d| d.key == user) {
+            let lp_delta = &delta.new_value - &delta.old_value;
+            if let Ok(lp_i64) = lp_delta.to_string().parse::<i64>() {
+                row = row.add("liquidity_pnl", lp_i64);
+            }
+        }
+
         // total_fees_paid: calculate from fills in this block and use .add()
         let fees_delta = calculate_user_fees(&fills, &user);
         if fees_delta > BigInt::from(0) {
@@ -746,89 +4405,745 @@ fn db_out(
         row = row.set_if_null("win_rate", "0");
     }
 
-    // Upsert user positions
-    // We use positions_deltas to know which positions changed, then get full data from stores
-    for delta in &positions_deltas.deltas {
-        let parts: Vec<&str> = delta.key.split(':').collect();
-        if parts.len() == 2 {
-            let user_address = parts[0];
-            let token_id = parts[1];
-            let position_id = delta.key.clone();
+    // Period P&L: realized deltas and volume accumulate per day / ISO week / month,
+    // unrealized is the latest mark in the period (period start mark filled by trigger)
+    if let Some(ts) = fills.block_timestamp.as_ref() {
+        let mut period_users: std::collections::BTreeSet<&str> = std::collections::BTreeSet::new();
+        period_users.extend(realized_pnl_deltas.deltas.iter().map(|d| d.key.as_str()));
+        period_users.extend(volume_deltas.deltas.iter().map(|d| d.key.as_str()));
+        period_users.extend(user_pnl.updates.iter().map(|u| u.user_address.as_str()));
+
+        for user in period_users {
+            let realized = realized_pnl_deltas
+                .deltas
+                .iter()
+                .filter(|d| d.key == user)
+                .fold(BigInt::from(0), |acc, d| acc + (&d.new_value - &d.old_value));
+            let volume = volume_deltas
+                .deltas
+                .iter()
+                .filter(|d| d.key == user)
+                .fold(BigInt::from(0), |acc, d| acc + (&d.new_value - &d.old_value));
+            let trades = trade_count_deltas
+                .deltas
+                .iter()
+                .filter(|d| d.key == user)
+                .map(|d| d.new_value - d.old_value)
+                .sum::<i64>();
+            let unrealized = user_pnl.updates.iter().find(|u| u.user_address == user).map(|u| {
+                format_usdc_from_bigint(&BigInt::from_str(&u.unrealized_pnl).unwrap_or_default())
+            });
+
+            for period in PNL_PERIODS {
+                let start = period_start(ts.seconds, period);
+                let mut row = tables
+                    .update_row("user_period_pnl", format!("{}:{}:{}", user, period, start))
+                    .set("user_address", user)
+                    .set("period", period)
+                    .set("period_start", unix_to_timestamp(start))
+                    .add("realized_pnl", format_usdc_from_bigint(&realized))
+                    .add("volume", volume.to_string())
+                    .add("trades", trades)
+                    .set("block_number", fills.block_number);
+                if let Some(unrealized) = &unrealized {
+                    row = row.set("unrealized_pnl", unrealized);
+                }
+            }
+        }
+    }
+
+    // Upsert user positions
+    // We use positions_deltas to know which positions changed, then get full data from stores
+    for delta in &positions_deltas.deltas {
+        let parts: Vec<&str> = delta.key.split(':').collect();
+        if parts.len() == 2 {
+            let user_address = parts[0];
+            let token_id = parts[1];
+            let position_id = delta.key.clone();
+
+            // Get current position data
+            let quantity = positions_store
+                .get_last(&delta.key)
+                .unwrap_or_else(|| BigInt::from(0));
+
+            let cost_basis = cost_basis_store
+                .get_last(&delta.key)
+                .unwrap_or_else(|| BigInt::from(0));
+
+            // Average entry price of the position's buys (18 decimals)
+            let avg_entry = avg_entry_price(
+                &buy_cost_store.get_last(&delta.key).unwrap_or_else(|| BigInt::from(0)),
+                &bought_shares_store.get_last(&delta.key).unwrap_or_else(|| BigInt::from(0)),
+            );
+            let avg_entry_price = format_signed_price(&avg_entry);
+
+            // Get current price
+            let current_price = prices_store
+                .get_last(token_id)
+                .map(|p| p.price.clone())
+                .unwrap_or_else(|| "0.000000000000000000".to_string());
+
+            // Current value and unrealized P&L: (current_price - avg_entry_price) * quantity, in USDC (6 decimals)
+            let scale = BigInt::from_str("1000000000000000000").unwrap(); // 10^18
+            let current_value = market_value(&quantity, &current_price);
+            let unrealized_pnl = (parse_price_decimal(&current_price) - &avg_entry) * &quantity / &scale;
+
+            // Get timestamp from block
+            let timestamp = fills.block_timestamp
+                .as_ref()
+                .map(|t| unix_to_timestamp(t.seconds))
+                .unwrap_or_else(|| "1970-01-01 00:00:00".to_string());
+
+            tables
+                .update_row("user_positions", &position_id)
+                .set("user_address", user_address)
+                .set("token_id", token_id)
+                .set("quantity", quantity.to_string())
+                .set("avg_entry_price", avg_entry_price)
+                .set("total_cost_basis", cost_basis.to_string())
+                .set("unrealized_pnl", unrealized_pnl.to_string())
+                .set("current_price", current_price)
+                .set("current_value", current_value.to_string())
+                .set("last_updated_at", &timestamp);
+        }
+    }
+
+    // Per-position realized P&L (18-decimal store value converted to USDC 6 decimals)
+    for delta in &position_realized_pnl_deltas.deltas {
+        tables
+            .update_row("user_positions", &delta.key)
+            .set("realized_pnl", format_usdc_from_bigint(&delta.new_value));
+    }
+
+    // Condition-level user P&L (YES + NO legs combined)
+    let market_pnl_timestamp = user_market_pnl
+        .block_timestamp
+        .as_ref()
+        .map(|t| unix_to_timestamp(t.seconds))
+        .unwrap_or_else(|| "1970-01-01 00:00:00".to_string());
+
+    for update in &user_market_pnl.updates {
+        tables
+            .update_row("user_market_pnl", format!("{}:{}", update.user_address, update.condition_id))
+            .set("user_address", &update.user_address)
+            .set("condition_id", &update.condition_id)
+            .set("yes_token_id", &update.yes_token_id)
+            .set("no_token_id", &update.no_token_id)
+            .set("yes_quantity", &update.yes_quantity)
+            .set("no_quantity", &update.no_quantity)
+            .set("net_exposure", &update.net_exposure)
+            .set("cost_basis", &update.cost_basis)
+            .set("realized_pnl", &update.realized_pnl)
+            .set("unrealized_pnl", &update.unrealized_pnl)
+            .set("complete_sets", &update.complete_sets)
+            .set("is_hedged", update.is_hedged)
+            .set("last_updated_at", &market_pnl_timestamp);
+    }
+
+    // Holder concentration (after user_positions: the market_holders trigger sums the top-10 share from it)
+    let holders_timestamp = market_holders
+        .block_timestamp
+        .as_ref()
+        .map(|t| unix_to_timestamp(t.seconds))
+        .unwrap_or_else(|| "1970-01-01 00:00:00".to_string());
+
+    for stats in &market_holders.markets {
+        tables
+            .update_row("market_holders", &stats.token_id)
+            .set("holder_count", stats.holder_count)
+            .set("total_shares", &stats.total_shares)
+            .set("hhi", &stats.hhi)
+            .set("top_n", top_holders)
+            .set("last_updated_at", &holders_timestamp);
+    }
+
+    // Upsert market stats
+    for stat in market_stats.stats {
+        tables
+            .update_row("markets", &stat.token_id)
+            .set("total_volume", &stat.total_volume)
+            .set("current_price", &stat.current_price);
+    }
+
+    // Rolling price impact coefficients of traded tokens
+    for impact in &price_impacts.markets {
+        tables
+            .update_row("markets", &impact.token_id)
+            .set("kyle_lambda_1h", &impact.kyle_lambda_1h)
+            .set("kyle_lambda_24h", &impact.kyle_lambda_24h);
+    }
+
+    // Arbitrage: locked-in set profits per transaction, running total per user
+    let arbitrage_timestamp = arbitrage
+        .block_timestamp
+        .as_ref()
+        .map(|t| unix_to_timestamp(t.seconds))
+        .unwrap_or_else(|| "1970-01-01 00:00:00".to_string());
+
+    for trade in &arbitrage.trades {
+        tables
+            .create_row("arbitrage_trades", &trade.id)
+            .set("tx_hash", &trade.tx_hash)
+            .set("block_number", trade.block_number)
+            .set("block_timestamp", &arbitrage_timestamp)
+            .set("user_address", &trade.user_address)
+            .set("market_id", &trade.market_id)
+            .set("is_neg_risk", trade.is_neg_risk)
+            .set("kind", &trade.kind)
+            .set("outcomes", trade.outcomes)
+            .set("sets", &trade.sets)
+            .set("usdc", &trade.usdc)
+            .set("profit", &trade.profit);
+    }
+    for delta in &arbitrage_profit_deltas.deltas {
+        tables
+            .update_row("user_pnl", &delta.key)
+            .set("total_arbitrage_profit", delta.new_value.to_string());
+    }
+
+    // USDC cash flows and balances
+    let cash_flow_timestamp = cash_flows
+        .block_timestamp
+        .as_ref()
+        .map(|t| unix_to_timestamp(t.seconds))
+        .unwrap_or_else(|| "1970-01-01 00:00:00".to_string());
+
+    for flow in &cash_flows.flows {
+        tables
+            .create_row("user_cash_flows", &flow.id)
+            .set("tx_hash", &flow.tx_hash)
+            .set("log_index", flow.log_index)
+            .set("block_number", flow.block_number)
+            .set("block_timestamp", &cash_flow_timestamp)
+            .set("user_address", &flow.user_address)
+            .set("counterparty", &flow.counterparty)
+            .set("flow_type", &flow.flow_type)
+            .set("amount", &flow.amount);
+    }
+    for delta in &cash_balance_deltas.deltas {
+        tables
+            .update_row("user_pnl", &delta.key)
+            .set("cash_balance", delta.new_value.to_string());
+    }
+
+    // Portfolio NAV history: one row per user and bucket (last block of the bucket wins)
+    if let Some(ts) = navs.block_timestamp.as_ref() {
+        let bucket_start = ts.seconds - ts.seconds.rem_euclid(nav_bucket_seconds);
+        let bucket = unix_to_timestamp(bucket_start);
+
+        for nav in &navs.navs {
+            let twr = log_return_store.get_last(&nav.user_address).unwrap_or(0.0).exp_m1();
+            tables
+                .update_row("user_nav_history", format!("{}:{}", nav.user_address, bucket_start))
+                .set("user_address", &nav.user_address)
+                .set("bucket_start", &bucket)
+                .set("nav", &nav.nav)
+                .set("cash_balance", &nav.cash_balance)
+                .set("position_value", &nav.position_value)
+                .set("net_deposits", &nav.net_deposits)
+                .set("time_weighted_return", format!("{:.8}", twr))
+                .set("block_number", navs.block_number);
+        }
+    }
+
+    // Smart money flow: rolling windows per token and threshold crossings
+    let smart_flow_timestamp = smart_flows
+        .block_timestamp
+        .as_ref()
+        .map(|t| unix_to_timestamp(t.seconds))
+        .unwrap_or_else(|| "1970-01-01 00:00:00".to_string());
+
+    for flow in &smart_flows.flows {
+        tables
+            .update_row("market_smart_flow", &flow.token_id)
+            .set("flow_1h", &flow.flow_1h)
+            .set("flow_24h", &flow.flow_24h)
+            .set("block_number", smart_flows.block_number)
+            .set("last_updated_at", &smart_flow_timestamp);
+    }
+    for alert in &smart_flows.alerts {
+        tables
+            .create_row(
+                "smart_flow_alerts",
+                format!("{}:{}:{}", alert.token_id, alert.window, smart_flows.block_number),
+            )
+            .set("token_id", &alert.token_id)
+            .set("flow_window", &alert.window)
+            .set("flow", &alert.flow)
+            .set("threshold", &alert.threshold)
+            .set("direction", &alert.direction)
+            .set("block_number", smart_flows.block_number)
+            .set("block_timestamp", &smart_flow_timestamp);
+    }
+
+    // Fresh-wallet positions (the condition_resolutions trigger fills time to resolution and outcome)
+    let suspicious_timestamp = suspicious
+        .block_timestamp
+        .as_ref()
+        .map(|t| unix_to_timestamp(t.seconds))
+        .unwrap_or_else(|| "1970-01-01 00:00:00".to_string());
+
+    for position in &suspicious.positions {
+        tables
+            .update_row("suspicious_positions", format!("{}:{}", position.user_address, position.condition_id))
+            .set("user_address", &position.user_address)
+            .set("condition_id", &position.condition_id)
+            .set("token_id", &position.token_id)
+            .set("first_trade_at", unix_to_timestamp(position.first_trade_at))
+            .set("wallet_age_seconds", position.wallet_age_seconds)
+            .set("position_usdc", &position.position_usdc)
+            .set("market_volume", &position.market_volume)
+            .set("market_share", &position.market_share)
+            .set("concentration", &position.concentration)
+            .set("last_buy_block", suspicious.block_number)
+            .set("last_buy_at", &suspicious_timestamp);
+    }
+
+    // Rule-based alerts
+    for alert in &alerts.alerts {
+        let row = tables
+            .create_row("alerts", &alert.id)
+            .set("alert_type", &alert.alert_type)
+            .set("block_number", alert.block_number)
+            .set(
+                "block_timestamp",
+                alert
+                    .timestamp
+                    .as_ref()
+                    .map(|t| unix_to_timestamp(t.seconds))
+                    .unwrap_or_else(|| "1970-01-01 00:00:00".to_string()),
+            )
+            .set("tx_hash", &alert.tx_hash)
+            .set("token_id", &alert.token_id)
+            .set("condition_id", &alert.condition_id)
+            .set("user_address", &alert.user_address)
+            .set("description", &alert.description);
+
+        // Rules without a measured value or threshold (new_market) leave the columns NULL
+        if !alert.value.is_empty() {
+            row.set("value", &alert.value);
+        }
+        if !alert.threshold.is_empty() {
+            row.set("threshold", &alert.threshold);
+        }
+    }
+
+    // Trader profiles (trades near resolution are counted into user_resolution_trades by the
+    // condition_resolutions trigger)
+    let profiles_timestamp = profiles
+        .block_timestamp
+        .as_ref()
+        .map(|t| unix_to_timestamp(t.seconds))
+        .unwrap_or_else(|| "1970-01-01 00:00:00".to_string());
+
+    for profile in &profiles.profiles {
+        tables
+            .update_row("user_profiles", &profile.user_address)
+            .set("total_trades", profile.total_trades)
+            .set("maker_share", &profile.maker_share)
+            .set("avg_holding_seconds", profile.avg_holding_seconds)
+            .set("trades_per_day", &profile.trades_per_day)
+            .set("avg_trade_size", &profile.avg_trade_size)
+            .set("neg_risk_volume_share", &profile.neg_risk_volume_share)
+            .set("style", &profile.style)
+            .set("last_updated_at", &profiles_timestamp);
+    }
+
+    // Volume excluding wash-flagged fills
+    for delta in &user_clean_volume_deltas.deltas {
+        tables
+            .update_row("user_pnl", &delta.key)
+            .set("clean_volume", delta.new_value.to_string());
+    }
+    for delta in &market_clean_volume_deltas.deltas {
+        tables
+            .update_row("markets", &delta.key)
+            .set("clean_volume", delta.new_value.to_string());
+    }
+
+    // Condition resolutions (the condition_resolutions trigger scores open positions into user_condition_calibration)
+    let resolved_at = resolutions
+        .block_timestamp
+        .as_ref()
+        .map(|t| unix_to_timestamp(t.seconds))
+        .unwrap_or_else(|| "1970-01-01 00:00:00".to_string());
+
+    for resolution in &resolutions.resolutions {
+        tables
+            .create_row("condition_resolutions", &resolution.condition_id)
+            .set("question_id", &resolution.question_id)
+            .set("oracle", &resolution.oracle)
+            .set("payout_numerators", resolution.payout_numerators.join(","))
+            .set("yes_token_id", &resolution.yes_token_id)
+            .set("no_token_id", &resolution.no_token_id)
+            .set("yes_payout", &resolution.yes_payout)
+            .set("no_payout", &resolution.no_payout)
+            .set("resolved_block", resolutions.block_number)
+            .set("resolved_at", &resolved_at);
+    }
+
+    // Per-market P&L (after user_market_pnl, markets and condition_resolutions: the market_pnl
+    // trigger re-marks holders and ranks market_leaderboard, settling at the resolution payouts)
+    let mut leaderboard_conditions: std::collections::BTreeSet<&str> =
+        user_market_pnl.condition_ids.iter().map(|c| c.as_str()).collect();
+    leaderboard_conditions.extend(resolutions.resolutions.iter().map(|r| r.condition_id.as_str()));
+    let leaderboard_timestamp = user_market_pnl
+        .block_timestamp
+        .as_ref()
+        .or(resolutions.block_timestamp.as_ref())
+        .map(|t| unix_to_timestamp(t.seconds))
+        .unwrap_or_else(|| "1970-01-01 00:00:00".to_string());
+    for condition_id in leaderboard_conditions {
+        tables
+            .update_row("market_pnl", condition_id)
+            .set("top_n", top_leaderboard)
+            .set("block_number", user_market_pnl.block_number.max(resolutions.block_number))
+            .set("last_updated_at", &leaderboard_timestamp);
+    }
+
+    // Market-wide calibration: last price of each outcome token vs whether it paid out.
+    // Tokens without a trade or with a split payout (e.g. 50/50) are not counted.
+    for resolution in &resolutions.resolutions {
+        for (price, payout) in [
+            (&resolution.yes_price, &resolution.yes_payout),
+            (&resolution.no_price, &resolution.no_payout),
+        ] {
+            let outcome_column = match payout.as_str() {
+                "1.000000000000000000" => "resolved_yes",
+                "0.000000000000000000" => "resolved_no",
+                _ => continue,
+            };
+            if price.is_empty() {
+                continue;
+            }
+
+            let bucket = calibration_bucket(price);
+            tables
+                .update_row("calibration_buckets", bucket.to_string())
+                .set("price_low", format_price_decimal(&BigInt::from(bucket), &BigInt::from(CALIBRATION_BUCKETS)))
+                .set("price_high", format_price_decimal(&BigInt::from(bucket + 1), &BigInt::from(CALIBRATION_BUCKETS)))
+                .add("price_sum", price.as_str())
+                .add("token_count", 1)
+                .add(outcome_column, 1);
+        }
+    }
+
+    // Open interest: current value on markets, hourly snapshot (last block of the hour wins)
+    if let Some(ts) = open_interest.block_timestamp.as_ref() {
+        let hour_start = ts.seconds - ts.seconds.rem_euclid(3600);
+        let hour = unix_to_timestamp(hour_start);
+
+        for token in &open_interest.tokens {
+            tables
+                .update_row("markets", &token.token_id)
+                .set("open_interest", &token.open_interest)
+                .set("open_interest_usdc", &token.collateral_locked);
+
+            tables
+                .update_row("open_interest_history", format!("{}:{}", token.token_id, hour_start))
+                .set("token_id", &token.token_id)
+                .set("condition_id", &token.condition_id)
+                .set("hour", &hour)
+                .set("open_interest", &token.open_interest)
+                .set("open_interest_usdc", &token.collateral_locked)
+                .set("block_number", open_interest.block_number);
+        }
+    }
+
+    // Upsert LP positions: current state with .set(), per-event P&L with .add()
+    for update in &lp_updates.updates {
+        let lp_id = format!("{}:{}", update.user_address, update.pool_address);
+        let lp_timestamp = update
+            .timestamp
+            .as_ref()
+            .map(|t| unix_to_timestamp(t.seconds))
+            .unwrap_or_else(|| "1970-01-01 00:00:00".to_string());
+
+        let mut row = tables
+            .update_row("lp_positions", &lp_id)
+            .set("user_address", &update.user_address)
+            .set("pool_address", &update.pool_address)
+            .set("shares", &update.shares)
+            .set("usdc_deposited", &update.usdc_deposited)
+            .set_if_null("first_funded_at", &lp_timestamp)
+            .set("last_updated_at", &lp_timestamp);
+
+        for (column, value) in [
+            ("fees_earned", &update.fees_earned),
+            ("removed_value", &update.removed_value),
+            ("cost_removed", &update.cost_removed),
+            ("realized_pnl", &update.realized_pnl),
+        ] {
+            if let Ok(value_i64) = value.parse::<i64>() {
+                if value_i64 != 0 {
+                    row = row.add(column, value_i64);
+                }
+            }
+        }
+    }
+
+    // Link tokens to their condition (TokenRegistered)
+    for token in &registry.tokens {
+        tables
+            .update_row("markets", &token.token_id)
+            .set("condition_id", &token.condition_id)
+            .set("complement_token_id", &token.complement_token_id)
+            .set("is_neg_risk", token.exchange == "neg_risk");
+    }
+
+    // Link NegRisk questions to their CTF condition (the adapter is the condition's oracle)
+    let adapter = format_address(&NEG_RISK_ADAPTER);
+    for condition in &registry.conditions {
+        if !condition.oracle.eq_ignore_ascii_case(&adapter) {
+            continue;
+        }
+        let question_bytes = Hex::decode(condition.question_id.trim_start_matches("0x")).unwrap_or_default();
+        let question_index = question_bytes.last().copied().unwrap_or(0) as u32;
+
+        tables
+            .update_row("neg_risk_questions", &condition.question_id)
+            .set("market_id", neg_risk_market_id(&question_bytes))
+            .set("question_index", question_index)
+            .set("condition_id", &condition.condition_id);
+    }
+
+    // NegRisk market groups: market -> questions
+    for market in &neg_risk_events.markets {
+        let created_at = market
+            .timestamp
+            .as_ref()
+            .map(|t| unix_to_timestamp(t.seconds))
+            .unwrap_or_else(|| "1970-01-01 00:00:00".to_string());
+
+        tables
+            .update_row("neg_risk_markets", &market.market_id)
+            .set("oracle", &market.oracle)
+            .set("fee_bips", &market.fee_bips)
+            .set("data", &market.data)
+            .set("created_block", market.block_number)
+            .set("created_at", &created_at);
+    }
+
+    for question in &neg_risk_events.questions {
+        let created_at = question
+            .timestamp
+            .as_ref()
+            .map(|t| unix_to_timestamp(t.seconds))
+            .unwrap_or_else(|| "1970-01-01 00:00:00".to_string());
+
+        tables
+            .update_row("neg_risk_questions", &question.question_id)
+            .set("market_id", &question.market_id)
+            .set("question_index", question.question_index)
+            .set("data", &question.data)
+            .set("created_block", question.block_number)
+            .set("created_at", &created_at);
+
+        tables
+            .update_row("neg_risk_markets", &question.market_id)
+            .add("question_count", 1i64);
+    }
+
+    // NegRisk conversions (NO positions -> YES positions + USDC)
+    for conversion in &neg_risk_events.conversions {
+        let conversion_timestamp = conversion
+            .timestamp
+            .as_ref()
+            .map(|t| unix_to_timestamp(t.seconds))
+            .unwrap_or_else(|| "1970-01-01 00:00:00".to_string());
+
+        let join_legs = |legs: &[pnl::TokenLeg]| {
+            legs.iter()
+                .map(|leg| leg.token_id.as_str())
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+
+        tables
+            .create_row("neg_risk_conversions", &conversion.id)
+            .set("block_number", conversion.block_number)
+            .set("block_timestamp", &conversion_timestamp)
+            .set("tx_hash", &conversion.tx_hash)
+            .set("log_index", conversion.log_index)
+            .set("stakeholder", &conversion.stakeholder)
+            .set("market_id", &conversion.market_id)
+            .set("index_set", &conversion.index_set)
+            .set("amount", &conversion.amount)
+            .set("no_count", conversion.no_count)
+            .set("usdc_returned", &conversion.usdc_returned)
+            .set("no_token_ids", join_legs(&conversion.tokens_out))
+            .set("yes_token_ids", join_legs(&conversion.tokens_in));
+    }
+
+    // NegRisk event-level aggregates: totals with .set(), realized P&L change with .add()
+    let neg_risk_timestamp = neg_risk_stats
+        .block_timestamp
+        .as_ref()
+        .map(|t| unix_to_timestamp(t.seconds))
+        .unwrap_or_else(|| "1970-01-01 00:00:00".to_string());
+
+    for event in &neg_risk_stats.events {
+        let mut row = tables
+            .update_row("neg_risk_events", &event.market_id)
+            .set("total_volume", &event.total_volume)
+            .set("open_interest", &event.open_interest)
+            .set("yes_price_sum", &event.yes_price_sum)
+            .set("overround", &event.overround)
+            .set("last_updated_at", &neg_risk_timestamp);
+
+        if let Ok(pnl_i64) = event.realized_pnl_change.parse::<i64>() {
+            if pnl_i64 != 0 {
+                row = row.add("realized_pnl", pnl_i64);
+            }
+        }
+    }
+
+    for user in &neg_risk_stats.users {
+        tables
+            .update_row("neg_risk_event_users", format!("{}:{}", user.market_id, user.user_address))
+            .set("market_id", &user.market_id)
+            .set("user_address", &user.user_address)
+            .set("realized_pnl", &user.realized_pnl)
+            .set("last_updated_at", &neg_risk_timestamp);
+    }
+
+    // Upsert orders: fill totals only once the order has fills, cancellation when seen
+    for order in &orders.orders {
+        let mut row = tables
+            .update_row("orders", &order.order_hash)
+            .set("status", &order.status);
+
+        if !order.exchange.is_empty() {
+            row = row.set("exchange", &order.exchange);
+        }
+        if order.fill_count > 0 {
+            row = row
+                .set("maker", &order.maker)
+                .set("token_id", &order.token_id)
+                .set("side", &order.side)
+                .set("filled_shares", &order.filled_shares)
+                .set("filled_usdc", &order.filled_usdc)
+                .set("fill_count", order.fill_count)
+                .set("vwap", &order.vwap)
+                .set("first_fill_block", order.first_fill_block);
+            if !order.size.is_empty() {
+                row = row.set("size", &order.size);
+            }
+        }
+        if order.last_fill_block > 0 {
+            row = row.set("last_fill_block", order.last_fill_block);
+        }
+        if order.cancelled_block > 0 {
+            row = row.set("cancelled_block", order.cancelled_block);
+        }
+    }
+
+    // Exchange admin / governance events
+    for event in &exchange_events.events {
+        let event_timestamp = event
+            .timestamp
+            .as_ref()
+            .map(|t| unix_to_timestamp(t.seconds))
+            .unwrap_or_else(|| "1970-01-01 00:00:00".to_string());
 
-            // Get current position data
-            let quantity = positions_store
-                .get_last(&delta.key)
-                .unwrap_or_else(|| BigInt::from(0));
+        let row = tables
+            .create_row("exchange_events", &event.id)
+            .set("block_number", event.block_number)
+            .set("block_timestamp", &event_timestamp)
+            .set("tx_hash", &event.tx_hash)
+            .set("log_index", event.log_index)
+            .set("exchange", &event.exchange)
+            .set("event_type", &event.event_type)
+            .set("account", &event.account)
+            .set("counterparty", &event.counterparty);
+
+        // Only fee events carry a token and amount; the columns stay NULL otherwise
+        if event.event_type == "fee_charged" {
+            row.set("token_id", &event.token_id).set("amount", &event.amount);
+        }
+    }
 
-            let cost_basis = cost_basis_store
-                .get_last(&delta.key)
-                .unwrap_or_else(|| BigInt::from(0));
+    // Pause windows: opened on pause, closed on unpause (the delta's old value is the pause block)
+    let pause_timestamp = exchange_events
+        .block_timestamp
+        .as_ref()
+        .map(|t| unix_to_timestamp(t.seconds))
+        .unwrap_or_else(|| "1970-01-01 00:00:00".to_string());
 
-            // Calculate average entry price (cost_basis is in USDC 6-decimals, scale to 18 decimals)
-            let avg_entry_price = if !quantity.is_zero() {
-                // Cost basis (6 decimals) / quantity (token units) = price in USDC
-                // To get 18-decimal price format, we multiply by 10^12
-                let scale = BigInt::from_str("1000000000000").unwrap();
-                let scaled_price = (&cost_basis * &scale) / &quantity;
-                format_price_decimal(&scaled_price, &BigInt::from_str("1000000000000000000").unwrap())
-            } else {
-                "0.000000000000000000".to_string()
-            };
+    for delta in &paused_deltas.deltas {
+        let actor = |event_type: &str| {
+            exchange_events
+                .events
+                .iter()
+                .find(|e| e.exchange == delta.key && e.event_type == event_type)
+                .map(|e| e.account.clone())
+                .unwrap_or_default()
+        };
 
-            // Get current price
-            let current_price = prices_store
-                .get_last(token_id)
-                .map(|p| p.price.clone())
-                .unwrap_or_else(|| "0.000000000000000000".to_string());
+        if delta.new_value > 0 {
+            tables
+                .update_row("trading_pauses", format!("{}:{}", delta.key, delta.new_value))
+                .set("exchange", &delta.key)
+                .set("paused_block", delta.new_value)
+                .set("paused_at", &pause_timestamp)
+                .set("paused_by", actor("trading_paused"))
+                .set("is_active", true);
+        } else if delta.old_value > 0 {
+            tables
+                .update_row("trading_pauses", format!("{}:{}", delta.key, delta.old_value))
+                .set("unpaused_block", exchange_events.block_number)
+                .set("unpaused_at", &pause_timestamp)
+                .set("unpaused_by", actor("trading_unpaused"))
+                .set("is_active", false);
+        }
+    }
 
-            // Calculate current value (quantity * price, converted to USDC format)
-            let current_value = if !quantity.is_zero() {
-                let price_bigint = parse_price_decimal(&current_price);
-                let value_18_dec = price_bigint * &quantity;
-                format_usdc_from_bigint(&value_18_dec)
-            } else {
-                "0".to_string()
-            };
+    // Position lifecycles: one row per holding episode, per-block changes with .add()
+    for lifecycle in &lifecycles.lifecycles {
+        let opened_at = lifecycle.opened_at.as_ref().map(|t| unix_to_timestamp(t.seconds));
+        if let Some(opened_at) = &opened_at {
+            tables
+                .update_row("user_positions", format!("{}:{}", lifecycle.user_address, lifecycle.token_id))
+                .set("opened_at", opened_at);
+        }
 
-            // Calculate unrealized P&L: (current_price - avg_entry_price) * quantity
-            // Then convert from 18 decimals to USDC 6 decimals
-            let avg_entry_bigint = parse_price_decimal(&avg_entry_price);
-            let current_price_bigint = parse_price_decimal(&current_price);
-            let unrealized_pnl = if !quantity.is_zero() {
-                let pnl_18_dec = (current_price_bigint - avg_entry_bigint) * &quantity;
-                format_usdc_from_bigint(&pnl_18_dec)
-            } else {
-                "0".to_string()
-            };
+        let row = tables
+            .update_row("position_lifecycles", &lifecycle.id)
+            .set("user_address", &lifecycle.user_address)
+            .set("token_id", &lifecycle.token_id)
+            .set("opened_block", lifecycle.opened_block);
 
-            // Get timestamp from block
-            let timestamp = fills.block_timestamp
+        if let Some(opened_at) = &opened_at {
+            row.set("opened_at", opened_at).set("status", "open");
+        }
+        for (column, value) in [
+            ("total_bought", &lifecycle.bought_shares),
+            ("total_sold", &lifecycle.sold_shares),
+            ("realized_pnl", &lifecycle.realized_pnl),
+        ] {
+            if value != "0" {
+                row.add(column, value.as_str());
+            }
+        }
+        if let Ok(max_size) = lifecycle.max_size.parse::<i64>() {
+            if max_size > 0 {
+                row.max("max_size", max_size);
+            }
+        }
+        if lifecycle.closed_block > 0 {
+            let closed_at = lifecycle
+                .closed_at
                 .as_ref()
                 .map(|t| unix_to_timestamp(t.seconds))
                 .unwrap_or_else(|| "1970-01-01 00:00:00".to_string());
-
-            tables
-                .update_row("user_positions", &position_id)
-                .set("user_address", user_address)
-                .set("token_id", token_id)
-                .set("quantity", quantity.to_string())
-                .set("avg_entry_price", avg_entry_price)
-                .set("total_cost_basis", cost_basis.to_string())
-                .set("unrealized_pnl", unrealized_pnl)
-                .set("current_price", current_price)
-                .set("current_value", current_value)
-                .set("last_updated_at", &timestamp);
+            row.set("closed_block", lifecycle.closed_block)
+                .set("closed_at", &closed_at)
+                .set("holding_seconds", lifecycle.holding_seconds)
+                .set("exit_type", &lifecycle.exit_type)
+                .set("status", "closed");
         }
     }
 
-    // Upsert market stats
-    for stat in market_stats.stats {
-        tables
-            .update_row("markets", &stat.token_id)
-            .set("total_volume", &stat.total_volume)
-            .set("current_price", &stat.current_price);
-    }
-
     Ok(tables.to_database_changes())
 }
 
@@ -1173,6 +5488,571 @@ mod tests {
         assert!(diff < BigInt::from(10000000000000000u64));
     }
 
+    //==============================================
+    // LP P&L Tests
+    //==============================================
+
+    #[test]
+    fn test_fpmm_outcome_value_balanced_pool() {
+        // Balanced removal: every outcome priced at 1/n, value equals one complete set
+        let amounts = vec![BigInt::from(1000000u64), BigInt::from(1000000u64)];
+        assert_eq!(fpmm_outcome_value(&amounts), BigInt::from(1000000u64));
+    }
+
+    #[test]
+    fn test_fpmm_outcome_value_skewed_pool() {
+        // 3 YES / 1 NO removed: YES priced 0.25, NO priced 0.75 -> 3*0.25 + 1*0.75 = 1.5
+        let amounts = vec![BigInt::from(3000000u64), BigInt::from(1000000u64)];
+        assert_eq!(fpmm_outcome_value(&amounts), BigInt::from(1500000u64));
+    }
+
+    #[test]
+    fn test_fpmm_outcome_value_empty_or_zero() {
+        assert_eq!(fpmm_outcome_value(&[]), BigInt::from(0u64));
+        let amounts = vec![BigInt::from(0u64), BigInt::from(1000000u64)];
+        assert_eq!(fpmm_outcome_value(&amounts), BigInt::from(0u64));
+    }
+
+    #[test]
+    fn test_fpmm_leg_costs_of_tokens_sent_back() {
+        // addFunding of 3 USDC into a 1:3 pool keeps [1, 3] and sends 2 of outcome 0 back
+        let amounts = vec![BigInt::from(1000000u64), BigInt::from(3000000u64)];
+        assert_eq!(
+            fpmm_prices(&amounts),
+            vec![BigInt::from(750000000000000000u64), BigInt::from(250000000000000000u64)]
+        );
+
+        let legs = vec![
+            pnl::TokenLeg { token_id: "1".to_string(), amount: "2000000".to_string() },
+            pnl::TokenLeg { token_id: "2".to_string(), amount: "0".to_string() },
+        ];
+        let costs = fpmm_leg_costs(&amounts, &legs);
+        assert_eq!(costs, vec![BigInt::from(1500000u64), BigInt::from(0u64)]);
+        // The 1.5 USDC net deposit matches the pool value of the amounts kept
+        assert_eq!(BigInt::from(3000000u64) - &costs[0], fpmm_outcome_value(&amounts));
+
+        // Legs that don't line up with the outcomes get no cost
+        assert_eq!(fpmm_leg_costs(&amounts, &legs[..1]), vec![BigInt::from(0u64)]);
+    }
+
+    #[test]
+    fn test_lp_cost_removed_average_cost() {
+        // 100 USDC for 80 shares, burning 20 shares removes a quarter of the cost
+        let cost = lp_cost_removed(
+            &BigInt::from(20000000u64),
+            &BigInt::from(100000000u64),
+            &BigInt::from(80000000u64),
+        );
+        assert_eq!(cost, BigInt::from(25000000u64));
+
+        // No recorded mints (e.g. shares received by transfer) -> no cost
+        let cost = lp_cost_removed(&BigInt::from(1u64), &BigInt::from(1u64), &BigInt::from(0u64));
+        assert_eq!(cost, BigInt::from(0u64));
+    }
+
+    //==============================================
+    // NegRisk Adapter Tests
+    //==============================================
+
+    #[test]
+    fn test_allocate_by_weight_proportional() {
+        // 1 USDC split across legs priced 0.25 / 0.75
+        let weights = vec![BigInt::from(250000u64), BigInt::from(750000u64)];
+        let shares = allocate_by_weight(&BigInt::from(1000000u64), &weights);
+        assert_eq!(shares, vec![BigInt::from(250000u64), BigInt::from(750000u64)]);
+    }
+
+    #[test]
+    fn test_allocate_by_weight_remainder_to_last_leg() {
+        let weights = vec![BigInt::from(1u64), BigInt::from(1u64), BigInt::from(1u64)];
+        let shares = allocate_by_weight(&BigInt::from(100u64), &weights);
+        assert_eq!(shares, vec![BigInt::from(33u64), BigInt::from(33u64), BigInt::from(34u64)]);
+    }
+
+    #[test]
+    fn test_allocate_by_weight_unpriced_legs_split_evenly() {
+        let weights = vec![BigInt::from(0u64), BigInt::from(0u64)];
+        let shares = allocate_by_weight(&BigInt::from(1000000u64), &weights);
+        assert_eq!(shares, vec![BigInt::from(500000u64), BigInt::from(500000u64)]);
+        assert!(allocate_by_weight(&BigInt::from(1u64), &[]).is_empty());
+    }
+
+    #[test]
+    fn test_neg_risk_market_id_clears_index_byte() {
+        let mut question_id = vec![0xabu8; 32];
+        question_id[31] = 0x05;
+        let market_id = neg_risk_market_id(&question_id);
+        assert_eq!(market_id, format!("0x{}00", "ab".repeat(31)));
+    }
+
+    //==============================================
+    // NegRisk Event Stats Tests
+    //==============================================
+
+    #[test]
+    fn test_implied_yes_price_from_no_fill() {
+        assert_eq!(implied_yes_price("0.300000000000000000", true), BigInt::from(300000000000000000u64));
+        assert_eq!(implied_yes_price("0.300000000000000000", false), BigInt::from(700000000000000000u64));
+    }
+
+    #[test]
+    fn test_long_quantity_ignores_short_positions() {
+        assert_eq!(long_quantity(&BigInt::from(500i64)), BigInt::from(500i64));
+        assert_eq!(long_quantity(&BigInt::from(-500i64)), BigInt::from(0));
+    }
+
+    #[test]
+    fn test_format_signed_price_overround() {
+        // YES prices summing to 1.05 -> 5% overround
+        let price_sum = BigInt::from(1050000000000000000u64);
+        assert_eq!(format_signed_price(&price_sum), "1.050000000000000000");
+
+        let overround = price_sum - BigInt::from(1000000000000000000u64);
+        assert_eq!(format_signed_price(&overround), "0.050000000000000000");
+
+        let underround = BigInt::from(-20000000000000000i64);
+        assert_eq!(format_signed_price(&underround), "-0.020000000000000000");
+    }
+
+    //==============================================
+    // Cash Flow Tests
+    //==============================================
+
+    #[test]
+    fn test_cash_flow_type() {
+        let exchange = "0x4bfb41d5b3570defd03c39a9a4d8de6bd8b8982e";
+        let ctf = "0x4d97dcd97ec945f40cf65f87097ace5ea0476045";
+        let other = "0x1234567890123456789012345678901234567890";
+
+        assert_eq!(cash_flow_type(exchange, true, false, false), "trade_settlement");
+        assert_eq!(cash_flow_type(ctf, false, false, false), "split");
+        assert_eq!(cash_flow_type(ctf, true, false, true), "redemption");
+        assert_eq!(cash_flow_type(ctf, true, false, false), "merge");
+        assert_eq!(cash_flow_type(other, false, true, false), "fee");
+        assert_eq!(cash_flow_type(other, true, false, false), "deposit");
+        assert_eq!(cash_flow_type(other, false, false, false), "withdrawal");
+    }
+
+    #[test]
+    fn test_smart_money_weight() {
+        let amount = BigInt::from(1_000_000_000u64);
+        // 15 of 20 exits won: edge (15 - 5) / 20 = 0.5
+        assert_eq!(smart_money_weight(&amount, 20, 15), BigInt::from(500_000_000u64));
+        // Coin flip or worse carries no weight
+        assert_eq!(smart_money_weight(&amount, 20, 10), BigInt::from(0));
+        assert_eq!(smart_money_weight(&amount, 20, 4), BigInt::from(0));
+        // Too few exits to judge
+        assert_eq!(smart_money_weight(&amount, 5, 5), BigInt::from(0));
+    }
+
+    #[test]
+    fn test_alert_rules() {
+        let half = BigInt::from(500_000_000_000_000_000u64);
+        // 0.50 -> 0.56 is 1200 bps, either direction
+        assert_eq!(price_move_bps(&half, &BigInt::from(560_000_000_000_000_000u64)), 1200);
+        assert_eq!(price_move_bps(&half, &BigInt::from(440_000_000_000_000_000u64)), 1200);
+        assert_eq!(price_move_bps(&BigInt::from(0), &half), 0);
+
+        let threshold = BigInt::from(100);
+        assert!(crossed_threshold(&BigInt::from(50), &BigInt::from(100), &threshold));
+        assert!(crossed_threshold(&BigInt::from(-50), &BigInt::from(-120), &threshold));
+        // Already beyond the threshold
+        assert!(!crossed_threshold(&BigInt::from(150), &BigInt::from(200), &threshold));
+        assert!(!crossed_threshold(&BigInt::from(0), &BigInt::from(99), &threshold));
+    }
+
+    #[test]
+    fn test_kyle_lambda() {
+        // One 1,000 USDC buy moving the price by 0.01
+        let price_flow = BigInt::from(10_000_000_000_000_000u64) * BigInt::from(1_000_000_000u64);
+        let flow_squared = BigInt::from(1_000_000_000u64) * BigInt::from(1_000_000_000u64);
+        assert_eq!(kyle_lambda(&price_flow, &flow_squared), "0.010000000000000000");
+        // A sell that moved the price up has negative lambda
+        assert_eq!(kyle_lambda(&(BigInt::from(0) - &price_flow), &flow_squared), "-0.010000000000000000");
+        assert_eq!(kyle_lambda(&price_flow, &BigInt::from(0)), "0");
+    }
+
+    #[test]
+    fn test_registration_outcomes() {
+        let condition_id = "0x0000000000000000000000000000000000000000000000000000000000000001";
+        let condition = Hex::decode(condition_id).unwrap();
+        let yes = ctf_position_id(&USDC_CONTRACT, &ctf_collection_id(&condition, 1));
+        let no = ctf_position_id(&USDC_CONTRACT, &ctf_collection_id(&condition, 2));
+        assert_ne!(yes, no);
+
+        // NO registered first is still recognised as the complement of YES
+        let registration = pnl::TokenRegistration {
+            token_id: no.clone(),
+            complement_token_id: yes.clone(),
+            condition_id: condition_id.to_string(),
+            exchange: "ctf".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(registration_outcomes(&registration), (yes.clone(), no.clone()));
+
+        // Tokens that are not positions of the condition keep registration order
+        let registration = pnl::TokenRegistration {
+            token_id: "1".to_string(),
+            complement_token_id: "2".to_string(),
+            ..registration
+        };
+        assert_eq!(registration_outcomes(&registration), ("1".to_string(), "2".to_string()));
+    }
+
+    #[test]
+    fn test_trader_style() {
+        // 10 arbitrage trades over 30 fills
+        assert_eq!(trader_style(30, 0, 10, 3600), "arbitrageur");
+        // 80% of fills as maker
+        assert_eq!(trader_style(100, 80, 0, 3600), "market_maker");
+        assert_eq!(trader_style(100, 50, 0, 3600), "scalper");
+        assert_eq!(trader_style(100, 50, 0, 3 * 86400), "swing");
+        assert_eq!(trader_style(100, 50, 0, 30 * 86400), "holder");
+        // Nothing closed yet
+        assert_eq!(trader_style(5, 0, 0, 0), "holder");
+    }
+
+    #[test]
+    fn test_period_start() {
+        // 2024-03-14 15:30:00 UTC, a Thursday
+        let ts = 1710430200;
+        assert_eq!(unix_to_timestamp(period_start(ts, "day")), "2024-03-14 00:00:00");
+        assert_eq!(unix_to_timestamp(period_start(ts, "week")), "2024-03-11 00:00:00");
+        assert_eq!(unix_to_timestamp(period_start(ts, "month")), "2024-03-01 00:00:00");
+        // A Monday starts its own week
+        assert_eq!(unix_to_timestamp(period_start(1710115200, "week")), "2024-03-11 00:00:00");
+    }
+
+    #[test]
+    fn test_ratio_decimal() {
+        // 250 USDC profit on 1000 USDC deployed
+        assert_eq!(ratio_decimal(&BigInt::from(250_000_000u64), &BigInt::from(1_000_000_000u64)), "0.250000000000000000");
+        assert_eq!(ratio_decimal(&BigInt::from(-500_000_000i64), &BigInt::from(1_000_000_000u64)), "-0.500000000000000000");
+        assert_eq!(ratio_decimal(&BigInt::from(3_000_000u64), &BigInt::from(2_000_000u64)), "1.500000000000000000");
+        // No capital deployed yet
+        assert_eq!(ratio_decimal(&BigInt::from(1_000_000u64), &BigInt::from(0)), "0");
+    }
+
+    #[test]
+    fn test_nav_log_return_excludes_flows() {
+        // 100 -> 250 with a 100 deposit: 50% return
+        let r = nav_log_return(&BigInt::from(100_000_000u64), &BigInt::from(250_000_000u64), &BigInt::from(100_000_000u64));
+        assert!((r - 1.5f64.ln()).abs() < 1e-12);
+
+        // First deposit into an empty portfolio has no defined return
+        assert_eq!(nav_log_return(&BigInt::from(0), &BigInt::from(100_000_000u64), &BigInt::from(100_000_000u64)), 0.0);
+
+        // Withdrawing everything is not a loss
+        let r = nav_log_return(&BigInt::from(100_000_000u64), &BigInt::from(0), &BigInt::from(-100_000_000i64));
+        assert_eq!(r, 0.0);
+    }
+
+    //==============================================
+    // Arbitrage Tests
+    //==============================================
+
+    #[test]
+    fn test_set_arbitrage_buy_below_one() {
+        // 100 YES at 0.45 + 100 NO at 0.50 = 95 USDC for 100 sets -> 5 USDC locked in
+        let (yes_shares, yes_usdc) = (BigInt::from(100_000_000u64), BigInt::from(45_000_000u64));
+        let (no_shares, no_usdc) = (BigInt::from(100_000_000u64), BigInt::from(50_000_000u64));
+        let (sets, usdc, profit) = set_arbitrage(&[(&yes_shares, &yes_usdc), (&no_shares, &no_usdc)], true).unwrap();
+        assert_eq!(sets, BigInt::from(100_000_000u64));
+        assert_eq!(usdc, BigInt::from(95_000_000u64));
+        assert_eq!(profit, BigInt::from(5_000_000u64));
+
+        // Same legs sold: proceeds below 1 per set is not arbitrage
+        assert!(set_arbitrage(&[(&yes_shares, &yes_usdc), (&no_shares, &no_usdc)], false).is_none());
+    }
+
+    #[test]
+    fn test_set_arbitrage_prorates_unmatched_shares() {
+        // 200 YES for 80 USDC, 100 NO for 50 USDC: 100 sets cost 40 + 50 = 90
+        let (yes_shares, yes_usdc) = (BigInt::from(200_000_000u64), BigInt::from(80_000_000u64));
+        let (no_shares, no_usdc) = (BigInt::from(100_000_000u64), BigInt::from(50_000_000u64));
+        let (_, usdc, profit) = set_arbitrage(&[(&yes_shares, &yes_usdc), (&no_shares, &no_usdc)], true).unwrap();
+        assert_eq!(usdc, BigInt::from(90_000_000u64));
+        assert_eq!(profit, BigInt::from(10_000_000u64));
+
+        // A missing leg forms no set
+        let zero = BigInt::from(0);
+        assert!(set_arbitrage(&[(&yes_shares, &yes_usdc), (&zero, &zero)], true).is_none());
+    }
+
+    //==============================================
+    // Wash Trade Tests
+    //==============================================
+
+    #[test]
+    fn test_fill_counterparties() {
+        let fill = pnl::OrderFill {
+            maker: "0xAAAA".to_string(),
+            taker: "0xbbbb".to_string(),
+            side: "buy".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(fill_counterparties(&fill), ("0xbbbb".to_string(), "0xaaaa".to_string()));
+
+        let fill = pnl::OrderFill { side: "sell".to_string(), ..fill };
+        assert_eq!(fill_counterparties(&fill), ("0xaaaa".to_string(), "0xbbbb".to_string()));
+    }
+
+    #[test]
+    fn test_market_spent_entry() {
+        let yes = pnl::MarketToken {
+            token_id: "1".to_string(),
+            complement_token_id: "2".to_string(),
+            condition_id: "0xc0".to_string(),
+            is_yes: true,
+        };
+        let no = pnl::MarketToken { token_id: "2".to_string(), complement_token_id: "1".to_string(), is_yes: false, ..yes.clone() };
+
+        // Taker buys YES, then the maker buys NO of the same condition: both count for the condition
+        let buy = pnl::OrderFill {
+            maker: "0xAAAA".to_string(),
+            taker: "0xbbbb".to_string(),
+            side: "buy".to_string(),
+            amount: "600000".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(market_spent_entry(&buy, &yes), Some(("0xbbbb:0xc0".to_string(), BigInt::from(600000u64))));
+        let maker_buy = pnl::OrderFill { side: "sell".to_string(), amount: "400000".to_string(), ..buy.clone() };
+        assert_eq!(market_spent_entry(&maker_buy, &no), Some(("0xaaaa:0xc0".to_string(), BigInt::from(400000u64))));
+
+        // The taker order's own fill repeats the maker fills
+        let own_fill = pnl::OrderFill { taker: "0x4bfb41d5b3570defd03c39a9a4d8de6bd8b8982e".to_string(), ..maker_buy };
+        assert_eq!(market_spent_entry(&own_fill, &no), None);
+    }
+
+    #[test]
+    fn test_fresh_wallet_position_filters() {
+        let min_position = BigInt::from(DEFAULT_MIN_SUSPICIOUS_POSITION);
+        let large = BigInt::from(5_000_000_000u64);
+        let fresh = DEFAULT_FRESH_WALLET_SECONDS;
+
+        assert!(is_fresh_wallet_position(3600, &large, fresh, &min_position));
+        // Exactly at the limits still counts
+        assert!(is_fresh_wallet_position(fresh, &min_position, fresh, &min_position));
+        // Older wallet
+        assert!(!is_fresh_wallet_position(fresh + 1, &large, fresh, &min_position));
+        // Position below min_position
+        assert!(!is_fresh_wallet_position(3600, &BigInt::from(999_999_999u64), fresh, &min_position));
+    }
+
+    #[test]
+    fn test_position_ratios() {
+        // 2,000 USDC of a 10,000 USDC market, out of 2,500 USDC spent overall
+        let (market_share, concentration) = position_ratios(
+            &BigInt::from(2_000_000_000u64),
+            &BigInt::from(10_000_000_000u64),
+            &BigInt::from(2_500_000_000u64),
+        );
+        assert_eq!(market_share, "0.200000000000000000");
+        assert_eq!(concentration, "0.800000000000000000");
+
+        // No recorded volume or spending yet
+        assert_eq!(position_ratios(&BigInt::from(1_000_000u64), &BigInt::from(0), &BigInt::from(0)), ("0".to_string(), "0".to_string()));
+    }
+
+    //==============================================
+    // Resolution Tests
+    //==============================================
+
+    #[test]
+    fn test_payout_fractions() {
+        let yes_wins = payout_fractions(&["1".to_string(), "0".to_string()]);
+        assert_eq!(yes_wins, vec!["1.000000000000000000", "0.000000000000000000"]);
+
+        // 50/50 resolution
+        let split = payout_fractions(&["1".to_string(), "1".to_string()]);
+        assert_eq!(split, vec!["0.500000000000000000", "0.500000000000000000"]);
+    }
+
+    #[test]
+    fn test_calibration_bucket() {
+        assert_eq!(calibration_bucket("0.050000000000000000"), 0);
+        assert_eq!(calibration_bucket("0.100000000000000000"), 1);
+        assert_eq!(calibration_bucket("0.999000000000000000"), 9);
+        assert_eq!(calibration_bucket("0"), 0);
+    }
+
+    //==============================================
+    // Market Holder Tests
+    //==============================================
+
+    #[test]
+    fn test_herfindahl_index() {
+        // Holders of 60 and 40 shares: 0.6^2 + 0.4^2 = 0.52
+        let squares = BigInt::from(60 * 60 + 40 * 40);
+        assert_eq!(herfindahl_index(&squares, &BigInt::from(100)), "0.520000000000000000");
+
+        // Single holder = fully concentrated
+        assert_eq!(herfindahl_index(&BigInt::from(2500), &BigInt::from(50)), "1.000000000000000000");
+
+        // No holders
+        assert_eq!(herfindahl_index(&BigInt::from(0), &BigInt::from(0)), "0.000000000000000000");
+    }
+
+    #[test]
+    fn test_param_value() {
+        let params = "min_trade_size=1000000&top_holders=25";
+        assert_eq!(param_value(params, "min_trade_size"), Some("1000000"));
+        assert_eq!(param_value(params, "top_holders"), Some("25"));
+        assert_eq!(param_value("min_trade_size=1000000", "top_holders"), None);
+    }
+
+    //==============================================
+    // User Market P&L Tests
+    //==============================================
+
+    #[test]
+    fn test_market_value_of_leg() {
+        // 150 shares at 0.60 USDC = 90 USDC
+        let quantity = BigInt::from(150_000_000u64);
+        assert_eq!(market_value(&quantity, "600000000000000000"), BigInt::from(90_000_000u64));
+        assert_eq!(market_value(&quantity, "0"), BigInt::from(0));
+    }
+
+    //==============================================
+    // Enriched Fill Tests
+    //==============================================
+
+    #[test]
+    fn test_position_action() {
+        let q = |v: i64| BigInt::from(v);
+        assert_eq!(position_action(&q(0), &q(100)), "open");
+        assert_eq!(position_action(&q(100), &q(150)), "add");
+        assert_eq!(position_action(&q(100), &q(40)), "reduce");
+        assert_eq!(position_action(&q(100), &q(0)), "close");
+        assert_eq!(position_action(&q(100), &q(-20)), "flip");
+        assert_eq!(position_action(&q(-100), &q(-150)), "add");
+    }
+
+    #[test]
+    fn test_avg_entry_price_realizes_against_buys() {
+        // Buy 100 shares for 40 USDC, sell them for 60 USDC -> +20 USDC
+        let avg = avg_entry_price(&BigInt::from(40_000_000u64), &BigInt::from(100_000_000u64));
+        assert_eq!(format_signed_price(&avg), "0.400000000000000000");
+        let shares = BigInt::from(100_000_000u64);
+        let pnl = fill_realized_pnl(&shares, &shares, &BigInt::from(60_000_000u64), &avg);
+        assert_eq!(pnl, BigInt::from(20_000_000u64));
+
+        // Half sold for 30 USDC realizes +10 against the same average
+        let half = BigInt::from(50_000_000u64);
+        assert_eq!(fill_realized_pnl(&shares, &half, &BigInt::from(30_000_000u64), &avg), BigInt::from(10_000_000u64));
+        assert_eq!(avg_entry_price(&BigInt::from(1), &BigInt::from(0)), BigInt::from(0));
+    }
+
+    #[test]
+    fn test_fill_realized_pnl() {
+        // Sell 100 shares bought at 0.40 for 60 USDC -> +20 USDC
+        let avg_entry = BigInt::from(400000000000000000u64);
+        let pnl = fill_realized_pnl(
+            &BigInt::from(100000000u64),
+            &BigInt::from(100000000u64),
+            &BigInt::from(60000000u64),
+            &avg_entry,
+        );
+        assert_eq!(pnl, BigInt::from(20000000u64));
+
+        // Selling 200 against a 100 long only realizes on the closing half
+        let pnl = fill_realized_pnl(
+            &BigInt::from(100000000u64),
+            &BigInt::from(200000000u64),
+            &BigInt::from(120000000u64),
+            &avg_entry,
+        );
+        assert_eq!(pnl, BigInt::from(20000000u64));
+
+        // Nothing to realize without a long position
+        let pnl = fill_realized_pnl(&BigInt::from(0), &BigInt::from(100u64), &BigInt::from(50u64), &avg_entry);
+        assert_eq!(pnl, BigInt::from(0));
+    }
+
+    #[test]
+    fn test_realized_fills_cover_exchange_and_synthetic_fills() {
+        let row = |fill_id: &str, realized_pnl: &str| pnl::EnrichedFill {
+            fill_id: fill_id.to_string(),
+            realized_pnl: realized_pnl.to_string(),
+            ..Default::default()
+        };
+        let enriched = pnl::EnrichedFills {
+            fills: vec![row("sell", "-2000000"), row("buy", "0")],
+            synthetic_fills: vec![row("redemption", "5000000")],
+            ..Default::default()
+        };
+
+        let realized: Vec<(String, BigInt)> = realized_fills(&enriched).map(|(f, pnl)| (f.fill_id.clone(), pnl)).collect();
+        assert_eq!(
+            realized,
+            vec![
+                ("sell".to_string(), BigInt::from_str("-2000000000000000000").unwrap()),
+                ("redemption".to_string(), BigInt::from_str("5000000000000000000").unwrap()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_exit_type() {
+        assert_eq!(exit_type(true, None, false), "sold");
+        assert_eq!(exit_type(false, Some(&BigInt::from(5000000u64)), false), "redeemed");
+        assert_eq!(exit_type(false, Some(&BigInt::from(0)), false), "expired_worthless");
+        assert_eq!(exit_type(false, None, true), "merged");
+        assert_eq!(exit_type(false, None, false), "transferred");
+    }
+
+    //==============================================
+    // Order Aggregation Tests
+    //==============================================
+
+    #[test]
+    fn test_order_side_and_shares_from_maker_perspective() {
+        // Maker pays USDC (asset 0) for 200 tokens -> taker sells, maker's order is a buy
+        let fill = pnl::OrderFill {
+            side: "sell".to_string(),
+            maker_asset_id: "0".to_string(),
+            taker_asset_id: "123".to_string(),
+            maker_amount_filled: "100000000".to_string(),
+            taker_amount_filled: "200000000".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(order_side(&fill), "buy");
+        assert_eq!(order_shares(&fill), BigInt::from(200000000u64));
+    }
+
+    #[test]
+    fn test_fill_trader() {
+        // Exchange fill: the maker's buy order, whatever the taker did
+        let fill = pnl::OrderFill {
+            maker: "0xAAAA".to_string(),
+            taker: "0xbbbb".to_string(),
+            side: "sell".to_string(),
+            maker_asset_id: "0".to_string(),
+            exchange: "ctf".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(fill_trader(&fill), Some(("0xaaaa".to_string(), "buy")));
+
+        // Synthetic adapter fill: the stakeholder (taker) on the fill's side
+        let adapter = pnl::OrderFill { exchange: "neg_risk_adapter".to_string(), ..fill.clone() };
+        assert_eq!(fill_trader(&adapter), Some(("0xbbbb".to_string(), "sell")));
+
+        // Protocol contracts are never traders
+        let excluded = pnl::OrderFill { maker: ZERO_ADDRESS.to_string(), ..fill };
+        assert_eq!(fill_trader(&excluded), None);
+    }
+
+    #[test]
+    fn test_order_status() {
+        let size = BigInt::from(100u64);
+        assert_eq!(order_status(&BigInt::from(100u64), Some(&size), false), "filled");
+        assert_eq!(order_status(&BigInt::from(40u64), Some(&size), false), "partially_filled");
+        assert_eq!(order_status(&BigInt::from(40u64), Some(&size), true), "partially_filled");
+        assert_eq!(order_status(&BigInt::from(40u64), None, false), "partially_filled");
+        assert_eq!(order_status(&BigInt::from(0u64), Some(&size), true), "cancelled");
+        assert_eq!(order_status(&BigInt::from(0u64), None, false), "open");
+    }
+
     //==============================================
     // Helper Function Tests
     //==============================================
@@ -1184,6 +6064,13 @@ mod tests {
         assert_eq!(result, "0x0102030405");
     }
 
+    #[test]
+    fn test_is_exchange_address() {
+        assert!(is_exchange_address("0x4BFB41D5B3570DEFD03C39A9A4D8DE6BD8B8982E"));
+        assert!(is_exchange_address("0xc5d563a36ae78145c45a50134d48a1215220f80a"));
+        assert!(!is_exchange_address("0xd91e80cf2e7be2e162c6513ced06f1dd0da35296"));
+    }
+
     #[test]
     fn test_is_excluded_address() {
         assert!(is_excluded_address("0x4bfb41d5b3570defd03c39a9a4d8de6bd8b8982e"));
@@ -1191,6 +6078,16 @@ mod tests {
         assert!(!is_excluded_address("0x1234567890123456789012345678901234567890"));
     }
 
+    #[test]
+    fn test_is_internal_transfer_keeps_mints_and_burns() {
+        let exchange = "0x4bfb41d5b3570defd03c39a9a4d8de6bd8b8982e";
+        let adapter = "0xd91e80cf2e7be2e162c6513ced06f1dd0da35296";
+        assert!(is_internal_transfer(exchange, adapter));
+        assert!(!is_internal_transfer(ZERO_ADDRESS, exchange)); // Mint on MINT match
+        assert!(!is_internal_transfer(adapter, ZERO_ADDRESS)); // Burn on merge
+        assert!(!is_internal_transfer(exchange, "0x1234567890123456789012345678901234567890"));
+    }
+
     #[test]
     fn test_unix_to_timestamp() {
         // Test epoch